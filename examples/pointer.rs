@@ -5,7 +5,7 @@ use wlroots::{CompositorBuilder, CompositorHandle, Cursor, CursorHandle, CursorH
               InputManagerHandler, KeyboardHandle, KeyboardHandler, OutputBuilder,
               OutputBuilderResult, OutputHandle, OutputHandler, OutputLayout, OutputLayoutHandle,
               OutputLayoutHandler, OutputManagerHandler, PointerHandle, PointerHandler,
-              XCursorManager};
+              StateHandle, XCursorManager};
 use wlroots::key_events::KeyEvent;
 use wlroots::pointer_events::{AbsoluteMotionEvent, AxisEvent, ButtonEvent, MotionEvent};
 use wlroots::utils::{init_logging, L_DEBUG};
@@ -33,17 +33,23 @@ impl State {
     }
 }
 
-compositor_data!(State);
-
 struct ExCursor;
 
-struct OutputManager;
+struct OutputManager {
+    state: StateHandle<State>
+}
 
-struct ExOutput;
+struct ExOutput {
+    state: StateHandle<State>
+}
 
-struct InputManager;
+struct InputManager {
+    state: StateHandle<State>
+}
 
-struct ExPointer;
+struct ExPointer {
+    state: StateHandle<State>
+}
 
 struct ExKeyboardHandler;
 
@@ -55,12 +61,11 @@ impl OutputLayoutHandler for OutputLayoutEx {}
 
 impl OutputManagerHandler for OutputManager {
     fn output_added<'output>(&mut self,
-                             compositor: CompositorHandle,
+                             _: CompositorHandle,
                              builder: OutputBuilder<'output>)
                              -> Option<OutputBuilderResult<'output>> {
-        let mut result = builder.build_best_mode(ExOutput);
-        with_handles!([(compositor: {compositor})] => {
-            let state: &mut State = compositor.data.downcast_mut().unwrap();
+        let mut result = builder.build_best_mode(ExOutput { state: self.state.clone() });
+        with_handles!([(state: {&self.state})] => {
             let layout = &mut state.layout;
             let cursor = &mut state.cursor;
             let xcursor_manager = &mut state.xcursor_manager;
@@ -93,11 +98,10 @@ impl KeyboardHandler for ExKeyboardHandler {
 
 impl PointerHandler for ExPointer {
     fn on_motion_absolute(&mut self,
-                          compositor: CompositorHandle,
+                          _: CompositorHandle,
                           _: PointerHandle,
                           event: &AbsoluteMotionEvent) {
-        with_handles!([(compositor: {compositor})] => {
-            let state: &mut State = compositor.into();
+        with_handles!([(state: {&self.state})] => {
             let (x, y) = event.pos();
             state.cursor
                 .run(|cursor| cursor.warp_absolute(event.device(), x, y))
@@ -105,9 +109,8 @@ impl PointerHandler for ExPointer {
         }).unwrap();
     }
 
-    fn on_motion(&mut self, compositor: CompositorHandle, _: PointerHandle, event: &MotionEvent) {
-        with_handles!([(compositor: {compositor})] => {
-            let state: &mut State = compositor.into();
+    fn on_motion(&mut self, _: CompositorHandle, _: PointerHandle, event: &MotionEvent) {
+        with_handles!([(state: {&self.state})] => {
             let (delta_x, delta_y) = event.delta();
             state.cursor
                 .run(|cursor| cursor.move_to(None, delta_x, delta_y))
@@ -115,9 +118,8 @@ impl PointerHandler for ExPointer {
         }).unwrap();
     }
 
-    fn on_button(&mut self, compositor: CompositorHandle, _: PointerHandle, event: &ButtonEvent) {
-        with_handles!([(compositor: {compositor})] => {
-            let state: &mut State = compositor.into();
+    fn on_button(&mut self, _: CompositorHandle, _: PointerHandle, event: &ButtonEvent) {
+        with_handles!([(state: {&self.state})] => {
             if event.state() == WLR_BUTTON_RELEASED {
                 state.color = state.default_color;
             } else {
@@ -127,9 +129,8 @@ impl PointerHandler for ExPointer {
         }).unwrap();
     }
 
-    fn on_axis(&mut self, compositor: CompositorHandle, _: PointerHandle, event: &AxisEvent) {
-        with_handles!([(compositor: {compositor})] => {
-            let state: &mut State = compositor.into();
+    fn on_axis(&mut self, _: CompositorHandle, _: PointerHandle, event: &AxisEvent) {
+        with_handles!([(state: {&self.state})] => {
             for color_byte in &mut state.default_color[..3] {
                 *color_byte += if event.delta() > 0.0 { -0.05 } else { 0.05 };
                 if *color_byte > 1.0 {
@@ -146,8 +147,9 @@ impl PointerHandler for ExPointer {
 
 impl OutputHandler for ExOutput {
     fn on_frame(&mut self, compositor: CompositorHandle, output: OutputHandle) {
-        with_handles!([(compositor: {compositor}), (output: {output})] => {
-            let state: &mut State = compositor.data.downcast_mut().unwrap();
+        with_handles!([(compositor: {compositor}),
+                       (state: {&self.state}),
+                       (output: {output})] => {
             let renderer = compositor.renderer.as_mut()
                 .expect("Compositor was not loaded with a renderer");
             let mut render_context = renderer.render(output, None);
@@ -158,16 +160,15 @@ impl OutputHandler for ExOutput {
 
 impl InputManagerHandler for InputManager {
     fn pointer_added(&mut self,
-                     compositor: CompositorHandle,
+                     _: CompositorHandle,
                      pointer: PointerHandle)
                      -> Option<Box<PointerHandler>> {
-        with_handles!([(compositor: {compositor}), (pointer: {pointer})] => {
-            let state: &mut State = compositor.into();
+        with_handles!([(state: {&self.state}), (pointer: {pointer})] => {
             state.cursor
                 .run(|cursor| cursor.attach_input_device(pointer.input_device()))
                 .unwrap();
         }).unwrap();
-        Some(Box::new(ExPointer))
+        Some(Box::new(ExPointer { state: self.state.clone() }))
     }
 
     fn keyboard_added(&mut self,
@@ -189,10 +190,12 @@ fn main() {
           .unwrap();
     let layout = OutputLayout::create(Box::new(OutputLayoutEx));
 
+    let mut builder = CompositorBuilder::new();
+    let state = builder.state(State::new(xcursor_manager, layout, cursor));
     let compositor =
-        CompositorBuilder::new().gles2(true)
-                                .input_manager(Box::new(InputManager))
-                                .output_manager(Box::new(OutputManager))
-                                .build_auto(State::new(xcursor_manager, layout, cursor));
+        builder.gles2(true)
+               .input_manager(Box::new(InputManager { state: state.clone() }))
+               .output_manager(Box::new(OutputManager { state }))
+               .build_auto(());
     compositor.run();
 }
@@ -221,10 +221,10 @@ impl OutputHandler for OutputEx {
 
                                   height: (16.0 * (state.pressure + 1.0)) as i32 };
                 let mut area = Area { origin, size };
-                let matrix = project_box(area,
-                                         WL_OUTPUT_TRANSFORM_NORMAL,
-                                         state.ring as _,
-                                         transform_matrix.clone());
+                let matrix = Matrix3::project_box(area,
+                                                  WL_OUTPUT_TRANSFORM_NORMAL,
+                                                  state.ring as _,
+                                                  transform_matrix.clone());
                 renderer.render_colored_quad(tool_color, matrix);
 
                 area.origin.x += state.tilt.0 as i32;
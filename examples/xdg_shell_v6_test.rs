@@ -4,9 +4,9 @@ extern crate wlroots;
 use std::process::Command;
 use std::thread;
 
-use wlroots::{project_box, Area, Capability, CompositorBuilder, CompositorHandle, Cursor,
+use wlroots::{Area, Capability, CompositorBuilder, CompositorHandle, Cursor,
               CursorHandle, CursorHandler, InputManagerHandler, KeyboardHandle, KeyboardHandler,
-              Origin, OutputBuilder, OutputBuilderResult, OutputHandle, OutputHandler,
+              Matrix3, Origin, OutputBuilder, OutputBuilderResult, OutputHandle, OutputHandler,
               OutputLayout, OutputLayoutHandle, OutputLayoutHandler, OutputManagerHandler,
               PointerHandle, PointerHandler, Renderer, Seat, SeatHandle, SeatHandler, Size,
               XCursorManager, XdgV6ShellHandler, XdgV6ShellManagerHandler, XdgV6ShellState,
@@ -302,11 +302,10 @@ fn render_shells(state: &mut State, renderer: &mut Renderer) {
                                                  render_height));
             if layout.intersects(renderer.output, render_box) {
                 let transform = renderer.output.get_transform().invert();
-                let matrix = project_box(render_box,
-                                         transform,
-                                         0.0,
-                                         renderer.output
-                                         .transform_matrix());
+                let matrix = Matrix3::project_box(render_box,
+                                                  transform,
+                                                  0.0,
+                                                  renderer.output.transform_matrix());
                 renderer.render_texture_with_matrix(&surface.texture(),
                                                     matrix);
                 surface.send_frame_done(current_time());
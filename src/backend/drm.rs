@@ -0,0 +1,18 @@
+//! A backend that drives the outputs of a GPU directly using DRM/KMS.
+
+use wlroots_sys::wlr_backend;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct DRMBackend {
+    backend: *mut wlr_backend
+}
+
+impl DRMBackend {
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Self {
+        DRMBackend { backend }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        self.backend
+    }
+}
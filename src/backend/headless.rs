@@ -0,0 +1,58 @@
+//! A backend that has no real outputs or input devices.
+//!
+//! Outputs and input devices can be created on demand, which makes this
+//! backend useful for testing compositors without a display.
+
+use wlroots_sys::{wlr_backend, wlr_headless_add_input_device, wlr_headless_add_output,
+                  wlr_input_device_type};
+
+use {InputDevice, OutputHandle, Size};
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct HeadlessBackend {
+    backend: *mut wlr_backend
+}
+
+impl HeadlessBackend {
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Self {
+        HeadlessBackend { backend }
+    }
+
+    /// Creates a new virtual output with the given dimensions.
+    ///
+    /// If the compositor is already running and the output was accepted by
+    /// the `OutputManagerHandler`, a handle to the new output is returned.
+    /// Otherwise the output will be announced when the backend is started.
+    pub fn add_output(&mut self, size: Size) -> Option<OutputHandle> {
+        unsafe {
+            let output = wlr_headless_add_output(self.backend,
+                                                 size.width as u32,
+                                                 size.height as u32);
+            if output.is_null() || (*output).data.is_null() {
+                None
+            } else {
+                Some(OutputHandle::from_ptr(output))
+            }
+        }
+    }
+
+    /// Creates a new virtual input device of the given type.
+    ///
+    /// Returns `None` if the device could not be created.
+    pub fn add_input_device(&mut self,
+                            device_type: wlr_input_device_type)
+                            -> Option<InputDevice> {
+        unsafe {
+            let device = wlr_headless_add_input_device(self.backend, device_type);
+            if device.is_null() {
+                None
+            } else {
+                Some(InputDevice::from_ptr(device))
+            }
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        self.backend
+    }
+}
@@ -0,0 +1,18 @@
+//! A backend that reads input devices directly using libinput.
+
+use wlroots_sys::wlr_backend;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct LibInputBackend {
+    backend: *mut wlr_backend
+}
+
+impl LibInputBackend {
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Self {
+        LibInputBackend { backend }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        self.backend
+    }
+}
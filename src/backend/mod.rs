@@ -0,0 +1,264 @@
+//! Backends that the compositor can use to display outputs and receive input.
+//!
+//! By default `CompositorBuilder::build_auto` will let wlroots decide which
+//! backend to use based on the environment. Use a `BackendConfig` with
+//! `CompositorBuilder::build_with_backend` to pick one explicitly.
+
+mod drm;
+mod headless;
+mod libinput;
+mod multi;
+mod session;
+mod wayland;
+mod x11;
+
+pub use self::drm::DRMBackend;
+pub use self::headless::HeadlessBackend;
+pub use self::libinput::LibInputBackend;
+pub use self::multi::MultiBackend;
+pub use self::session::Session;
+pub use self::wayland::WaylandBackend;
+pub use self::x11::X11Backend;
+
+use std::{ptr, ffi::CString, path::PathBuf};
+
+use wayland_sys::server::wl_display;
+use wlroots_sys::{wlr_backend, wlr_backend_autocreate, wlr_backend_destroy, wlr_backend_is_drm,
+                  wlr_backend_is_headless, wlr_backend_is_libinput, wlr_backend_is_multi,
+                  wlr_backend_is_wl, wlr_backend_is_x11, wlr_drm_backend_create,
+                  wlr_headless_backend_create, wlr_libinput_backend_create,
+                  wlr_multi_backend_add, wlr_multi_backend_create, wlr_session_open_file,
+                  wlr_session_find_gpus, wlr_wl_backend_create,
+                  wlr_wl_output_create, wlr_x11_backend_create, wlr_x11_output_create};
+
+/// Describes which backend the compositor should be built with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendConfig {
+    /// Let wlroots pick the backend based on the environment.
+    ///
+    /// This is what `CompositorBuilder::build_auto` uses.
+    Auto,
+    /// A backend with no real outputs or inputs.
+    ///
+    /// Outputs and input devices can be added to it with the methods on
+    /// `HeadlessBackend`. This is mostly useful for testing.
+    Headless,
+    /// Run nested as a client of another Wayland compositor.
+    Wayland {
+        /// Name of the remote display to connect to.
+        ///
+        /// If `None` then `$WAYLAND_DISPLAY` is used.
+        remote: Option<String>,
+        /// Number of windows (outputs) to open on the remote display.
+        outputs: usize
+    },
+    /// Run nested as a client of an X11 server.
+    X11 {
+        /// Name of the X11 display to connect to.
+        ///
+        /// If `None` then `$DISPLAY` is used.
+        display: Option<String>,
+        /// Number of windows (outputs) to open on the X11 display.
+        outputs: usize
+    },
+    /// Drive the outputs of a GPU directly using DRM/KMS.
+    ///
+    /// This requires a session, which is created automatically and owned by
+    /// the compositor, see `Compositor::session`.
+    Drm {
+        /// Path to the GPU device (e.g `/dev/dri/card0`).
+        ///
+        /// If `None` then the first GPU found by the session is used.
+        gpu: Option<PathBuf>
+    },
+    /// Read input devices directly using libinput.
+    ///
+    /// This requires a session, which is created automatically.
+    Libinput,
+    /// Combine several backends into one.
+    ///
+    /// This is usually used to pair a `Drm` backend with a `Libinput` backend.
+    /// All of the backends that need a session will share the same one.
+    Multi(Vec<BackendConfig>)
+}
+
+/// The backend the compositor is using.
+#[derive(Debug)]
+pub enum Backend {
+    Headless(HeadlessBackend),
+    Wayland(WaylandBackend),
+    X11(X11Backend),
+    DRM(DRMBackend),
+    LibInput(LibInputBackend),
+    Multi(MultiBackend)
+}
+
+impl Backend {
+    /// Wraps a raw backend pointer, determining its type.
+    ///
+    /// Returns `None` if the type of the backend could not be determined.
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Option<Self> {
+        if backend.is_null() {
+            return None
+        }
+        Some(if wlr_backend_is_headless(backend) {
+                 Backend::Headless(HeadlessBackend::from_ptr(backend))
+             } else if wlr_backend_is_wl(backend) {
+                 Backend::Wayland(WaylandBackend::from_ptr(backend))
+             } else if wlr_backend_is_x11(backend) {
+                 Backend::X11(X11Backend::from_ptr(backend))
+             } else if wlr_backend_is_drm(backend) {
+                 Backend::DRM(DRMBackend::from_ptr(backend))
+             } else if wlr_backend_is_libinput(backend) {
+                 Backend::LibInput(LibInputBackend::from_ptr(backend))
+             } else if wlr_backend_is_multi(backend) {
+                 Backend::Multi(MultiBackend::from_ptr(backend))
+             } else {
+                 return None
+             })
+    }
+
+    /// Creates the backend described by the configuration.
+    ///
+    /// `session` is shared between all of the backends that need one, and is
+    /// created the first time it is needed.
+    ///
+    /// Returns `None` if wlroots could not create the backend.
+    pub(crate) unsafe fn create(display: *mut wl_display,
+                                config: &BackendConfig,
+                                session: &mut Option<Session>)
+                                -> Option<Self> {
+        use self::BackendConfig::*;
+        let backend = match *config {
+            Auto => wlr_backend_autocreate(display as *mut _, None),
+            Headless => wlr_headless_backend_create(display as *mut _, None),
+            Wayland { ref remote,
+                      outputs } => {
+                let remote = match remote.clone().map(CString::new) {
+                    Some(Ok(remote)) => Some(remote),
+                    Some(Err(_)) => return None,
+                    None => None
+                };
+                let remote_ptr = remote.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null());
+                let backend = wlr_wl_backend_create(display as *mut _, remote_ptr, None);
+                if !backend.is_null() {
+                    for _ in 0..outputs {
+                        wlr_wl_output_create(backend);
+                    }
+                }
+                backend
+            }
+            X11 { display: ref x11_display,
+                  outputs } => {
+                let x11_display = match x11_display.clone().map(CString::new) {
+                    Some(Ok(x11_display)) => Some(x11_display),
+                    Some(Err(_)) => return None,
+                    None => None
+                };
+                let x11_display_ptr =
+                    x11_display.as_ref().map(|s| s.as_ptr()).unwrap_or(ptr::null());
+                let backend = wlr_x11_backend_create(display as *mut _, x11_display_ptr, None);
+                if !backend.is_null() {
+                    for _ in 0..outputs {
+                        wlr_x11_output_create(backend);
+                    }
+                }
+                backend
+            }
+            Drm { ref gpu } => {
+                let had_session = session.is_some();
+                let session_ref = session;
+                let session = Session::get_or_create(display, session_ref)?;
+                let gpu_fd = match *gpu {
+                    Some(ref path) => {
+                        match CString::new(path.to_string_lossy().into_owned()) {
+                            Ok(path) => wlr_session_open_file(session, path.as_ptr()),
+                            Err(_) => -1
+                        }
+                    }
+                    None => {
+                        // NOTE
+                        // Every GPU found is opened, so only ask for the
+                        // one that is used.
+                        let mut gpu = -1;
+                        if wlr_session_find_gpus(session, 1, &mut gpu) == 0 {
+                            -1
+                        } else {
+                            gpu
+                        }
+                    }
+                };
+                if gpu_fd < 0 {
+                    wlr_log!(L_ERROR, "Could not open a GPU for the DRM backend");
+                    discard_session(session_ref, had_session);
+                    return None
+                }
+                let backend = wlr_drm_backend_create(display as *mut _,
+                                                     session,
+                                                     gpu_fd,
+                                                     ptr::null_mut(),
+                                                     None);
+                if backend.is_null() {
+                    discard_session(session_ref, had_session);
+                    return None
+                }
+                return Some(Backend::DRM(DRMBackend::from_ptr(backend)))
+            }
+            Libinput => {
+                let had_session = session.is_some();
+                let session_ref = session;
+                let session = Session::get_or_create(display, session_ref)?;
+                let backend = wlr_libinput_backend_create(display as *mut _, session);
+                if backend.is_null() {
+                    discard_session(session_ref, had_session);
+                    return None
+                }
+                return Some(Backend::LibInput(LibInputBackend::from_ptr(backend)))
+            }
+            Multi(ref configs) => {
+                let multi = wlr_multi_backend_create(display as *mut _);
+                if multi.is_null() {
+                    return None
+                }
+                let had_session = session.is_some();
+                for config in configs {
+                    match Backend::create(display, config, session) {
+                        Some(sub_backend) => wlr_multi_backend_add(multi, sub_backend.as_ptr()),
+                        None => {
+                            // NOTE
+                            // This also destroys the backends that were
+                            // already added to it.
+                            wlr_backend_destroy(multi);
+                            discard_session(session, had_session);
+                            return None
+                        }
+                    }
+                }
+                multi
+            }
+        };
+        Backend::from_ptr(backend)
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        match *self {
+            Backend::Headless(ref backend) => backend.as_ptr(),
+            Backend::Wayland(ref backend) => backend.as_ptr(),
+            Backend::X11(ref backend) => backend.as_ptr(),
+            Backend::DRM(ref backend) => backend.as_ptr(),
+            Backend::LibInput(ref backend) => backend.as_ptr(),
+            Backend::Multi(ref backend) => backend.as_ptr()
+        }
+    }
+}
+
+/// Destroys the session if it was created while making a backend that
+/// failed, so that it doesn't hold on to the devices it opened.
+unsafe fn discard_session(session: &mut Option<Session>, had_session: bool) {
+    if had_session {
+        return
+    }
+    if let Some(session) = session.take() {
+        session.destroy();
+    }
+}
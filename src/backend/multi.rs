@@ -0,0 +1,41 @@
+//! A backend that combines several other backends into one.
+
+use wlroots_sys::{wlr_backend, wlr_multi_backend_add, wlr_multi_backend_remove,
+                  wlr_multi_is_empty};
+
+use super::Backend;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct MultiBackend {
+    backend: *mut wlr_backend
+}
+
+impl MultiBackend {
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Self {
+        MultiBackend { backend }
+    }
+
+    /// Adds a backend to this multi backend.
+    ///
+    /// The multi backend takes ownership of the backend, and will destroy it
+    /// when it is destroyed.
+    pub fn add(&mut self, backend: Backend) {
+        unsafe {
+            wlr_multi_backend_add(self.backend, backend.as_ptr());
+        }
+    }
+
+    /// Removes a backend from this multi backend.
+    pub fn remove(&mut self, backend: &Backend) {
+        unsafe { wlr_multi_backend_remove(self.backend, backend.as_ptr()) }
+    }
+
+    /// Determines if this multi backend has any backends in it.
+    pub fn is_empty(&self) -> bool {
+        unsafe { wlr_multi_is_empty(self.backend) }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        self.backend
+    }
+}
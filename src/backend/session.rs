@@ -0,0 +1,63 @@
+//! A session is used to gain access to privileged devices (e.g GPUs and
+//! input devices) without running the compositor as root.
+
+use wayland_sys::server::wl_display;
+use wlroots_sys::{wlr_session, wlr_session_change_vt, wlr_session_create, wlr_session_destroy};
+
+/// The session of the compositor.
+///
+/// It is owned by the compositor, get it with `Compositor::session`.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct Session {
+    session: *mut wlr_session
+}
+
+impl Session {
+    /// Gets the session stored in `session`, creating it if there isn't one yet.
+    pub(crate) unsafe fn get_or_create(display: *mut wl_display,
+                                       session: &mut Option<Session>)
+                                       -> Option<*mut wlr_session> {
+        if session.is_none() {
+            let session_ptr = wlr_session_create(display as *mut _);
+            if session_ptr.is_null() {
+                wlr_log!(L_ERROR, "Could not create a session");
+                return None
+            }
+            *session = Some(Session::from_ptr(session_ptr));
+        }
+        session.as_ref().map(|session| session.session)
+    }
+
+    pub(crate) unsafe fn from_ptr(session: *mut wlr_session) -> Self {
+        Session { session }
+    }
+
+    /// Determines if the session is active (e.g the compositor's virtual
+    /// terminal is being displayed).
+    pub fn active(&self) -> bool {
+        unsafe { (*self.session).active }
+    }
+
+    /// Gets the number of the virtual terminal the session is running on.
+    pub fn vt_number(&self) -> u32 {
+        unsafe { (*self.session).vtnr }
+    }
+
+    /// Changes the active virtual terminal.
+    ///
+    /// Returns `false` if the virtual terminal could not be changed.
+    pub fn change_vt(&mut self, vt: u32) -> bool {
+        unsafe { wlr_session_change_vt(self.session, vt) }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_session {
+        self.session
+    }
+
+    /// Destroys the session, giving back the devices it opened.
+    ///
+    /// It must not be used by any backend anymore.
+    pub(crate) unsafe fn destroy(self) {
+        wlr_session_destroy(self.session)
+    }
+}
@@ -0,0 +1,36 @@
+//! A backend that runs the compositor nested as a client of another Wayland
+//! compositor. Each output is a window on the remote display.
+
+use wlroots_sys::{wlr_backend, wlr_wl_output_create};
+
+use OutputHandle;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct WaylandBackend {
+    backend: *mut wlr_backend
+}
+
+impl WaylandBackend {
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Self {
+        WaylandBackend { backend }
+    }
+
+    /// Opens a new window on the remote display to use as an output.
+    ///
+    /// If the compositor is already running and the output was accepted by
+    /// the `OutputManagerHandler`, a handle to the new output is returned.
+    pub fn add_output(&mut self) -> Option<OutputHandle> {
+        unsafe {
+            let output = wlr_wl_output_create(self.backend);
+            if output.is_null() || (*output).data.is_null() {
+                None
+            } else {
+                Some(OutputHandle::from_ptr(output))
+            }
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        self.backend
+    }
+}
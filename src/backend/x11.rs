@@ -0,0 +1,36 @@
+//! A backend that runs the compositor nested as a client of an X11 server.
+//! Each output is a window on the X11 display.
+
+use wlroots_sys::{wlr_backend, wlr_x11_output_create};
+
+use OutputHandle;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct X11Backend {
+    backend: *mut wlr_backend
+}
+
+impl X11Backend {
+    pub(crate) unsafe fn from_ptr(backend: *mut wlr_backend) -> Self {
+        X11Backend { backend }
+    }
+
+    /// Opens a new window on the X11 display to use as an output.
+    ///
+    /// If the compositor is already running and the output was accepted by
+    /// the `OutputManagerHandler`, a handle to the new output is returned.
+    pub fn add_output(&mut self) -> Option<OutputHandle> {
+        unsafe {
+            let output = wlr_x11_output_create(self.backend);
+            if output.is_null() || (*output).data.is_null() {
+                None
+            } else {
+                Some(OutputHandle::from_ptr(output))
+            }
+        }
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wlr_backend {
+        self.backend
+    }
+}
@@ -2,26 +2,65 @@
 //! See examples for documentation on how to use this struct.
 
 use libc;
-use std::{env, panic, ptr, any::Any, cell::{Cell, UnsafeCell}, ffi::CStr, rc::{Rc, Weak}};
+use std::{env, fmt, panic, ptr, any::Any, cell::{Cell, UnsafeCell}, ffi::CStr,
+          hash::{Hash, Hasher}, mem::ManuallyDrop, rc::{Rc, Weak}};
 
-use {DataDeviceManager, Surface, SurfaceHandle, XWaylandManagerHandler, XWaylandServer};
-use errors::{HandleErr, HandleResult};
+use {Backend, BackendConfig, DataDeviceManager, OutputLayoutHandle, Surface, SurfaceHandle,
+     XWaylandManagerHandler, XWaylandServer};
+use backend::Session;
+use event_loop::EventLoop;
+use sender::{CommandReceiver, CompositorSender};
+use errors::{CompositorError, HandleErr, HandleResult};
+use handle::Handle;
 use types::surface::{InternalSurface, InternalSurfaceState};
+use extensions::gamma_control::GammaControlManager;
+use extensions::output_management::{OutputManagementHandler, OutputManagementManager};
+use extensions::presentation::Presentation;
+use extensions::screenshooter::{Screenshooter, ScreenshooterHandler};
 use extensions::server_decoration::ServerDecorationManager;
+use extensions::xdg_output::XdgOutputManager;
 use manager::{InputManager, InputManagerHandler, OutputManager, OutputManagerHandler,
               XdgShellManager,
               XdgShellManagerHandler, XdgV6ShellManager, XdgV6ShellManagerHandler};
 use render::GenericRenderer;
 
-use wayland_sys::server::{wl_display, wl_event_loop, signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
-use wlroots_sys::{wlr_backend, wlr_backend_autocreate, wlr_backend_destroy, wlr_backend_start,
+use wayland_sys::server::{wl_display, signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::{wlr_backend_destroy, wlr_backend_start, wlr_multi_get_session,
                   wlr_compositor, wlr_compositor_create, wlr_compositor_destroy,
                   wlr_xdg_shell_v6, wlr_xdg_shell_v6_create,
                   wlr_xdg_shell, wlr_xdg_shell_create};
 use wlroots_sys::wayland_server::sys::wl_display_init_shm;
 
-/// Global compositor pointer, used to refer to the compositor state unsafely.
-pub(crate) static mut COMPOSITOR_PTR: *mut Compositor = 0 as *mut _;
+thread_local! {
+    /// The compositor running on this thread, if there is one.
+    ///
+    /// Each thread can run at most one compositor at a time, but several
+    /// threads can each run their own.
+    static RUNNING_COMPOSITOR: Cell<*mut Compositor> = Cell::new(ptr::null_mut())
+}
+
+/// Gets a pointer to the compositor running on this thread.
+///
+/// The pointer is null if no compositor is running on this thread.
+pub(crate) fn running_compositor() -> *mut Compositor {
+    RUNNING_COMPOSITOR.with(|compositor| compositor.get())
+}
+
+/// Gets the renderer of the compositor running on this thread, if it has one.
+///
+/// Output signals that read back frames fire while the compositor is
+/// borrowed for rendering, so the renderer can't be reached through a handle.
+pub(crate) unsafe fn running_renderer() -> Option<ManuallyDrop<GenericRenderer>> {
+    let compositor = running_compositor();
+    if compositor.is_null() {
+        return None
+    }
+    (*compositor).renderer.as_ref().map(|renderer| renderer.alias())
+}
+
+fn set_running_compositor(compositor: *mut Compositor) {
+    RUNNING_COMPOSITOR.with(|running| running.set(compositor))
+}
 
 pub trait CompositorHandler {
     /// Callback that's triggered when a surface is provided to the compositor.
@@ -33,13 +72,12 @@ pub trait CompositorHandler {
 
 impl CompositorHandler for () {}
 
-wayland_listener!(InternalCompositor, Box<CompositorHandler>, [
+wayland_listener!(InternalCompositor, (CompositorHandle, Box<CompositorHandler>), [
     new_surface_listener => new_surface_notify: |this: &mut InternalCompositor,
                                                  surface_ptr: *mut libc::c_void,|
     unsafe {
-        let handler = &mut this.data;
+        let (ref compositor, ref mut handler) = this.data;
         let surface_ptr = surface_ptr as _;
-        let compositor = (&mut *COMPOSITOR_PTR).weak_reference();
         let surface = Surface::new(surface_ptr);
         handler.new_surface(compositor.clone(), surface.weak_reference());
         let mut internal_surface = InternalSurface::new((surface, Box::new(())));
@@ -55,7 +93,7 @@ wayland_listener!(InternalCompositor, Box<CompositorHandler>, [
     shutdown_listener => shutdown_notify: |this: &mut InternalCompositor,
                                            _data: *mut libc::c_void,|
     unsafe {
-        let handler = &mut this.data;
+        let (_, ref mut handler) = this.data;
         handler.on_shutdown();
     };
 ]);
@@ -63,13 +101,45 @@ wayland_listener!(InternalCompositor, Box<CompositorHandler>, [
 #[derive(Debug, Clone)]
 pub struct CompositorHandle {
     /// This ensures that this handle is still alive and not already borrowed.
-    handle: Weak<Cell<bool>>
+    handle: Weak<CompositorLock>,
+    /// Identifies the compositor, as the weak reference can't be compared.
+    lock: *const CompositorLock
+}
+
+/// State shared between a `Compositor` and its handles.
+#[derive(Debug)]
+pub(crate) struct CompositorLock {
+    /// Set while the compositor is borrowed.
+    borrowed: Cell<bool>,
+    /// Where the compositor lives while it is running, null otherwise.
+    compositor: Cell<*mut Compositor>
+}
+
+/// A weak reference to state owned by a `Compositor`.
+///
+/// Get one with `CompositorBuilder::state`. Unlike `Compositor::data`, the
+/// type of the state is part of the handle, so handlers that keep one get a
+/// `&mut D` without downcasting.
+pub struct StateHandle<D> {
+    /// This ensures that this handle is still alive and not already borrowed.
+    handle: Weak<StateLock<D>>,
+    /// Identifies the state, as the weak reference can't be compared.
+    lock: *const StateLock<D>
+}
+
+/// State owned by a `Compositor`, along with its borrow flag.
+struct StateLock<D> {
+    /// Set while the state is borrowed.
+    borrowed: Cell<bool>,
+    data: UnsafeCell<D>
 }
 
 #[allow(dead_code)]
 pub struct Compositor {
     /// User data.
     pub data: Box<Any>,
+    /// The states given to `CompositorBuilder::state`.
+    states: Vec<Rc<Any>>,
     /// Internal compositor handler
     compositor_handler: Option<Box<InternalCompositor>>,
     /// Manager for the inputs.
@@ -88,18 +158,35 @@ pub struct Compositor {
     xdg_v6_shell_global: *mut wlr_xdg_shell_v6,
     /// Pointer to the wlr_compositor.
     compositor: *mut wlr_compositor,
-    /// Pointer to the wlroots backend in use.
-    backend: *mut wlr_backend,
+    /// The wlroots backend in use.
+    backend: Backend,
+    /// The session the backend uses, if it uses one.
+    session: Option<Session>,
+    /// Whether the session was created for the backend by the compositor,
+    /// rather than by wlroots, and so has to be destroyed by it.
+    owns_session: bool,
     /// Pointer to the wayland display.
     display: *mut wl_display,
-    /// Pointer to the event loop.
-    event_loop: *mut wl_event_loop,
+    /// The event loop the compositor runs on.
+    event_loop: EventLoop,
+    /// Receives commands sent from other threads, created on first use.
+    command_receiver: Option<CommandReceiver>,
     /// Shared memory buffer file descriptor.
     shm_fd: i32,
     /// Name of the Wayland socket that we are binding to.
     socket_name: String,
     /// Optional decoration manager extension.
     pub server_decoration_manager: Option<ServerDecorationManager>,
+    /// Optional gamma control extension.
+    pub gamma_control_manager: Option<GammaControlManager>,
+    /// Optional screenshooter extension.
+    pub screenshooter: Option<Screenshooter>,
+    /// Optional presentation-time extension.
+    pub presentation: Option<Presentation>,
+    /// Optional xdg output extension.
+    pub xdg_output_manager: Option<XdgOutputManager>,
+    /// Optional output management extension.
+    pub output_management: Option<OutputManagementManager>,
     /// The renderer used to draw things to the screen.
     pub renderer: Option<GenericRenderer>,
     /// XWayland server, only Some if it is enabled
@@ -110,10 +197,10 @@ pub struct Compositor {
     panic_error: Option<Box<Any + Send>>,
     /// Custom function to run at shutdown (or when a panic occurs).
     user_terminate: Option<fn()>,
-    /// Lock used to borrow the compositor globally.
+    /// Lock used to borrow the compositor through its handles.
     /// Should always be set before passing a reference to the compositor
     /// in a callback.
-    pub(crate) lock: Rc<Cell<bool>>
+    pub(crate) lock: Rc<CompositorLock>
 }
 
 pub struct CompositorBuilder {
@@ -123,10 +210,19 @@ pub struct CompositorBuilder {
     xdg_shell_manager_handler: Option<Box<XdgShellManagerHandler>>,
     xdg_v6_shell_manager_handler: Option<Box<XdgV6ShellManagerHandler>>,
     gles2: bool,
+    software_renderer: bool,
     server_decoration_manager: bool,
+    gamma_control_manager: bool,
+    screenshooter: bool,
+    screenshooter_handler: Option<Box<ScreenshooterHandler>>,
+    presentation: bool,
+    xdg_output_layout: Option<OutputLayoutHandle>,
+    output_management_layout: Option<OutputLayoutHandle>,
+    output_management_handler: Option<Box<OutputManagementHandler>>,
     data_device_manager: bool,
     xwayland: Option<Box<XWaylandManagerHandler>>,
-    user_terminate: Option<fn()>
+    user_terminate: Option<fn()>,
+    states: Vec<Rc<Any>>
 }
 
 impl CompositorBuilder {
@@ -135,7 +231,15 @@ impl CompositorBuilder {
     /// Unless otherwise noted, each option is `false`/`None`.
     pub fn new() -> Self {
         CompositorBuilder { gles2: false,
+                            software_renderer: false,
                             server_decoration_manager: false,
+                            gamma_control_manager: false,
+                            screenshooter: false,
+                            screenshooter_handler: None,
+                            presentation: false,
+                            xdg_output_layout: None,
+                            output_management_layout: None,
+                            output_management_handler: None,
                             data_device_manager: false,
                             compositor_handler: None,
                             input_manager_handler: None,
@@ -143,7 +247,26 @@ impl CompositorBuilder {
                             xdg_shell_manager_handler: None,
                             xdg_v6_shell_manager_handler: None,
                             xwayland: None,
-                            user_terminate: None }
+                            user_terminate: None,
+                            states: Vec::new() }
+    }
+
+    /// Gives `data` to the compositor to own, returning a typed handle to it.
+    ///
+    /// Clones of the handle can be given to the handlers before the
+    /// compositor is built. Running a function on it gives it a `&mut D`,
+    /// so which type of state a handler expects is checked when it is
+    /// compiled.
+    ///
+    /// The state is dropped along with the compositor, or with the builder
+    /// if the compositor could not be built.
+    pub fn state<D: 'static>(&mut self, data: D) -> StateHandle<D> {
+        let state = Rc::new(StateLock { borrowed: Cell::new(false),
+                                        data: UnsafeCell::new(data) });
+        let handle = StateHandle { handle: Rc::downgrade(&state),
+                                   lock: &*state };
+        self.states.push(state);
+        handle
     }
 
     /// Set the handler for global compositor callbacks.
@@ -193,6 +316,16 @@ impl CompositorBuilder {
         self
     }
 
+    /// Decide whether or not to render on the CPU with pixman.
+    ///
+    /// This takes precedence over `gles2`. The software renderer draws into
+    /// memory instead of into the outputs, see
+    /// `GenericRenderer::pixman_renderer`.
+    pub fn software_renderer(mut self, software_renderer: bool) -> Self {
+        self.software_renderer = software_renderer;
+        self
+    }
+
     /// Decide whether or not to enable the server decoration manager protocol
     /// extension.
     pub fn server_decoration_manager(mut self, server_decoration_manager: bool) -> Self {
@@ -200,6 +333,72 @@ impl CompositorBuilder {
         self
     }
 
+    /// Decide whether or not to enable the gamma control protocol extension.
+    ///
+    /// This lets clients change the gamma of the outputs, which is reset
+    /// when they disconnect.
+    pub fn gamma_control_manager(mut self, gamma_control_manager: bool) -> Self {
+        self.gamma_control_manager = gamma_control_manager;
+        self
+    }
+
+    /// Decide whether or not to enable the screenshooter protocol extension.
+    ///
+    /// Unless a handler is set with `screenshooter_handler`, every screenshot
+    /// clients ask for is taken.
+    pub fn screenshooter(mut self, screenshooter: bool) -> Self {
+        self.screenshooter = screenshooter;
+        self
+    }
+
+    /// Set the handler that decides which screenshots clients can take.
+    pub fn screenshooter_handler(mut self,
+                                 screenshooter_handler: Box<ScreenshooterHandler>)
+                                 -> Self {
+        self.screenshooter_handler = Some(screenshooter_handler);
+        self
+    }
+
+    /// Decide whether or not to enable the presentation-time protocol
+    /// extension.
+    ///
+    /// This tells clients when the content of their surfaces was shown, see
+    /// `extensions::presentation`.
+    pub fn presentation(mut self, presentation: bool) -> Self {
+        self.presentation = presentation;
+        self
+    }
+
+    /// Enable the xdg output protocol extension, which tells clients the
+    /// position and logical size of the outputs in the layout.
+    ///
+    /// Clients are updated whenever the layout changes.
+    pub fn xdg_output_manager(mut self, layout: OutputLayoutHandle) -> Self {
+        self.xdg_output_layout = Some(layout);
+        self
+    }
+
+    /// Enable the wlr output management protocol extension, which lets
+    /// clients change the mode, position, scale and transform of the outputs
+    /// in the layout, or enable and disable them.
+    ///
+    /// Only the outputs the output manager handler accepted are offered.
+    /// Unless a handler is set with `output_management_handler`, every
+    /// configuration the outputs support is applied.
+    pub fn output_management(mut self, layout: OutputLayoutHandle) -> Self {
+        self.output_management_layout = Some(layout);
+        self
+    }
+
+    /// Set the handler that decides which output configurations clients can
+    /// apply.
+    pub fn output_management_handler(mut self,
+                                     output_management_handler: Box<OutputManagementHandler>)
+                                     -> Self {
+        self.output_management_handler = Some(output_management_handler);
+        self
+    }
+
     /// Add a handler for xwayland.
     ///
     /// If you do not provide a handler then the xwayland server does not run.
@@ -222,21 +421,98 @@ impl CompositorBuilder {
     /// compositor with.
     pub fn build_auto<D>(self, data: D) -> Compositor
         where D: Any + 'static
+    {
+        self.build_with_backend(BackendConfig::Auto, data)
+    }
+
+    /// Makes a new compositor that uses the backend described by `config`.
+    ///
+    /// Also automatically opens the socket for clients to communicate to the
+    /// compositor with.
+    ///
+    /// # Panics
+    /// Panics if any part of the compositor could not be set up.
+    /// Use `try_build` to handle these errors instead.
+    pub fn build_with_backend<D>(self, config: BackendConfig, data: D) -> Compositor
+        where D: Any + 'static
+    {
+        match self.try_build(config, data) {
+            Ok(compositor) => compositor,
+            // NOTE Rationale for panicking:
+            // * Won't be in C land just yet, so it's safe to panic
+            // * Use `try_build` if you need to recover from this.
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    /// Makes a new compositor that uses the backend described by `config`.
+    ///
+    /// Also automatically opens the socket for clients to communicate to the
+    /// compositor with.
+    ///
+    /// If the backend, the renderer, or the socket could not be set up then
+    /// everything created so far is torn down and the cause is returned.
+    pub fn try_build<D>(self,
+                        config: BackendConfig,
+                        data: D)
+                        -> Result<Compositor, CompositorError>
+        where D: Any + 'static
     {
         unsafe {
             let display =
                 ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_create,) as *mut wl_display;
             let event_loop =
                 ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_get_event_loop, display);
-            // TODO Make optional
-            let backend = wlr_backend_autocreate(display as *mut _, None);
-            if backend.is_null() {
-                // NOTE Rationale for panicking:
-                // * Won't be in C land just yet, so it's safe to panic
-                // * Can always be returned in a Result instead, but for now
-                //   if you auto create it's assumed you can't recover.
-                panic!("Could not auto-create backend");
+            let event_loop = EventLoop::from_ptr(event_loop);
+            let mut session = None;
+            let backend = match Backend::create(display, &config, &mut session) {
+                Some(backend) => backend,
+                None => {
+                    wlr_log!(L_ERROR, "Could not create backend {:?}", config);
+                    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_destroy, display);
+                    return Err(CompositorError::BackendCreationFailed)
+                }
+            };
+            let renderer = if self.software_renderer {
+                match GenericRenderer::pixman_renderer() {
+                    Some(renderer) => Some(renderer),
+                    None => {
+                        destroy_backend(display, backend, session);
+                        return Err(CompositorError::RendererCreationFailed)
+                    }
+                }
+            } else if self.gles2 {
+                match GenericRenderer::gles2_renderer(backend.as_ptr()) {
+                    Some(renderer) => Some(renderer),
+                    None => {
+                        destroy_backend(display, backend, session);
+                        return Err(CompositorError::RendererCreationFailed)
+                    }
+                }
+            } else {
+                None
+            };
+
+            // Open the socket to the Wayland server.
+            let socket = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_add_socket_auto, display);
+            if socket.is_null() {
+                destroy_backend(display, backend, session);
+                return Err(CompositorError::SocketOpenFailed)
             }
+            // NOTE
+            // wlroots makes and owns the session of an automatically created
+            // backend, it's only borrowed to be able to switch VTs.
+            let owns_session = session.is_some();
+            if session.is_none() {
+                if let Backend::Multi(ref multi) = backend {
+                    let session_ptr = wlr_multi_get_session(multi.as_ptr());
+                    if !session_ptr.is_null() {
+                        session = Some(Session::from_ptr(session_ptr));
+                    }
+                }
+            }
+            let socket_name = CStr::from_ptr(socket).to_string_lossy().into_owned();
+
             // Set up shared memory buffer for Wayland clients.
             let shm_fd = wl_display_init_shm(display as *mut _);
             // Create optional extensions.
@@ -250,22 +526,40 @@ impl CompositorBuilder {
             } else {
                 None
             };
-            let compositor;
-            let renderer = if self.gles2 {
-                let gles2 = GenericRenderer::gles2_renderer(backend);
-                // Set up wlr_compositor
-                let gles2_ptr = gles2.as_ptr();
-                compositor = wlr_compositor_create(display as *mut _, gles2_ptr);
-                Some(gles2)
+            let gamma_control_manager = if self.gamma_control_manager {
+                GammaControlManager::new(display)
+            } else {
+                None
+            };
+            let screenshooter = if self.screenshooter {
+                let handler = self.screenshooter_handler.unwrap_or_else(|| Box::new(()));
+                Screenshooter::new(display as _, handler)
+            } else {
+                None
+            };
+            let presentation = if self.presentation {
+                Presentation::new(display)
             } else {
-                compositor = wlr_compositor_create(display as *mut _, ptr::null_mut());
                 None
             };
+            let xdg_output_manager =
+                self.xdg_output_layout
+                    .and_then(|layout| XdgOutputManager::new(display, layout));
+            // Set up wlr_compositor
+            let renderer_ptr = renderer.as_ref()
+                                       .map(|renderer| renderer.as_ptr())
+                                       .unwrap_or_else(ptr::null_mut);
+            let compositor = wlr_compositor_create(display as *mut _, renderer_ptr);
+
+            // The handles given to the handlers refer to this compositor.
+            let lock = Rc::new(CompositorLock { borrowed: Cell::new(false),
+                                                compositor: Cell::new(ptr::null_mut()) });
 
             // Set up compositor handler, if the user provided it.
             let compositor_handler = self.compositor_handler.or_else(|| Some(Box::new(())));
             let compositor_handler = compositor_handler.map(|handler| {
-                let mut compositor_handler = InternalCompositor::new(handler);
+                let handle = CompositorHandle::from_lock(&lock);
+                let mut compositor_handler = InternalCompositor::new((handle, handler));
                 wl_signal_add(&mut (*compositor).events.new_surface as *mut _ as _,
                               compositor_handler.new_surface_listener() as *mut _ as _);
                 wl_signal_add(&mut (*compositor).events.destroy as *mut _ as _,
@@ -276,7 +570,7 @@ impl CompositorBuilder {
             // Set up input manager, if the user provided it.
             let input_manager = self.input_manager_handler.map(|handler| {
                 let mut input_manager = InputManager::new(handler);
-                wl_signal_add(&mut (*backend).events.new_input as *mut _ as _,
+                wl_signal_add(&mut (*backend.as_ptr()).events.new_input as *mut _ as _,
                               input_manager.add_listener() as *mut _ as _);
                 input_manager
             });
@@ -284,11 +578,23 @@ impl CompositorBuilder {
             // Set up output manager, if the user provided it.
             let output_manager = self.output_manager_handler.map(|handler| {
                 let mut output_manager = OutputManager::new(handler);
-                wl_signal_add(&mut (*backend).events.new_output as *mut _ as _,
+                wl_signal_add(&mut (*backend.as_ptr()).events.new_output as *mut _ as _,
                               output_manager.add_listener() as *mut _ as _);
                 output_manager
             });
 
+            // NOTE
+            // Set up after the output manager, so that new outputs were
+            // accepted or refused by the handler when they are advertised.
+            let output_management_handler = self.output_management_handler;
+            let output_management = match self.output_management_layout {
+                Some(layout) => {
+                    let handler = output_management_handler.unwrap_or_else(|| Box::new(()));
+                    OutputManagementManager::new(display, backend.as_ptr(), layout, handler)
+                }
+                None => None
+            };
+
             // Set up the xdg_shell handler and associated Wayland global,
             // if user provided a manager for it.
             let mut xdg_shell_global = ptr::null_mut();
@@ -320,22 +626,14 @@ impl CompositorBuilder {
                                                   });
 
             let user_terminate = self.user_terminate;
+            let states = self.states;
 
-            // Open the socket to the Wayland server.
-            let socket = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_add_socket_auto, display);
-            if socket.is_null() {
-                // NOTE Rationale for panicking:
-                // * Won't be in C land just yet, so it's safe to panic
-                // * Can always be returned in a Result instead, but for now
-                //   if you auto create it's assumed you can't recover.
-                panic!("Unable to open wayland socket");
-            }
-            let socket_name = CStr::from_ptr(socket).to_string_lossy().into_owned();
             wlr_log!(L_DEBUG,
                      "Running compositor on wayland display {}",
                      socket_name);
             env::set_var("_WAYLAND_DISPLAY", socket_name.clone());
             let compositor = Compositor { data: Box::new(data),
+                                          states,
                                           compositor_handler,
                                           socket_name,
                                           input_manager,
@@ -347,17 +645,25 @@ impl CompositorBuilder {
                                           data_device_manager,
                                           compositor,
                                           backend,
+                                          session,
+                                          owns_session,
                                           display,
                                           event_loop,
+                                          command_receiver: None,
                                           shm_fd,
                                           server_decoration_manager,
+                                          gamma_control_manager,
+                                          screenshooter,
+                                          presentation,
+                                          xdg_output_manager,
+                                          output_management,
                                           renderer,
                                           xwayland,
                                           user_terminate,
                                           panic_error: None,
-                                          lock: Rc::new(Cell::new(false)) };
+                                          lock };
             compositor.set_lock(true);
-            compositor
+            Ok(compositor)
         }
     }
 }
@@ -365,49 +671,96 @@ impl CompositorBuilder {
 impl Compositor {
     /// Creates a weak reference to the `Compositor`.
     pub fn weak_reference(&self) -> CompositorHandle {
-        let handle = Rc::downgrade(&self.lock);
-        CompositorHandle { handle }
+        CompositorHandle::from_lock(&self.lock)
     }
 
     /// Enters the wayland event loop. Won't return until the compositor is
     /// shut off
+    ///
+    /// # Panics
+    /// Panics if the compositor could not be started.
+    /// Use `try_run` to handle this error instead.
     pub fn run(self) {
-        self.run_with(|_| unsafe {
+        self.run_with(|compositor| unsafe {
                           ffi_dispatch!(WAYLAND_SERVER_HANDLE,
                                         wl_display_run,
-                                        (*COMPOSITOR_PTR).display);
+                                        compositor.display);
                       })
     }
 
+    /// Enters the wayland event loop. Won't return until the compositor is
+    /// shut off
+    ///
+    /// Returns an error if the compositor could not be started.
+    pub fn try_run(self) -> Result<(), CompositorError> {
+        self.try_run_with(|compositor| unsafe {
+                              ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                            wl_display_run,
+                                            compositor.display);
+                          })
+    }
+
     /// Prepare to enter the wayland event loop. Instead of calling
     /// `wl_display_run`, the provided callback function is invoked. Allows
     /// integration with a different event loop.
+    ///
+    /// # Panics
+    /// Panics if the compositor could not be started.
+    /// Use `try_run_with` to handle this error instead.
     pub fn run_with<F>(self, runner: F)
         where F: FnOnce(&Compositor)
+    {
+        if let Err(err) = self.try_run_with(runner) {
+            // NOTE Rationale for panicking:
+            // * Nicer than an abort
+            // * Not yet in C land
+            // * Use `try_run_with` if you need to recover from this.
+            panic!("{}", err)
+        }
+    }
+
+    /// Prepare to enter the wayland event loop. Instead of calling
+    /// `wl_display_run`, the provided callback function is invoked. Allows
+    /// integration with a different event loop.
+    ///
+    /// Only one compositor can run on a thread at a time. To run several
+    /// compositors at once, run each of them on its own thread.
+    ///
+    /// Returns an error if the compositor could not be started.
+    pub fn try_run_with<F>(self, runner: F) -> Result<(), CompositorError>
+        where F: FnOnce(&Compositor)
     {
         unsafe {
+            if !running_compositor().is_null() {
+                return Err(CompositorError::AlreadyRunning)
+            }
             self.set_lock(false);
             let compositor = UnsafeCell::new(self);
-            if COMPOSITOR_PTR != 0 as _ {
-                // NOTE Rationale for panicking:
-                // * Nicer than an abort
-                // * Not yet in C land
-                panic!("A compositor is already running!")
-            }
-            COMPOSITOR_PTR = compositor.get();
+            let compositor_ptr = compositor.get();
+            (*compositor_ptr).lock.compositor.set(compositor_ptr);
+            set_running_compositor(compositor_ptr);
             wlr_log!(L_INFO, "Starting compositor");
-            if !wlr_backend_start((*compositor.get()).backend) {
-                wlr_backend_destroy((*compositor.get()).backend);
-                // NOTE Rationale for panicking:
-                // * Won't be in C land just yet, so it's safe to panic
-                // * Can always be returned in a Result instead, but for now
-                //   if you auto create it's assumed you can't recover.
-                panic!("Failed to start backend");
+            if !wlr_backend_start((*compositor_ptr).backend.as_ptr()) {
+                // NOTE The backend is destroyed when the compositor is dropped.
+                (*compositor_ptr).lock.compositor.set(ptr::null_mut());
+                set_running_compositor(ptr::null_mut());
+                return Err(CompositorError::BackendStartFailed)
             }
-            env::set_var("WAYLAND_DISPLAY", (*COMPOSITOR_PTR).socket_name.clone());
-            runner(&*COMPOSITOR_PTR);
-            match (*compositor.get()).panic_error.take() {
-                None => {}
+            // NOTE
+            // The environment is shared by the whole process, so with several
+            // compositors this refers to the one that started last.
+            env::set_var("WAYLAND_DISPLAY", (*compositor_ptr).socket_name.clone());
+            runner(&*compositor_ptr);
+            let panic_error = (*compositor_ptr).panic_error.take();
+            // NOTE
+            // Destroy signals are emitted while the compositor is torn down,
+            // so it has to be unreachable from handles and from
+            // `handle_unwind` before it is dropped.
+            (*compositor_ptr).lock.compositor.set(ptr::null_mut());
+            set_running_compositor(ptr::null_mut());
+            drop(compositor);
+            match panic_error {
+                None => Ok(()),
                 Some(err) => {
                     // A panic occured, now we can re-throw it safely.
                     ::std::panic::resume_unwind(err)
@@ -427,8 +780,46 @@ impl Compositor {
         self.display
     }
 
-    pub unsafe fn event_loop(&self) -> *mut wl_event_loop {
-        self.event_loop
+    /// Gets the backend the compositor was built with.
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    /// Gets the backend the compositor was built with mutably, e.g to add
+    /// outputs to a headless backend.
+    pub fn backend_mut(&mut self) -> &mut Backend {
+        &mut self.backend
+    }
+
+    /// Gets the session the backend uses to open devices, if it uses one.
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    /// Gets the session the backend uses mutably, e.g to switch VTs.
+    pub fn session_mut(&mut self) -> Option<&mut Session> {
+        self.session.as_mut()
+    }
+
+    /// Gets the event loop the compositor runs on.
+    ///
+    /// Use this to add timers, idle callbacks, and to watch file descriptors
+    /// and signals.
+    pub fn event_loop(&self) -> EventLoop {
+        self.event_loop.clone()
+    }
+
+    /// Gets a sender that can be used to run closures on the compositor
+    /// from other threads.
+    ///
+    /// Returns `None` if the channel could not be set up.
+    pub fn sender(&mut self) -> Option<CompositorSender> {
+        if let Some(ref receiver) = self.command_receiver {
+            return Some(receiver.sender())
+        }
+        let (receiver, sender) = CommandReceiver::new(&self.event_loop)?;
+        self.command_receiver = Some(receiver);
+        Some(sender)
     }
 
     /// Saves the panic error information in the compositor, to be re-thrown
@@ -442,16 +833,55 @@ impl Compositor {
     /// # Panics
     /// Panics when trying to set the lock on an upgraded handle.
     unsafe fn set_lock(&self, val: bool) {
-        self.lock.set(val)
+        self.lock.borrowed.set(val)
     }
 }
 
 impl Drop for Compositor {
     fn drop(&mut self) {
-        unsafe { wlr_compositor_destroy(self.compositor) }
+        unsafe {
+            // NOTE
+            // The extensions have to be destroyed before the display,
+            // as they are globals on it.
+            self.xwayland.take();
+            self.server_decoration_manager.take();
+            self.gamma_control_manager.take();
+            self.screenshooter.take();
+            self.presentation.take();
+            self.xdg_output_manager.take();
+            self.output_management.take();
+            self.data_device_manager.take();
+            self.command_receiver.take();
+            wlr_compositor_destroy(self.compositor);
+            // NOTE
+            // The gles2 renderer is owned by the backend, so it's freed here
+            // too. The software renderer is freed when it's dropped.
+            self.renderer.take();
+            wlr_backend_destroy(self.backend.as_ptr());
+            // NOTE
+            // The session can only go once no backend uses it anymore, and
+            // giving it back releases the VT.
+            if let Some(session) = self.session.take() {
+                if self.owns_session {
+                    session.destroy()
+                }
+            }
+            // Destroying the display closes the socket, so that another
+            // compositor can be started in the same process.
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_destroy, self.display);
+        }
     }
 }
 
+/// Tears down the backend made by `try_build` when it can't go on.
+unsafe fn destroy_backend(display: *mut wl_display, backend: Backend, session: Option<Session>) {
+    wlr_backend_destroy(backend.as_ptr());
+    if let Some(session) = session {
+        session.destroy()
+    }
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_destroy, display);
+}
+
 impl CompositorHandle {
     /// Constructs a new `CompositorHandle` that is always invalid. Calling `run` on this
     /// will always fail.
@@ -459,7 +889,13 @@ impl CompositorHandle {
     /// This is useful for pre-filling a value before it's provided by the server, or
     /// for mocking/testing.
     pub fn new() -> Self {
-        CompositorHandle { handle: Weak::new() }
+        CompositorHandle { handle: Weak::new(),
+                           lock: ptr::null() }
+    }
+
+    fn from_lock(lock: &Rc<CompositorLock>) -> Self {
+        CompositorHandle { handle: Rc::downgrade(lock),
+                           lock: &**lock }
     }
 
     /// Upgrades the compositor handle to a reference to the backing `Compositor`.
@@ -476,14 +912,15 @@ impl CompositorHandle {
             // We drop the Rc here because having two would allow a dangling
             // pointer to exist!
             .and_then(|check| {
-                if check.get() {
+                if check.borrowed.get() {
                     return Err(HandleErr::AlreadyBorrowed)
                 }
-                if COMPOSITOR_PTR.is_null() {
+                let compositor = check.compositor.get();
+                if compositor.is_null() {
                     return Err(HandleErr::AlreadyDropped)
                 }
-                check.set(true);
-                Ok(&mut *COMPOSITOR_PTR)
+                check.borrowed.set(true);
+                Ok(&mut *compositor)
             })
     }
 
@@ -510,13 +947,13 @@ impl CompositorHandle {
         let res = panic::catch_unwind(panic::AssertUnwindSafe(|| runner(compositor)));
         self.handle.upgrade().map(|check| {
                                       // Sanity check that it hasn't been tampered with.
-                                      if !check.get() {
+                                      if !check.borrowed.get() {
                                           wlr_log!(L_ERROR,
                                                    "After running compositor callback, mutable \
                                                     lock was false");
                                           panic!("Compositor lock in incorrect state!");
                                       }
-                                      check.set(false)
+                                      check.borrowed.set(false)
                                   });
         match res {
             Ok(res) => Ok(res),
@@ -525,27 +962,181 @@ impl CompositorHandle {
     }
 }
 
-/// Terminates the compositor and execute any user clean up code.
+impl PartialEq for CompositorHandle {
+    fn eq(&self, other: &CompositorHandle) -> bool {
+        self.lock == other.lock
+    }
+}
+
+impl Eq for CompositorHandle {}
+
+impl Hash for CompositorHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lock.hash(state)
+    }
+}
+
+impl Handle for CompositorHandle {
+    type Target = Compositor;
+
+    fn run<F, R>(&self, runner: F) -> HandleResult<R>
+        where F: FnOnce(&mut Compositor) -> R
+    {
+        CompositorHandle::run(self, runner)
+    }
+
+    /// Determines if the compositor is still running.
+    fn is_alive(&self) -> bool {
+        self.handle.upgrade()
+            .map(|check| !check.compositor.get().is_null())
+            .unwrap_or(false)
+    }
+
+    fn id(&self) -> usize {
+        self.lock as usize
+    }
+}
+
+impl<D> StateHandle<D> {
+    /// Run a function on the referenced state, if the compositor that owns
+    /// it still exists.
+    ///
+    /// Returns the result of the function, if successful.
+    ///
+    /// Returns `HandleErr::AlreadyBorrowed` if the state is already being
+    /// used further up the stack.
+    pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
+        where F: FnOnce(&mut D) -> R
+    {
+        let state = self.handle.upgrade().ok_or(HandleErr::AlreadyDropped)?;
+        if state.borrowed.get() {
+            return Err(HandleErr::AlreadyBorrowed)
+        }
+        state.borrowed.set(true);
+        let res = {
+            let data = unsafe { &mut *state.data.get() };
+            panic::catch_unwind(panic::AssertUnwindSafe(|| runner(data)))
+        };
+        state.borrowed.set(false);
+        match res {
+            Ok(res) => Ok(res),
+            Err(err) => panic::resume_unwind(err)
+        }
+    }
+}
+
+impl<D> Clone for StateHandle<D> {
+    fn clone(&self) -> Self {
+        StateHandle { handle: self.handle.clone(),
+                      lock: self.lock }
+    }
+}
+
+impl<D> fmt::Debug for StateHandle<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StateHandle {{ lock: {:p} }}", self.lock)
+    }
+}
+
+impl<D> PartialEq for StateHandle<D> {
+    fn eq(&self, other: &StateHandle<D>) -> bool {
+        self.lock == other.lock
+    }
+}
+
+impl<D> Eq for StateHandle<D> {}
+
+impl<D> Hash for StateHandle<D> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lock.hash(state)
+    }
+}
+
+impl<D> Handle for StateHandle<D> {
+    type Target = D;
+
+    fn run<F, R>(&self, runner: F) -> HandleResult<R>
+        where F: FnOnce(&mut D) -> R
+    {
+        StateHandle::run(self, runner)
+    }
+
+    fn is_alive(&self) -> bool {
+        self.handle.upgrade().is_some()
+    }
+
+    fn id(&self) -> usize {
+        self.lock as usize
+    }
+}
+
+/// Terminates the compositor running on this thread and execute any user
+/// clean up code.
 pub fn terminate() {
     unsafe {
-        if COMPOSITOR_PTR != 0 as _ {
-            let compositor = &mut *COMPOSITOR_PTR;
+        let compositor = running_compositor();
+        if !compositor.is_null() {
+            let compositor = &mut *compositor;
             compositor.terminate();
             compositor.user_terminate.map(|f| f());
         }
     }
 }
 
-/// Gets a handle to the compositor.
+/// Gets a handle to the compositor running on this thread.
 ///
 /// If the compositor has not started running yet, or if it has stopped,
 /// then this function will return None.
 pub fn compositor_handle() -> Option<CompositorHandle> {
     unsafe {
-        if COMPOSITOR_PTR.is_null() {
+        let compositor = running_compositor();
+        if compositor.is_null() {
             None
         } else {
-            Some((&mut *COMPOSITOR_PTR).weak_reference())
+            Some((&*compositor).weak_reference())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{panic, rc::Rc};
+
+    use super::CompositorBuilder;
+    use {Handle, HandleErr};
+
+    #[test]
+    fn state_handle_borrows_typed_state() {
+        let mut builder = CompositorBuilder::new();
+        let state = builder.state(vec![1, 2]);
+        let other = state.clone();
+        assert_eq!(state, other);
+        assert!(state.is_alive());
+        state.run(|numbers: &mut Vec<i32>| numbers.push(3)).unwrap();
+        let nested = state.run(|_| other.run(|_| ()));
+        assert_eq!(nested, Ok(Err(HandleErr::AlreadyBorrowed)));
+        assert_eq!(other.run(|numbers| numbers.clone()), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn state_handle_is_released_after_panic() {
+        let mut builder = CompositorBuilder::new();
+        let state = builder.state(0);
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                                          state.run(|_| panic!("Expected panic")).unwrap();
+                                      }));
+        assert!(res.is_err());
+        assert_eq!(state.run(|count| *count += 1), Ok(()));
+    }
+
+    #[test]
+    fn state_is_dropped_with_its_owner() {
+        let data = Rc::new(());
+        let mut builder = CompositorBuilder::new();
+        let state = builder.state(data.clone());
+        drop(builder);
+        assert_eq!(Rc::strong_count(&data), 1);
+        assert!(!state.is_alive());
+        assert_eq!(state.run(|_| ()), Err(HandleErr::AlreadyDropped));
+    }
+}
@@ -1,7 +1,9 @@
 //! All the errors used in wlroots-rs.
 
 use std::error::Error;
-use std::fmt;
+use std::{fmt, io};
+
+use png;
 
 /// The result of trying to upgrade a handle, either using `run` or
 /// `with_handles!`.
@@ -36,3 +38,243 @@ impl Error for HandleErr {
         }
     }
 }
+
+/// The ways building or running a `Compositor` can fail.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CompositorError {
+    /// The backend could not be created.
+    BackendCreationFailed,
+    /// The renderer (GLES2 or software) could not be created.
+    RendererCreationFailed,
+    /// The Wayland socket for clients to connect to could not be opened.
+    SocketOpenFailed,
+    /// The backend was created, but could not be started.
+    BackendStartFailed,
+    /// Another compositor is already running on this thread.
+    AlreadyRunning
+}
+
+impl fmt::Display for CompositorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use CompositorError::*;
+        match *self {
+            BackendCreationFailed => write!(f, "Could not create backend"),
+            RendererCreationFailed => write!(f, "Could not construct renderer"),
+            SocketOpenFailed => write!(f, "Unable to open wayland socket"),
+            BackendStartFailed => write!(f, "Failed to start backend"),
+            AlreadyRunning => write!(f, "A compositor is already running!")
+        }
+    }
+}
+
+impl Error for CompositorError {
+    fn description(&self) -> &str {
+        use CompositorError::*;
+        match *self {
+            BackendCreationFailed => "Backend could not be created",
+            RendererCreationFailed => "Renderer could not be created",
+            SocketOpenFailed => "Wayland socket could not be opened",
+            BackendStartFailed => "Backend could not be started",
+            AlreadyRunning => "Compositor is already running"
+        }
+    }
+}
+
+/// The ways loading an image file can fail.
+#[derive(Debug)]
+pub enum ImageError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The PNG decoder failed.
+    Png(png::DecodingError),
+    /// The file is not in one of the supported formats.
+    UnknownFormat,
+    /// The file uses a feature of its format that isn't supported.
+    Unsupported(&'static str),
+    /// The file is corrupted, the string says which part.
+    Malformed(&'static str),
+    /// The renderer could not make a texture in the requested format.
+    TextureCreationFailed
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ImageError::*;
+        match *self {
+            Io(ref err) => write!(f, "Could not read image: {}", err),
+            Png(ref err) => write!(f, "Could not decode PNG: {}", err),
+            UnknownFormat => write!(f, "Unknown image format"),
+            Unsupported(what) => write!(f, "Unsupported {}", what),
+            Malformed(what) => write!(f, "Malformed {}", what),
+            TextureCreationFailed => write!(f, "Could not create texture")
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn description(&self) -> &str {
+        use ImageError::*;
+        match *self {
+            Io(_) => "Image could not be read",
+            Png(_) => "PNG could not be decoded",
+            UnknownFormat => "Image format is unknown",
+            Unsupported(_) => "Image uses an unsupported feature",
+            Malformed(_) => "Image is malformed",
+            TextureCreationFailed => "Texture could not be created"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ImageError::Io(ref err) => Some(err),
+            ImageError::Png(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(err: io::Error) -> Self {
+        ImageError::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(err: png::DecodingError) -> Self {
+        ImageError::Png(err)
+    }
+}
+
+/// The ways loading an `OutputConfig` can fail.
+#[derive(Debug)]
+pub enum OutputConfigError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file isn't a valid INI file.
+    Parse(String),
+    /// A setting has a value that can't be understood.
+    InvalidValue {
+        section: String,
+        key: String,
+        value: String
+    },
+    /// An `output` section has a key that isn't a setting.
+    UnknownKey { section: String, key: String },
+    /// `reload` was called on a config that wasn't loaded from a file.
+    NoPath
+}
+
+impl fmt::Display for OutputConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use OutputConfigError::*;
+        match *self {
+            Io(ref err) => write!(f, "Could not read output config: {}", err),
+            Parse(ref err) => write!(f, "Could not parse output config: {}", err),
+            InvalidValue { ref section,
+                           ref key,
+                           ref value } => {
+                write!(f, "Invalid value {:?} for {} in output section {}", value, key, section)
+            }
+            UnknownKey { ref section,
+                         ref key } => {
+                write!(f, "Unknown key {} in output section {}", key, section)
+            }
+            NoPath => write!(f, "Output config was not loaded from a file")
+        }
+    }
+}
+
+impl Error for OutputConfigError {
+    fn description(&self) -> &str {
+        use OutputConfigError::*;
+        match *self {
+            Io(_) => "Output config could not be read",
+            Parse(_) => "Output config could not be parsed",
+            InvalidValue { .. } => "Output config has an invalid value",
+            UnknownKey { .. } => "Output config has an unknown key",
+            NoPath => "Output config has no file to reload"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            OutputConfigError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for OutputConfigError {
+    fn from(err: io::Error) -> Self {
+        OutputConfigError::Io(err)
+    }
+}
+
+/// The ways loading or saving a `SavedLayout` can fail.
+#[derive(Debug)]
+pub enum SavedLayoutError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The file isn't a valid INI file.
+    Parse(String),
+    /// A `layout` section isn't numbered, e.g `[layout:abc]`.
+    InvalidSection(String),
+    /// A key has a value that can't be understood, or isn't a known key.
+    InvalidValue {
+        section: String,
+        key: String,
+        value: String
+    },
+    /// An output in a `layout` section has no position.
+    MissingPosition { section: String, output: String },
+    /// `save` was called on a layout that wasn't loaded from a file.
+    NoPath
+}
+
+impl fmt::Display for SavedLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use SavedLayoutError::*;
+        match *self {
+            Io(ref err) => write!(f, "Could not read or write saved layouts: {}", err),
+            Parse(ref err) => write!(f, "Could not parse saved layouts: {}", err),
+            InvalidSection(ref section) => write!(f, "Invalid layout section {}", section),
+            InvalidValue { ref section,
+                           ref key,
+                           ref value } => {
+                write!(f, "Invalid value {:?} for {} in layout section {}", value, key, section)
+            }
+            MissingPosition { ref section,
+                              ref output } => {
+                write!(f, "No position for {} in layout section {}", output, section)
+            }
+            NoPath => write!(f, "Saved layouts were not loaded from a file")
+        }
+    }
+}
+
+impl Error for SavedLayoutError {
+    fn description(&self) -> &str {
+        use SavedLayoutError::*;
+        match *self {
+            Io(_) => "Saved layouts could not be read or written",
+            Parse(_) => "Saved layouts could not be parsed",
+            InvalidSection(_) => "Saved layouts have an invalid section",
+            InvalidValue { .. } => "Saved layouts have an invalid value",
+            MissingPosition { .. } => "Saved layouts have an output without a position",
+            NoPath => "Saved layouts have no file to save to"
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            SavedLayoutError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SavedLayoutError {
+    fn from(err: io::Error) -> Self {
+        SavedLayoutError::Io(err)
+    }
+}
@@ -0,0 +1,528 @@
+//! Safe wrappers around the event loop the compositor runs on.
+//!
+//! Sources added to the event loop (file descriptors, timers, idle callbacks
+//! and signals) are removed when the returned source is dropped, so keep it
+//! around for as long as you want the callback to be called.
+
+use libc;
+use std::{panic, cell::Cell, os::unix::io::RawFd, rc::Rc, time::Duration};
+
+use wayland_sys::server::{wl_event_loop, wl_event_source, WAYLAND_SERVER_HANDLE};
+
+use compositor::{compositor_handle, CompositorHandle};
+use utils::{handle_unwind, ToMS};
+
+bitflags! {
+    /// The events to watch for on a file descriptor.
+    pub struct FdMask: u32 {
+        const READABLE = 0x01;
+        const WRITABLE = 0x02;
+        const HANGUP = 0x04;
+        const ERROR = 0x08;
+    }
+}
+
+wayland_listener!(EventLoopListener, Cell<bool>, [
+    destroy_listener => destroy_notify: |this: &mut EventLoopListener,
+                                         _data: *mut libc::c_void,|
+    unsafe {
+        this.data.set(false);
+    };
+]);
+
+/// Tracks whether the `wl_event_loop` is still alive, so that sources are
+/// not removed from an event loop that has already been destroyed.
+struct EventLoopGuard(Box<EventLoopListener>);
+
+/// A handle to the event loop the compositor runs on.
+///
+/// This can be cloned freely and stored in handlers.
+#[derive(Clone)]
+pub struct EventLoop {
+    event_loop: *mut wl_event_loop,
+    guard: Rc<EventLoopGuard>
+}
+
+/// An event source on the event loop.
+///
+/// When this is dropped, the source is removed from the event loop and its
+/// callback is freed.
+pub struct EventSource {
+    source: *mut wl_event_source,
+    guard: Rc<EventLoopGuard>,
+    /// Frees the boxed callback that was given to the event loop.
+    free: unsafe fn(*mut libc::c_void),
+    data: *mut libc::c_void
+}
+
+/// A timer on the event loop.
+///
+/// A new timer is not armed. Use `update` to arm it, and call it again from
+/// the callback to make the timer repeat.
+///
+/// When this is dropped, the timer is removed from the event loop.
+pub struct TimerHandle {
+    source: EventSource
+}
+
+/// A callback that runs once, the next time the event loop is idle.
+///
+/// When this is dropped before the callback has run, the callback is
+/// cancelled and freed.
+pub struct IdleHandle {
+    source: *mut wl_event_source,
+    guard: Rc<EventLoopGuard>,
+    data: *mut IdleData
+}
+
+/// The data given to the event loop for an idle callback.
+///
+/// This is owned by the `IdleHandle`, because the event loop removes idle
+/// sources by itself once they have been dispatched.
+struct IdleData {
+    callback: Option<IdleCallback>,
+    dispatched: bool
+}
+
+type FdCallback = Box<FnMut(CompositorHandle, RawFd, FdMask)>;
+type TimerCallback = Box<FnMut(CompositorHandle)>;
+type IdleCallback = Box<FnMut(CompositorHandle)>;
+type SignalCallback = Box<FnMut(CompositorHandle, libc::c_int)>;
+
+impl EventLoop {
+    /// Wraps the event loop, listening for when it is destroyed.
+    pub(crate) unsafe fn from_ptr(event_loop: *mut wl_event_loop) -> Self {
+        let mut listener = EventLoopListener::new(Cell::new(true));
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_event_loop_add_destroy_listener,
+                      event_loop,
+                      listener.destroy_listener() as *mut _ as _);
+        EventLoop { event_loop,
+                    guard: Rc::new(EventLoopGuard(listener)) }
+    }
+
+    /// Calls `callback` whenever one of the events in `mask` happens on `fd`.
+    ///
+    /// Returns `None` if the event loop has been destroyed or the file
+    /// descriptor could not be watched.
+    pub fn add_fd<F>(&self, fd: RawFd, mask: FdMask, callback: F) -> Option<EventSource>
+        where F: FnMut(CompositorHandle, RawFd, FdMask) + 'static
+    {
+        if !self.alive() {
+            return None
+        }
+        unsafe {
+            let data = Box::into_raw(Box::new(Box::new(callback) as FdCallback));
+            let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_fd,
+                                       self.event_loop,
+                                       fd,
+                                       mask.bits(),
+                                       fd_notify,
+                                       data as *mut _);
+            EventSource::new(source, self.guard.clone(), free::<FdCallback>, data as *mut _)
+        }
+    }
+
+    /// Adds a timer that calls `callback` when it fires.
+    ///
+    /// The timer is not armed, use `TimerHandle::update` to arm it.
+    ///
+    /// Returns `None` if the event loop has been destroyed or the timer
+    /// could not be created.
+    pub fn add_timer<F>(&self, callback: F) -> Option<TimerHandle>
+        where F: FnMut(CompositorHandle) + 'static
+    {
+        if !self.alive() {
+            return None
+        }
+        unsafe {
+            let data = Box::into_raw(Box::new(Box::new(callback) as TimerCallback));
+            let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_timer,
+                                       self.event_loop,
+                                       timer_notify,
+                                       data as *mut _);
+            EventSource::new(source,
+                             self.guard.clone(),
+                             free::<TimerCallback>,
+                             data as *mut _).map(|source| TimerHandle { source })
+        }
+    }
+
+    /// Calls `callback` once, the next time the event loop is idle.
+    ///
+    /// The callback is cancelled if the returned handle is dropped before it
+    /// has run.
+    ///
+    /// Returns `None` if the event loop has been destroyed or the callback
+    /// could not be added.
+    pub fn add_idle<F>(&self, callback: F) -> Option<IdleHandle>
+        where F: FnOnce(CompositorHandle) + 'static
+    {
+        if !self.alive() {
+            return None
+        }
+        let mut callback = Some(callback);
+        let callback = move |compositor| {
+            if let Some(callback) = callback.take() {
+                callback(compositor)
+            }
+        };
+        unsafe {
+            let callback: IdleCallback = Box::new(callback);
+            let data = Box::into_raw(Box::new(IdleData { callback: Some(callback),
+                                                         dispatched: false }));
+            let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_idle,
+                                       self.event_loop,
+                                       idle_notify,
+                                       data as *mut _);
+            if source.is_null() {
+                free::<IdleData>(data as *mut _);
+                return None
+            }
+            Some(IdleHandle { source,
+                              guard: self.guard.clone(),
+                              data })
+        }
+    }
+
+    /// Calls `callback` whenever the process receives the signal `signum`
+    /// (e.g `libc::SIGUSR1`).
+    ///
+    /// The signal is blocked for the process so that it can be read from the
+    /// event loop.
+    ///
+    /// Returns `None` if the event loop has been destroyed or the signal
+    /// could not be watched.
+    pub fn add_signal<F>(&self, signum: libc::c_int, callback: F) -> Option<EventSource>
+        where F: FnMut(CompositorHandle, libc::c_int) + 'static
+    {
+        if !self.alive() {
+            return None
+        }
+        unsafe {
+            let data = Box::into_raw(Box::new(Box::new(callback) as SignalCallback));
+            let source = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                       wl_event_loop_add_signal,
+                                       self.event_loop,
+                                       signum,
+                                       signal_notify,
+                                       data as *mut _);
+            EventSource::new(source,
+                             self.guard.clone(),
+                             free::<SignalCallback>,
+                             data as *mut _)
+        }
+    }
+
+    /// Determines if the event loop still exists.
+    pub fn alive(&self) -> bool {
+        self.guard.alive()
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wl_event_loop {
+        self.event_loop
+    }
+}
+
+impl EventLoopGuard {
+    fn alive(&self) -> bool {
+        self.0.data.get()
+    }
+}
+
+impl Drop for EventLoopGuard {
+    fn drop(&mut self) {
+        if self.alive() {
+            unsafe {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_list_remove,
+                              &mut (*self.0.destroy_listener()).link as *mut _ as _);
+            }
+        }
+    }
+}
+
+impl EventSource {
+    /// Takes ownership of the source and its callback data.
+    ///
+    /// If the source is null the data is freed and `None` is returned.
+    unsafe fn new(source: *mut wl_event_source,
+                  guard: Rc<EventLoopGuard>,
+                  free: unsafe fn(*mut libc::c_void),
+                  data: *mut libc::c_void)
+                  -> Option<Self> {
+        if source.is_null() {
+            free(data);
+            return None
+        }
+        Some(EventSource { source,
+                           guard,
+                           free,
+                           data })
+    }
+
+    pub unsafe fn as_ptr(&self) -> *mut wl_event_source {
+        self.source
+    }
+}
+
+impl Drop for EventSource {
+    fn drop(&mut self) {
+        unsafe {
+            // NOTE
+            // Once the event loop is destroyed its sources can't be removed
+            // anymore, so we only need to free the callback.
+            if self.guard.alive() {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.source);
+            }
+            (self.free)(self.data)
+        }
+    }
+}
+
+impl TimerHandle {
+    /// Arms the timer to fire once after `delay`.
+    ///
+    /// Calling this again re-arms the timer, replacing the previous delay.
+    /// A delay of zero disarms the timer.
+    ///
+    /// Returns `false` if the timer could not be updated.
+    pub fn update(&mut self, delay: Duration) -> bool {
+        if !self.source.guard.alive() {
+            return false
+        }
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_source_timer_update,
+                          self.source.source,
+                          delay.to_ms() as libc::c_int) == 0
+        }
+    }
+
+    /// Disarms the timer.
+    pub fn cancel(&mut self) -> bool {
+        self.update(Duration::from_millis(0))
+    }
+}
+
+impl IdleHandle {
+    /// Determines if the callback is still waiting to run.
+    pub fn pending(&self) -> bool {
+        self.guard.alive() && unsafe { !(*self.data).dispatched }
+    }
+}
+
+impl Drop for IdleHandle {
+    fn drop(&mut self) {
+        unsafe {
+            // NOTE
+            // The event loop removes idle sources itself once they have been
+            // dispatched, and frees all of its sources when it is destroyed.
+            if self.pending() {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_source_remove, self.source);
+            }
+            free::<IdleData>(self.data as *mut _)
+        }
+    }
+}
+
+unsafe fn free<T>(data: *mut libc::c_void) {
+    Box::from_raw(data as *mut T);
+}
+
+/// Gets a handle to the compositor to pass to callbacks.
+///
+/// If the compositor isn't running, the handle will always be invalid.
+fn callback_compositor() -> CompositorHandle {
+    compositor_handle().unwrap_or_else(CompositorHandle::new)
+}
+
+unsafe extern "C" fn fd_notify(fd: libc::c_int,
+                               mask: u32,
+                               data: *mut libc::c_void)
+                               -> libc::c_int {
+    let callback = &mut *(data as *mut FdCallback);
+    handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        callback(callback_compositor(), fd, FdMask::from_bits_truncate(mask))
+    })));
+    0
+}
+
+unsafe extern "C" fn timer_notify(data: *mut libc::c_void) -> libc::c_int {
+    let callback = &mut *(data as *mut TimerCallback);
+    handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        callback(callback_compositor())
+    })));
+    0
+}
+
+unsafe extern "C" fn idle_notify(data: *mut libc::c_void) {
+    // NOTE
+    // Idle sources are removed by the event loop after they are dispatched,
+    // so this is the last time we see the callback. The data itself is freed
+    // by the `IdleHandle`.
+    let idle = &mut *(data as *mut IdleData);
+    idle.dispatched = true;
+    if let Some(mut callback) = idle.callback.take() {
+        handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            callback(callback_compositor())
+        })));
+    }
+}
+
+unsafe extern "C" fn signal_notify(signum: libc::c_int, data: *mut libc::c_void) -> libc::c_int {
+    let callback = &mut *(data as *mut SignalCallback);
+    handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        callback(callback_compositor(), signum)
+    })));
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use libc;
+    use wayland_sys::server::WAYLAND_SERVER_HANDLE;
+
+    use super::{EventLoop, FdMask};
+
+    /// Runs `test` with an event loop that isn't attached to a compositor.
+    fn with_event_loop<F: FnOnce(&EventLoop)>(test: F) {
+        unsafe {
+            let ptr = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_loop_create,);
+            assert!(!ptr.is_null());
+            let event_loop = EventLoop::from_ptr(ptr);
+            test(&event_loop);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_event_loop_destroy, ptr);
+            assert!(!event_loop.alive());
+        }
+    }
+
+    fn dispatch(event_loop: &EventLoop, timeout: libc::c_int) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_loop_dispatch,
+                          event_loop.as_ptr(),
+                          timeout);
+        }
+    }
+
+    #[test]
+    fn fd_source() {
+        with_event_loop(|event_loop| unsafe {
+            let mut fds = [0; 2];
+            assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+            let events = Rc::new(Cell::new(0));
+            let seen = events.clone();
+            let source = event_loop.add_fd(fds[0], FdMask::READABLE, move |_, fd, mask| {
+                                       assert!(mask.contains(FdMask::READABLE));
+                                       let mut byte = 0u8;
+                                       libc::read(fd, &mut byte as *mut u8 as *mut _, 1);
+                                       seen.set(seen.get() + 1);
+                                   })
+                                   .expect("Could not add fd");
+            dispatch(event_loop, 0);
+            assert_eq!(events.get(), 0);
+            libc::write(fds[1], b"x".as_ptr() as *const _, 1);
+            dispatch(event_loop, 100);
+            assert_eq!(events.get(), 1);
+            drop(source);
+            libc::write(fds[1], b"x".as_ptr() as *const _, 1);
+            dispatch(event_loop, 0);
+            assert_eq!(events.get(), 1);
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        })
+    }
+
+    #[test]
+    fn timer_source() {
+        with_event_loop(|event_loop| {
+            let fired = Rc::new(Cell::new(0));
+            let seen = fired.clone();
+            let mut timer = event_loop.add_timer(move |_| seen.set(seen.get() + 1))
+                                      .expect("Could not add timer");
+            dispatch(event_loop, 0);
+            assert_eq!(fired.get(), 0);
+            assert!(timer.update(Duration::from_millis(1)));
+            dispatch(event_loop, 1000);
+            assert_eq!(fired.get(), 1);
+            assert!(timer.update(Duration::from_millis(1)));
+            assert!(timer.cancel());
+            dispatch(event_loop, 20);
+            assert_eq!(fired.get(), 1);
+        })
+    }
+
+    #[test]
+    fn idle_source() {
+        with_event_loop(|event_loop| {
+            let ran = Rc::new(Cell::new(0));
+            let seen = ran.clone();
+            let idle = event_loop.add_idle(move |_| seen.set(seen.get() + 1))
+                                 .expect("Could not add idle callback");
+            assert!(idle.pending());
+            dispatch(event_loop, 0);
+            assert_eq!(ran.get(), 1);
+            assert!(!idle.pending());
+            drop(idle);
+            dispatch(event_loop, 0);
+            assert_eq!(ran.get(), 1);
+
+            let seen = ran.clone();
+            let cancelled = event_loop.add_idle(move |_| seen.set(seen.get() + 1))
+                                      .expect("Could not add idle callback");
+            drop(cancelled);
+            dispatch(event_loop, 0);
+            assert_eq!(ran.get(), 1);
+        })
+    }
+
+    #[test]
+    fn idle_source_outlives_event_loop() {
+        let ran = Rc::new(Cell::new(false));
+        let mut idle = None;
+        with_event_loop(|event_loop| {
+            let seen = ran.clone();
+            idle = event_loop.add_idle(move |_| seen.set(true));
+            assert!(idle.is_some());
+        });
+        let idle = idle.unwrap();
+        assert!(!idle.pending());
+        drop(idle);
+        assert!(!ran.get());
+        // The callback, and the `Rc` it captured, were freed with the handle.
+        assert_eq!(Rc::strong_count(&ran), 1);
+    }
+
+    #[test]
+    fn signal_source() {
+        with_event_loop(|event_loop| unsafe {
+            let received = Rc::new(Cell::new(0));
+            let seen = received.clone();
+            let source = event_loop.add_signal(libc::SIGUSR1, move |_, signum| {
+                                       assert_eq!(signum, libc::SIGUSR1);
+                                       seen.set(seen.get() + 1);
+                                   })
+                                   .expect("Could not add signal");
+            assert_eq!(libc::raise(libc::SIGUSR1), 0);
+            dispatch(event_loop, 100);
+            assert_eq!(received.get(), 1);
+            drop(source);
+        })
+    }
+
+    #[test]
+    fn sources_not_added_to_destroyed_event_loop() {
+        let mut destroyed = None;
+        with_event_loop(|event_loop| destroyed = Some(event_loop.clone()));
+        let event_loop = destroyed.unwrap();
+        assert!(event_loop.add_timer(|_| {}).is_none());
+        assert!(event_loop.add_idle(|_| {}).is_none());
+        assert!(event_loop.add_fd(0, FdMask::empty(), |_, _, _| {}).is_none());
+    }
+}
@@ -0,0 +1,98 @@
+//! Support for the gamma control protocol, which lets clients (e.g redshift
+//! or gammastep) set the gamma lookup tables of the outputs.
+//!
+//! Clients only change the gamma while they are connected, when one goes away
+//! the outputs it controlled get back the gamma ramps the compositor last
+//! set with `Output::set_gamma_lut`, or linear ramps if it never set any.
+//!
+//! wlroots doesn't announce new gamma controls, so a control is only tracked
+//! once its client first sends a request on it, e.g to set the gamma.
+
+use libc;
+use std::ptr;
+
+use wayland_sys::server::{wl_display as wl_server_display, WAYLAND_SERVER_HANDLE,
+                          signal::wl_signal_add};
+use wlroots_sys::{wl_display, wl_display_add_protocol_logger, wl_protocol_logger,
+                  wl_protocol_logger_destroy, wl_protocol_logger_message,
+                  wl_protocol_logger_type, wlr_gamma_control, wlr_gamma_control_manager,
+                  wlr_gamma_control_manager_create, wlr_gamma_control_manager_destroy};
+
+use types::output::restore_gamma;
+
+wayland_listener!(ControlDestroyListener, *mut wlr_gamma_control, [
+    control_destroy_listener => control_destroy_notify: |this: &mut ControlDestroyListener,
+                                                         _data: *mut libc::c_void,|
+    unsafe {
+        // NOTE
+        // Controls are destroyed when their client goes away, so this is
+        // where the client's gamma is taken back.
+        let control = this.data;
+        if !(*control).output.is_null() {
+            restore_gamma((*control).output);
+        }
+        (*control).data = ptr::null_mut();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*this.control_destroy_listener()).link as *mut _ as _);
+        Box::from_raw(this as *mut ControlDestroyListener);
+    };
+]);
+
+/// The gamma control global.
+///
+/// Enable it with `CompositorBuilder::gamma_control_manager`.
+pub struct GammaControlManager {
+    manager: *mut wlr_gamma_control_manager,
+    logger: *mut wl_protocol_logger
+}
+
+impl GammaControlManager {
+    pub(crate) unsafe fn new(display: *mut wl_server_display) -> Option<Self> {
+        let manager = wlr_gamma_control_manager_create(display as *mut wl_display);
+        if manager.is_null() {
+            return None
+        }
+        let logger = wl_display_add_protocol_logger(display as *mut wl_display,
+                                                    Some(track_controls),
+                                                    manager as *mut libc::c_void);
+        if logger.is_null() {
+            wlr_gamma_control_manager_destroy(manager);
+            return None
+        }
+        Some(GammaControlManager { manager,
+                                   logger })
+    }
+}
+
+impl Drop for GammaControlManager {
+    fn drop(&mut self) {
+        unsafe {
+            wl_protocol_logger_destroy(self.logger);
+            // NOTE
+            // This destroys the remaining controls, which restores their
+            // outputs' gamma and frees their listeners.
+            wlr_gamma_control_manager_destroy(self.manager);
+        }
+    }
+}
+
+/// Starts tracking the gamma control a request is sent on, if it isn't yet.
+unsafe extern "C" fn track_controls(data: *mut libc::c_void,
+                                    direction: wl_protocol_logger_type,
+                                    message: *const wl_protocol_logger_message) {
+    if direction != wl_protocol_logger_type::WL_PROTOCOL_LOGGER_REQUEST {
+        return
+    }
+    let manager = data as *mut wlr_gamma_control_manager;
+    let resource = (*message).resource;
+    wl_list_for_each!((*manager).controls, link, (control: wlr_gamma_control) => {
+        if (*control).resource == resource && (*control).data.is_null() {
+            // NOTE Freed in its notify function, when the control is destroyed.
+            let listener = Box::into_raw(ControlDestroyListener::new(control));
+            (*control).data = listener as *mut libc::c_void;
+            wl_signal_add(&mut (*control).events.destroy as *mut _ as _,
+                          (*listener).control_destroy_listener() as *mut _ as _);
+        }
+    });
+}
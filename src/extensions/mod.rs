@@ -1 +1,6 @@
+pub mod gamma_control;
+pub mod output_management;
+pub mod presentation;
+pub mod screenshooter;
 pub mod server_decoration;
+pub mod xdg_output;
@@ -0,0 +1,1071 @@
+//! Support for the wlr-output-management protocol, which lets clients such
+//! as kanshi or wlr-randr list the outputs and change how they are set up.
+//!
+//! Every output the compositor accepted in
+//! `OutputManagerHandler::output_added` is advertised as a head, along with
+//! the modes from `Output::modes`. Clients are told again whenever an output
+//! is enabled or disabled, or its mode, scale, transform or position in the
+//! layout changes.
+//!
+//! A client describes a configuration of every head, which it can test
+//! before applying it. Testing checks that each output advertises the mode
+//! it is asked for (custom modes are only accepted by outputs without any
+//! modes, e.g nested ones) and asks the `OutputManagementHandler`. Applying
+//! tests the configuration first, then changes the outputs one by one. If
+//! one of them can't be changed, the outputs that were changed already are
+//! put back as they were and the client is told the configuration failed.
+
+use libc;
+use std::{panic, ptr, cell::{Cell, RefCell}, ffi::{CStr, CString}, rc::Rc};
+
+use wayland_sys::common::{wl_fixed_from_double, wl_fixed_t, wl_fixed_to_double, wl_interface};
+use wayland_sys::server::{wl_client, wl_display, wl_global, wl_resource, WAYLAND_SERVER_HANDLE};
+use wayland_sys::server::signal::wl_signal_add;
+use wlroots_sys::{wl_output_transform, wlr_backend, wlr_output, wlr_output_layout,
+                  wlr_output_mode};
+use wlroots_sys::protocols::wlr_output_management::server::interfaces;
+
+use {Handle, ModeSetting, Origin, Output, OutputHandle, OutputLayoutHandle, OutputSettings,
+     Transform};
+use compositor::{compositor_handle, CompositorHandle};
+use utils::handle_unwind;
+
+/// The version of the protocol that is implemented.
+const VERSION: u32 = 2;
+
+// Events of `zwlr_output_manager_v1`.
+const MANAGER_HEAD: u32 = 0;
+const MANAGER_DONE: u32 = 1;
+const MANAGER_FINISHED: u32 = 2;
+
+// Events of `zwlr_output_head_v1`.
+const HEAD_NAME: u32 = 0;
+const HEAD_DESCRIPTION: u32 = 1;
+const HEAD_PHYSICAL_SIZE: u32 = 2;
+const HEAD_MODE: u32 = 3;
+const HEAD_ENABLED: u32 = 4;
+const HEAD_CURRENT_MODE: u32 = 5;
+const HEAD_POSITION: u32 = 6;
+const HEAD_TRANSFORM: u32 = 7;
+const HEAD_SCALE: u32 = 8;
+const HEAD_FINISHED: u32 = 9;
+const HEAD_MAKE: u32 = 10;
+const HEAD_MODEL: u32 = 11;
+const HEAD_SERIAL_NUMBER: u32 = 12;
+
+// Events of `zwlr_output_mode_v1`.
+const MODE_SIZE: u32 = 0;
+const MODE_REFRESH: u32 = 1;
+const MODE_PREFERRED: u32 = 2;
+const MODE_FINISHED: u32 = 3;
+
+// Events and errors of `zwlr_output_configuration_v1`.
+const CONFIGURATION_SUCCEEDED: u32 = 0;
+const CONFIGURATION_FAILED: u32 = 1;
+const CONFIGURATION_CANCELLED: u32 = 2;
+const ALREADY_CONFIGURED_HEAD: u32 = 1;
+const UNCONFIGURED_HEAD: u32 = 2;
+const ALREADY_USED: u32 = 3;
+
+// Errors of `zwlr_output_configuration_head_v1`.
+const ALREADY_SET: u32 = 1;
+const INVALID_MODE: u32 = 2;
+const INVALID_CUSTOM_MODE: u32 = 3;
+const INVALID_TRANSFORM: u32 = 4;
+const INVALID_SCALE: u32 = 5;
+
+/// `WL_OUTPUT_MODE_PREFERRED`, which the bindings don't have.
+const PREFERRED_FLAG: u32 = 0x2;
+
+/// Decides which output configurations clients can apply.
+pub trait OutputManagementHandler {
+    /// Called when a client tests or applies a configuration, once it's known
+    /// that every output advertises the mode it is asked for.
+    ///
+    /// Return `false` to refuse it, e.g because the outputs can't all be
+    /// driven at once. The client is then told the configuration failed.
+    fn allow_configuration(&mut self, CompositorHandle, &[HeadConfiguration]) -> bool {
+        true
+    }
+
+    /// Called after a configuration was applied, e.g to save it.
+    fn configuration_applied(&mut self, CompositorHandle, &[HeadConfiguration]) {}
+}
+
+impl OutputManagementHandler for () {}
+
+/// What a client asks for one output in a configuration.
+#[derive(Debug, Clone)]
+pub struct HeadConfiguration {
+    pub output: OutputHandle,
+    /// The new settings of the output.
+    ///
+    /// `enabled` is always set. The other settings are only set for enabled
+    /// outputs, and those that are `None` are left alone.
+    pub settings: OutputSettings
+}
+
+/// The wlr-output-management global.
+///
+/// Enable it with `CompositorBuilder::output_management`.
+pub struct OutputManagementManager {
+    global: *mut wl_global,
+    state: Box<StateRef>,
+    listener: Box<ManagementListener>
+}
+
+/// The state of the global, shared with the resources bound to it so it
+/// outlives the global if clients still use it.
+type StateRef = Rc<ManagementState>;
+
+struct ManagementState {
+    handler: RefCell<Box<OutputManagementHandler>>,
+    /// The `zwlr_output_manager_v1` resources that are sent updates.
+    managers: RefCell<Vec<*mut wl_resource>>,
+    /// The advertised outputs.
+    heads: RefCell<Vec<*mut Head>>,
+    /// The serial of the last `done` event.
+    serial: Cell<u32>,
+    /// Set while a configuration is applied, so the heads are only sent
+    /// their new state once it's done.
+    applying: Cell<bool>
+}
+
+wayland_listener!(ManagementListener, (StateRef, *mut wlr_output_layout), [
+    new_output_listener => new_output_notify: |this: &mut ManagementListener,
+                                               data: *mut libc::c_void,|
+    unsafe {
+        add_head(&this.data.0, data as *mut wlr_output);
+    };
+    layout_change_listener => layout_change_notify: |this: &mut ManagementListener,
+                                                     _data: *mut libc::c_void,|
+    unsafe {
+        update_heads(&this.data.0);
+    };
+    layout_destroy_listener => layout_destroy_notify: |this: &mut ManagementListener,
+                                                       _data: *mut libc::c_void,|
+    unsafe {
+        this.detach_layout();
+    };
+]);
+
+/// An advertised output.
+struct HeadState {
+    output: *mut wlr_output,
+    /// The `zwlr_output_head_v1` resources of the output, one for each
+    /// manager resource that was bound while it existed.
+    resources: Vec<*mut wl_resource>,
+    /// The state of the global, which owns the head.
+    state: *const ManagementState
+}
+
+wayland_listener!(Head, HeadState, [
+    destroy_listener => destroy_notify: |this: &mut Head, _data: *mut libc::c_void,| unsafe {
+        remove_head(&*this.data.state, this);
+    };
+    mode_listener => mode_notify: |this: &mut Head, _data: *mut libc::c_void,| unsafe {
+        update_heads(&*this.data.state);
+    };
+    enable_listener => enable_notify: |this: &mut Head, _data: *mut libc::c_void,| unsafe {
+        update_heads(&*this.data.state);
+    };
+    scale_listener => scale_notify: |this: &mut Head, _data: *mut libc::c_void,| unsafe {
+        update_heads(&*this.data.state);
+    };
+    transform_listener => transform_notify: |this: &mut Head, _data: *mut libc::c_void,| unsafe {
+        update_heads(&*this.data.state);
+    };
+]);
+
+/// The user data of a `zwlr_output_head_v1` resource.
+struct HeadResource {
+    /// The head, null once its output is destroyed.
+    head: *mut Head,
+    /// The modes of the output, along with their `zwlr_output_mode_v1`
+    /// resources, whose user data is the mode until it's finished.
+    modes: Vec<(*mut wlr_output_mode, *mut wl_resource)>
+}
+
+/// The user data of a `zwlr_output_configuration_v1` resource.
+struct Configuration {
+    state: StateRef,
+    /// The serial the client based the configuration on.
+    serial: u32,
+    /// Set once the configuration was tested or applied.
+    used: bool,
+    heads: Vec<PendingHead>
+}
+
+/// The new settings of an output in a configuration.
+struct PendingHead {
+    output: *mut wlr_output,
+    settings: Rc<RefCell<OutputSettings>>
+}
+
+/// The user data of a `zwlr_output_configuration_head_v1` resource, which
+/// sets the settings of an enabled output.
+struct ConfigurationHead {
+    state: StateRef,
+    /// The output, null if its head was finished already.
+    output: *mut wlr_output,
+    settings: Rc<RefCell<OutputSettings>>
+}
+
+/// The requests of `zwlr_output_manager_v1`, in the order of the protocol.
+#[repr(C)]
+struct ManagerImpl {
+    create_configuration: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, u32),
+    stop: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+/// The requests of `zwlr_output_configuration_v1`, in the order of the
+/// protocol.
+#[repr(C)]
+struct ConfigurationImpl {
+    enable_head: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, u32, *mut wl_resource),
+    disable_head: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource),
+    apply: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    test: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource)
+}
+
+/// The requests of `zwlr_output_configuration_head_v1`, in the order of the
+/// protocol.
+#[repr(C)]
+struct ConfigurationHeadImpl {
+    set_mode: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource),
+    set_custom_mode: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32, i32),
+    set_position: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32, i32),
+    set_transform: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, i32),
+    set_scale: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, wl_fixed_t)
+}
+
+static MANAGER_IMPL: ManagerImpl = ManagerImpl { create_configuration,
+                                                 stop };
+
+static CONFIGURATION_IMPL: ConfigurationImpl = ConfigurationImpl { enable_head,
+                                                                   disable_head,
+                                                                   apply: apply_configuration,
+                                                                   test: test_configuration,
+                                                                   destroy:
+                                                                       destroy_configuration };
+
+static CONFIGURATION_HEAD_IMPL: ConfigurationHeadImpl =
+    ConfigurationHeadImpl { set_mode,
+                            set_custom_mode,
+                            set_position,
+                            set_transform,
+                            set_scale };
+
+impl OutputManagementManager {
+    pub(crate) unsafe fn new(display: *mut wl_display,
+                             backend: *mut wlr_backend,
+                             layout: OutputLayoutHandle,
+                             handler: Box<OutputManagementHandler>)
+                             -> Option<Self> {
+        if !layout.is_alive() {
+            return None
+        }
+        let state: Box<StateRef> =
+            Box::new(Rc::new(ManagementState { handler: RefCell::new(handler),
+                                               managers: RefCell::new(Vec::new()),
+                                               heads: RefCell::new(Vec::new()),
+                                               serial: Cell::new(0),
+                                               applying: Cell::new(false) }));
+        let global = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                   wl_global_create,
+                                   display,
+                                   &interfaces::zwlr_output_manager_v1_interface as *const _
+                                   as *const wl_interface,
+                                   VERSION as _,
+                                   &*state as *const StateRef as *mut libc::c_void,
+                                   bind);
+        if global.is_null() {
+            return None
+        }
+        let layout = layout.as_ptr();
+        let mut listener = ManagementListener::new(((*state).clone(), layout));
+        wl_signal_add(&mut (*backend).events.new_output as *mut _ as _,
+                      listener.new_output_listener() as *mut _ as _);
+        wl_signal_add(&mut (*layout).events.change as *mut _ as _,
+                      listener.layout_change_listener() as *mut _ as _);
+        wl_signal_add(&mut (*layout).events.destroy as *mut _ as _,
+                      listener.layout_destroy_listener() as *mut _ as _);
+        Some(OutputManagementManager { global,
+                                       state,
+                                       listener })
+    }
+}
+
+impl Drop for OutputManagementManager {
+    fn drop(&mut self) {
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_global_destroy, self.global);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.listener.new_output_listener()).link as *mut _ as _);
+            self.listener.detach_layout();
+            // NOTE
+            // The heads listen to the outputs, which outlive the global.
+            let heads = self.state.heads.replace(Vec::new());
+            for head in heads {
+                free_head(head)
+            }
+        }
+    }
+}
+
+impl ManagementState {
+    /// Determines if the output is advertised.
+    unsafe fn has_output(&self, output: *mut wlr_output) -> bool {
+        !output.is_null() && self.heads.borrow().iter().any(|&head| (*head).data.output == output)
+    }
+}
+
+impl ManagementListener {
+    /// Stops listening to the layout, which is destroyed or outlives the
+    /// global.
+    unsafe fn detach_layout(&mut self) {
+        if self.data.1.is_null() {
+            return
+        }
+        self.data.1 = ptr::null_mut();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.layout_change_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.layout_destroy_listener()).link as *mut _ as _);
+    }
+}
+
+/// Starts advertising a new output.
+unsafe fn add_head(state: &StateRef, output: *mut wlr_output) {
+    // NOTE
+    // This runs after the output manager, so outputs the compositor refused
+    // have no data anymore, and can't be set up.
+    if (*output).data.is_null() {
+        return
+    }
+    let mut head = Head::new(HeadState { output,
+                                         resources: Vec::new(),
+                                         state: &**state });
+    wl_signal_add(&mut (*output).events.destroy as *mut _ as _,
+                  head.destroy_listener() as *mut _ as _);
+    wl_signal_add(&mut (*output).events.mode as *mut _ as _,
+                  head.mode_listener() as *mut _ as _);
+    wl_signal_add(&mut (*output).events.enable as *mut _ as _,
+                  head.enable_listener() as *mut _ as _);
+    wl_signal_add(&mut (*output).events.scale as *mut _ as _,
+                  head.scale_listener() as *mut _ as _);
+    wl_signal_add(&mut (*output).events.transform as *mut _ as _,
+                  head.transform_listener() as *mut _ as _);
+    let head = Box::into_raw(head);
+    state.heads.borrow_mut().push(head);
+    for &manager in state.managers.borrow().iter() {
+        send_head(manager, head);
+    }
+    send_done(state);
+}
+
+/// Tells the clients the output is gone, and frees its head.
+unsafe fn remove_head(state: &ManagementState, head: *mut Head) {
+    state.heads.borrow_mut().retain(|&other| other != head);
+    for &resource in &(*head).data.resources {
+        let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+        for &(_, mode) in &(*(data as *mut HeadResource)).modes {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, mode, MODE_FINISHED);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_resource_set_user_data,
+                          mode,
+                          ptr::null_mut());
+        }
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, HEAD_FINISHED);
+    }
+    free_head(head);
+    send_done(state);
+}
+
+/// Stops listening to the output of the head and frees it, which makes its
+/// resources inert.
+unsafe fn free_head(head: *mut Head) {
+    for &resource in &(*head).data.resources {
+        let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+        (*(data as *mut HeadResource)).head = ptr::null_mut();
+    }
+    let mut head = Box::from_raw(head);
+    for link in &[&mut (*head.destroy_listener()).link as *mut _,
+                  &mut (*head.mode_listener()).link as *mut _,
+                  &mut (*head.enable_listener()).link as *mut _,
+                  &mut (*head.scale_listener()).link as *mut _,
+                  &mut (*head.transform_listener()).link as *mut _]
+    {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_list_remove, *link as _);
+    }
+}
+
+/// Sends the current state of every output, unless a configuration is
+/// being applied.
+unsafe fn update_heads(state: &ManagementState) {
+    if state.applying.get() {
+        return
+    }
+    for &head in state.heads.borrow().iter() {
+        for &resource in &(*head).data.resources {
+            send_head_state(resource);
+        }
+    }
+    send_done(state);
+}
+
+/// Tells the clients that everything about the new state was sent.
+unsafe fn send_done(state: &ManagementState) {
+    let serial = state.serial.get().wrapping_add(1);
+    state.serial.set(serial);
+    for &manager in state.managers.borrow().iter() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, manager, MANAGER_DONE, serial);
+    }
+}
+
+/// Advertises the output of the head to the client of the manager resource.
+unsafe fn send_head(manager: *mut wl_resource, head: *mut Head) {
+    let output = (*head).data.output;
+    let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, manager);
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, manager);
+    let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                 wl_resource_create,
+                                 client,
+                                 &interfaces::zwlr_output_head_v1_interface as *const _
+                                 as *const wl_interface,
+                                 version,
+                                 0);
+    if resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    // NOTE Freed in `head_resource_destroy`.
+    let data = Box::into_raw(Box::new(HeadResource { head,
+                                                     modes: Vec::new() }));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  resource,
+                  ptr::null(),
+                  data as *mut libc::c_void,
+                  Some(head_resource_destroy));
+    (*head).data.resources.push(resource);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, manager, MANAGER_HEAD, resource);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  HEAD_NAME,
+                  (*output).name.as_ptr());
+    let field = |field: &[libc::c_char]| CStr::from_ptr(field.as_ptr()).to_string_lossy();
+    let description = format!("{} {} ({})",
+                              field(&(*output).make[..]),
+                              field(&(*output).model[..]),
+                              field(&(*output).name[..]));
+    let description = CString::new(description).unwrap_or_default();
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  HEAD_DESCRIPTION,
+                  description.as_ptr());
+    if (*output).phys_width > 0 && (*output).phys_height > 0 {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      resource,
+                      HEAD_PHYSICAL_SIZE,
+                      (*output).phys_width,
+                      (*output).phys_height);
+    }
+    wl_list_for_each!((*output).modes, link, (mode: wlr_output_mode) => {
+        let mode_resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                          wl_resource_create,
+                                          client,
+                                          &interfaces::zwlr_output_mode_v1_interface as *const _
+                                          as *const wl_interface,
+                                          version,
+                                          0);
+        if mode_resource.is_null() {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        } else {
+            send_mode(resource, mode_resource, mode);
+            (*data).modes.push((mode, mode_resource));
+        }
+    });
+    if version >= 2 {
+        for &(opcode, field) in &[(HEAD_MAKE, &(*output).make[..]),
+                                  (HEAD_MODEL, &(*output).model[..]),
+                                  (HEAD_SERIAL_NUMBER, &(*output).serial[..])]
+        {
+            if field[0] != 0 {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              resource,
+                              opcode,
+                              field.as_ptr());
+            }
+        }
+    }
+    send_head_state(resource);
+}
+
+/// Advertises a mode of the output of a head resource.
+unsafe fn send_mode(head: *mut wl_resource,
+                    resource: *mut wl_resource,
+                    mode: *mut wlr_output_mode) {
+    // NOTE
+    // Modes have no requests in this version, the user data is only used to
+    // find the mode a configuration asks for.
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  resource,
+                  ptr::null(),
+                  mode as *mut libc::c_void,
+                  None);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, head, HEAD_MODE, resource);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  MODE_SIZE,
+                  (*mode).width,
+                  (*mode).height);
+    if (*mode).refresh > 0 {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      resource,
+                      MODE_REFRESH,
+                      (*mode).refresh);
+    }
+    if (*mode).flags & PREFERRED_FLAG != 0 {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, MODE_PREFERRED);
+    }
+}
+
+/// Sends the properties of the output that can be configured.
+unsafe fn send_head_state(resource: *mut wl_resource) {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let data = &*(data as *mut HeadResource);
+    if data.head.is_null() {
+        return
+    }
+    let output = (*data.head).data.output;
+    let enabled = (*output).enabled;
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  HEAD_ENABLED,
+                  enabled as i32);
+    if !enabled {
+        return
+    }
+    // Custom modes aren't advertised, so they can't be sent.
+    let current = (*output).current_mode;
+    if let Some(&(_, mode)) = data.modes.iter().find(|&&(mode, _)| mode == current) {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      resource,
+                      HEAD_CURRENT_MODE,
+                      mode);
+    }
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  HEAD_POSITION,
+                  (*output).lx,
+                  (*output).ly);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  HEAD_TRANSFORM,
+                  (*output).transform as i32);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  HEAD_SCALE,
+                  wl_fixed_from_double((*output).scale as f64));
+}
+
+/// Tests the configuration: every output has to advertise the mode it is
+/// asked for, and the handler has to allow it.
+unsafe fn test(state: &ManagementState, heads: &[HeadConfiguration]) -> bool {
+    let modes_exist = heads.iter().all(|head| {
+        let mode = match head.settings.mode {
+            Some(mode) => mode,
+            None => return true
+        };
+        head.output
+            .run(|output| output.modes().is_empty() || mode.find(output).is_some())
+            .unwrap_or(false)
+    });
+    if !modes_exist {
+        return false
+    }
+    let compositor = compositor_handle().unwrap_or_else(CompositorHandle::new);
+    let mut allowed = false;
+    handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        allowed = state.handler.borrow_mut().allow_configuration(compositor, heads)
+    })));
+    allowed
+}
+
+/// Tests the configuration and applies it.
+///
+/// If an output can't be changed, every output that was changed is put back
+/// as it was.
+unsafe fn apply(state: &ManagementState, heads: &[HeadConfiguration]) -> bool {
+    if !test(state, heads) {
+        return false
+    }
+    state.applying.set(true);
+    let mut previous = Vec::new();
+    let mut applied = true;
+    // NOTE
+    // Outputs are disabled first, in case they hold resources (e.g CRTCs)
+    // the outputs being enabled need.
+    let disabled = heads.iter().filter(|head| head.settings.enabled == Some(false));
+    let enabled = heads.iter().filter(|head| head.settings.enabled != Some(false));
+    for head in disabled.chain(enabled) {
+        let result = head.output.run(|output| {
+                                         previous.push((head.output.clone(),
+                                                        current_settings(output)));
+                                         head.settings.apply(output)
+                                     });
+        match result {
+            Ok(true) => {}
+            _ => {
+                applied = false;
+                break
+            }
+        }
+    }
+    if !applied {
+        wlr_log!(L_DEBUG, "Could not apply output configuration, rolling back");
+        for &(ref output, ref settings) in previous.iter().rev() {
+            output.run(|output| settings.apply(output)).ok();
+        }
+    }
+    state.applying.set(false);
+    update_heads(state);
+    if applied {
+        let compositor = compositor_handle().unwrap_or_else(CompositorHandle::new);
+        handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            state.handler.borrow_mut().configuration_applied(compositor, heads)
+        })));
+    }
+    applied
+}
+
+/// Gets the settings that put the output back as it is now.
+fn current_settings(output: &Output) -> OutputSettings {
+    let mode = match output.current_mode() {
+        Some(mode) => {
+            let (width, height) = mode.dimensions();
+            ModeSetting { width,
+                          height,
+                          refresh: Some(mode.refresh()) }
+        }
+        None => {
+            let (width, height) = output.size();
+            ModeSetting { width,
+                          height,
+                          refresh: Some(output.refresh_rate()) }
+        }
+    };
+    let (x, y) = output.layout_space_pos();
+    OutputSettings { enabled: Some(output.enabled()),
+                     mode: if mode.width > 0 && mode.height > 0 { Some(mode) } else { None },
+                     position: Some(Origin::new(x, y)),
+                     scale: Some(output.scale()),
+                     transform: Some(output.get_transform()) }
+}
+
+/// Converts a `wl_output.transform` sent by a client.
+fn transform_from_int(transform: i32) -> Option<Transform> {
+    use self::wl_output_transform::*;
+    Some(match transform {
+             0 => WL_OUTPUT_TRANSFORM_NORMAL,
+             1 => WL_OUTPUT_TRANSFORM_90,
+             2 => WL_OUTPUT_TRANSFORM_180,
+             3 => WL_OUTPUT_TRANSFORM_270,
+             4 => WL_OUTPUT_TRANSFORM_FLIPPED,
+             5 => WL_OUTPUT_TRANSFORM_FLIPPED_90,
+             6 => WL_OUTPUT_TRANSFORM_FLIPPED_180,
+             7 => WL_OUTPUT_TRANSFORM_FLIPPED_270,
+             _ => return None
+         })
+}
+
+/// Posts a protocol error, which disconnects the client.
+///
+/// `message` has to be nul terminated.
+unsafe fn post_error(resource: *mut wl_resource, code: u32, message: &'static [u8]) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_error,
+                  resource,
+                  code,
+                  message.as_ptr() as *const libc::c_char);
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, data: *mut libc::c_void, version: u32, id: u32) {
+    let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                 wl_resource_create,
+                                 client,
+                                 &interfaces::zwlr_output_manager_v1_interface as *const _
+                                 as *const wl_interface,
+                                 version as _,
+                                 id);
+    if resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    let state = (*(data as *const StateRef)).clone();
+    // NOTE Freed in `manager_destroy`.
+    let user_data = Box::into_raw(Box::new(state.clone()));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  resource,
+                  &MANAGER_IMPL as *const _ as *const libc::c_void,
+                  user_data as *mut libc::c_void,
+                  Some(manager_destroy));
+    state.managers.borrow_mut().push(resource);
+    for &head in state.heads.borrow().iter() {
+        send_head(resource, head);
+    }
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  MANAGER_DONE,
+                  state.serial.get());
+}
+
+unsafe extern "C" fn manager_destroy(resource: *mut wl_resource) {
+    let state = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let state = Box::from_raw(state as *mut StateRef);
+    state.managers.borrow_mut().retain(|&manager| manager != resource);
+}
+
+unsafe extern "C" fn head_resource_destroy(resource: *mut wl_resource) {
+    // NOTE
+    // Heads and modes have no destructor in this version, so they all go
+    // away with their client and the modes can't be used here anymore.
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let data = Box::from_raw(data as *mut HeadResource);
+    if !data.head.is_null() {
+        (*data.head).data.resources.retain(|&other| other != resource);
+    }
+}
+
+unsafe extern "C" fn create_configuration(client: *mut wl_client,
+                                          resource: *mut wl_resource,
+                                          id: u32,
+                                          serial: u32) {
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource);
+    let configuration = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                      wl_resource_create,
+                                      client,
+                                      &interfaces::zwlr_output_configuration_v1_interface
+                                      as *const _ as *const wl_interface,
+                                      version,
+                                      id);
+    if configuration.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    let state = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let state = (*(state as *const StateRef)).clone();
+    // NOTE Freed in `configuration_destroy`.
+    let data = Box::into_raw(Box::new(Configuration { state,
+                                                      serial,
+                                                      used: false,
+                                                      heads: Vec::new() }));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  configuration,
+                  &CONFIGURATION_IMPL as *const _ as *const libc::c_void,
+                  data as *mut libc::c_void,
+                  Some(configuration_destroy));
+}
+
+unsafe extern "C" fn stop(_client: *mut wl_client, resource: *mut wl_resource) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, MANAGER_FINISHED);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, resource);
+}
+
+unsafe extern "C" fn configuration_destroy(resource: *mut wl_resource) {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    Box::from_raw(data as *mut Configuration);
+}
+
+/// Gets the output of a head resource, or null if the head was finished.
+unsafe fn head_output(head: *mut wl_resource) -> *mut wlr_output {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, head);
+    let head = (*(data as *mut HeadResource)).head;
+    if head.is_null() {
+        ptr::null_mut()
+    } else {
+        (*head).data.output
+    }
+}
+
+/// Adds the settings of an output to a configuration.
+///
+/// Finished heads are ignored, as the configuration will be cancelled.
+unsafe fn configure(resource: *mut wl_resource,
+                    output: *mut wlr_output,
+                    settings: Rc<RefCell<OutputSettings>>) {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let configuration = &mut *(data as *mut Configuration);
+    if configuration.used {
+        return post_error(resource, ALREADY_USED, b"configuration was already used\0")
+    }
+    if output.is_null() {
+        return
+    }
+    if configuration.heads.iter().any(|head| head.output == output) {
+        return post_error(resource,
+                          ALREADY_CONFIGURED_HEAD,
+                          b"head was already configured\0")
+    }
+    configuration.heads.push(PendingHead { output, settings })
+}
+
+unsafe extern "C" fn enable_head(client: *mut wl_client,
+                                 resource: *mut wl_resource,
+                                 id: u32,
+                                 head: *mut wl_resource) {
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource);
+    let configuration_head = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                           wl_resource_create,
+                                           client,
+                                           &interfaces::zwlr_output_configuration_head_v1_interface
+                                           as *const _ as *const wl_interface,
+                                           version,
+                                           id);
+    if configuration_head.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let state = (*(data as *mut Configuration)).state.clone();
+    let output = head_output(head);
+    let settings = Rc::new(RefCell::new(OutputSettings { enabled: Some(true),
+                                                         ..OutputSettings::default() }));
+    // NOTE Freed in `configuration_head_destroy`.
+    let data = Box::into_raw(Box::new(ConfigurationHead { state,
+                                                          output,
+                                                          settings: settings.clone() }));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  configuration_head,
+                  &CONFIGURATION_HEAD_IMPL as *const _ as *const libc::c_void,
+                  data as *mut libc::c_void,
+                  Some(configuration_head_destroy));
+    configure(resource, output, settings);
+}
+
+unsafe extern "C" fn disable_head(_client: *mut wl_client,
+                                  resource: *mut wl_resource,
+                                  head: *mut wl_resource) {
+    let settings = OutputSettings { enabled: Some(false),
+                                    ..OutputSettings::default() };
+    configure(resource, head_output(head), Rc::new(RefCell::new(settings)));
+}
+
+/// Tests or applies a configuration, and tells the client how it went.
+unsafe fn finish_configuration(resource: *mut wl_resource, only_test: bool) {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let configuration = &mut *(data as *mut Configuration);
+    if configuration.used {
+        return post_error(resource, ALREADY_USED, b"configuration was already used\0")
+    }
+    configuration.used = true;
+    let state = configuration.state.clone();
+    // NOTE
+    // The serial changes whenever an output is added or removed, so the
+    // outputs of a configuration with the current serial are all alive.
+    if configuration.serial != state.serial.get() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      resource,
+                      CONFIGURATION_CANCELLED);
+        return
+    }
+    let outputs: Vec<_> = state.heads.borrow().iter().map(|&head| (*head).data.output).collect();
+    let configured = |output| configuration.heads.iter().any(|head| head.output == output);
+    if !outputs.into_iter().all(configured) {
+        return post_error(resource, UNCONFIGURED_HEAD, b"not every head was configured\0")
+    }
+    let heads: Vec<_> = configuration.heads
+                                     .iter()
+                                     .map(|head| {
+                                              let output = OutputHandle::from_ptr(head.output);
+                                              let settings = head.settings.borrow().clone();
+                                              HeadConfiguration { output, settings }
+                                          })
+                                     .collect();
+    let succeeded = if only_test {
+        test(&state, &heads)
+    } else {
+        apply(&state, &heads)
+    };
+    let event = if succeeded {
+        CONFIGURATION_SUCCEEDED
+    } else {
+        CONFIGURATION_FAILED
+    };
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, event);
+}
+
+unsafe extern "C" fn apply_configuration(_client: *mut wl_client, resource: *mut wl_resource) {
+    finish_configuration(resource, false)
+}
+
+unsafe extern "C" fn test_configuration(_client: *mut wl_client, resource: *mut wl_resource) {
+    finish_configuration(resource, true)
+}
+
+unsafe extern "C" fn destroy_configuration(_client: *mut wl_client, resource: *mut wl_resource) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, resource);
+}
+
+unsafe extern "C" fn configuration_head_destroy(resource: *mut wl_resource) {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    Box::from_raw(data as *mut ConfigurationHead);
+}
+
+/// Gets the user data of a `zwlr_output_configuration_head_v1` resource,
+/// unless its output is gone, in which case the request is ignored.
+unsafe fn configuration_head<'a>(resource: *mut wl_resource) -> Option<&'a mut ConfigurationHead> {
+    let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let head = &mut *(data as *mut ConfigurationHead);
+    if head.state.has_output(head.output) {
+        Some(head)
+    } else {
+        None
+    }
+}
+
+unsafe extern "C" fn set_mode(_client: *mut wl_client,
+                              resource: *mut wl_resource,
+                              mode_resource: *mut wl_resource) {
+    let head = match configuration_head(resource) {
+        Some(head) => head,
+        None => return
+    };
+    // Finished modes are ignored too.
+    let mode = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, mode_resource)
+               as *mut wlr_output_mode;
+    if mode.is_null() {
+        return
+    }
+    let mut settings = head.settings.borrow_mut();
+    if settings.mode.is_some() {
+        return post_error(resource, ALREADY_SET, b"mode was already set\0")
+    }
+    let mut found = false;
+    wl_list_for_each!((*head.output).modes, link, (other: wlr_output_mode) => {
+        found = found || other == mode;
+    });
+    if !found {
+        return post_error(resource, INVALID_MODE, b"mode doesn't belong to the head\0")
+    }
+    settings.mode = Some(ModeSetting { width: (*mode).width,
+                                       height: (*mode).height,
+                                       refresh: Some((*mode).refresh) });
+}
+
+unsafe extern "C" fn set_custom_mode(_client: *mut wl_client,
+                                     resource: *mut wl_resource,
+                                     width: i32,
+                                     height: i32,
+                                     refresh: i32) {
+    let head = match configuration_head(resource) {
+        Some(head) => head,
+        None => return
+    };
+    let mut settings = head.settings.borrow_mut();
+    if settings.mode.is_some() {
+        return post_error(resource, ALREADY_SET, b"mode was already set\0")
+    }
+    if width <= 0 || height <= 0 || refresh < 0 {
+        return post_error(resource, INVALID_CUSTOM_MODE, b"custom mode is invalid\0")
+    }
+    // A refresh rate of 0 lets the output pick one.
+    settings.mode = Some(ModeSetting { width,
+                                       height,
+                                       refresh: if refresh > 0 { Some(refresh) } else { None } });
+}
+
+unsafe extern "C" fn set_position(_client: *mut wl_client,
+                                  resource: *mut wl_resource,
+                                  x: i32,
+                                  y: i32) {
+    let head = match configuration_head(resource) {
+        Some(head) => head,
+        None => return
+    };
+    let mut settings = head.settings.borrow_mut();
+    if settings.position.is_some() {
+        return post_error(resource, ALREADY_SET, b"position was already set\0")
+    }
+    settings.position = Some(Origin::new(x, y));
+}
+
+unsafe extern "C" fn set_transform(_client: *mut wl_client,
+                                   resource: *mut wl_resource,
+                                   transform: i32) {
+    let head = match configuration_head(resource) {
+        Some(head) => head,
+        None => return
+    };
+    let mut settings = head.settings.borrow_mut();
+    if settings.transform.is_some() {
+        return post_error(resource, ALREADY_SET, b"transform was already set\0")
+    }
+    match transform_from_int(transform) {
+        Some(transform) => settings.transform = Some(transform),
+        None => post_error(resource, INVALID_TRANSFORM, b"transform is not a wl_output.transform\0")
+    }
+}
+
+unsafe extern "C" fn set_scale(_client: *mut wl_client,
+                               resource: *mut wl_resource,
+                               scale: wl_fixed_t) {
+    let head = match configuration_head(resource) {
+        Some(head) => head,
+        None => return
+    };
+    let mut settings = head.settings.borrow_mut();
+    if settings.scale.is_some() {
+        return post_error(resource, ALREADY_SET, b"scale was already set\0")
+    }
+    let scale = wl_fixed_to_double(scale);
+    if !(scale > 0.0) {
+        return post_error(resource, INVALID_SCALE, b"scale is not positive\0")
+    }
+    settings.scale = Some(scale as f32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::wl_output_transform::*;
+
+    #[test]
+    fn transforms_from_protocol() {
+        let transforms = [WL_OUTPUT_TRANSFORM_NORMAL,
+                          WL_OUTPUT_TRANSFORM_90,
+                          WL_OUTPUT_TRANSFORM_180,
+                          WL_OUTPUT_TRANSFORM_270,
+                          WL_OUTPUT_TRANSFORM_FLIPPED,
+                          WL_OUTPUT_TRANSFORM_FLIPPED_90,
+                          WL_OUTPUT_TRANSFORM_FLIPPED_180,
+                          WL_OUTPUT_TRANSFORM_FLIPPED_270];
+        for &transform in &transforms {
+            assert_eq!(transform_from_int(transform as i32), Some(transform));
+        }
+        assert_eq!(transform_from_int(-1), None);
+        assert_eq!(transform_from_int(8), None);
+    }
+}
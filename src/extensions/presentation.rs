@@ -0,0 +1,355 @@
+//! Support for the presentation-time protocol, which tells clients when
+//! their content was shown.
+//!
+//! A client asks for feedback about the next commit of a surface. Once that
+//! commit has been drawn on an output, the client is told when the output's
+//! buffers were swapped, along with the output's refresh interval. The swap
+//! happens just before the frame is shown, but it isn't the time of the
+//! vertical blank, so the feedback doesn't claim to be vsync or hardware
+//! accurate. If the commit is replaced by another one before it is drawn, or
+//! the surface or output goes away first, the client is told the content was
+//! discarded.
+//!
+//! Surfaces drawn with the surface functions of `Renderer` (e.g
+//! `render_surface_tree`, which `Scene` uses too) are marked as drawn
+//! automatically. Compositors that draw the textures of surfaces themselves
+//! call `Presentation::surface_sampled`.
+//!
+//! Nothing the software renderer draws reaches the outputs, so feedback
+//! about surfaces it draws is always discarded.
+
+use libc;
+use std::{cmp, ptr, cell::RefCell, rc::Rc, u32};
+
+use wayland_sys::common::{wl_interface, wl_list};
+use wayland_sys::server::{wl_client, wl_display, wl_global, wl_resource, WAYLAND_SERVER_HANDLE};
+use wayland_sys::server::signal::wl_signal_add;
+use wlroots_sys::{wlr_output, wlr_surface};
+use wlroots_sys::protocols::presentation_time::server::interfaces;
+
+use {Output, Surface};
+use compositor::running_compositor;
+use types::output::refresh_interval;
+use utils::current_time;
+
+/// `wp_presentation.clock_id`
+const CLOCK_ID: u32 = 0;
+/// `wp_presentation_feedback.sync_output`
+const SYNC_OUTPUT: u32 = 0;
+/// `wp_presentation_feedback.presented`
+const PRESENTED: u32 = 1;
+/// `wp_presentation_feedback.discarded`
+const DISCARDED: u32 = 2;
+
+/// The presentation-time global.
+///
+/// Enable it with `CompositorBuilder::presentation`.
+pub struct Presentation {
+    global: *mut wl_global,
+    feedbacks: Box<Feedbacks>
+}
+
+/// The feedback waiting for its surface to be drawn, shared with the
+/// resources bound to the global.
+type Feedbacks = Rc<RefCell<Vec<*mut PendingFeedback>>>;
+
+/// The requests of `wp_presentation`, in the order of the protocol.
+#[repr(C)]
+struct PresentationImpl {
+    destroy: unsafe extern "C" fn(*mut wl_client, *mut wl_resource),
+    feedback: unsafe extern "C" fn(*mut wl_client, *mut wl_resource, *mut wl_resource, u32)
+}
+
+static PRESENTATION_IMPL: PresentationImpl = PresentationImpl { destroy, feedback };
+
+/// Feedback a client asked for about the next commit of a surface.
+struct Feedback {
+    /// The `wp_presentation_feedback` resource, which owns this feedback.
+    resource: *mut wl_resource,
+    /// The surface, null once it is destroyed after being drawn.
+    surface: *mut wlr_surface,
+    /// Whether the commit the feedback is about happened.
+    committed: bool,
+    /// The output the commit was drawn on, null until it is drawn.
+    output: *mut wlr_output,
+    feedbacks: Feedbacks
+}
+
+wayland_listener!(PendingFeedback, Feedback, [
+    commit_listener => commit_notify: |this: &mut PendingFeedback, _data: *mut libc::c_void,|
+    unsafe {
+        this.commit();
+    };
+    surface_destroy_listener => surface_destroy_notify: |this: &mut PendingFeedback,
+                                                         _data: *mut libc::c_void,|
+    unsafe {
+        if this.data.output.is_null() {
+            this.discard();
+        } else {
+            // It was drawn already, so it is shown anyway.
+            this.detach_surface();
+        }
+    };
+    swap_buffers_listener => swap_buffers_notify: |this: &mut PendingFeedback,
+                                                   _data: *mut libc::c_void,|
+    unsafe {
+        this.present();
+    };
+    output_destroy_listener => output_destroy_notify: |this: &mut PendingFeedback,
+                                                       _data: *mut libc::c_void,|
+    unsafe {
+        this.discard();
+    };
+]);
+
+impl Presentation {
+    pub(crate) unsafe fn new(display: *mut wl_display) -> Option<Self> {
+        let feedbacks: Box<Feedbacks> = Box::new(Rc::new(RefCell::new(Vec::new())));
+        let global = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                   wl_global_create,
+                                   display,
+                                   &interfaces::wp_presentation_interface as *const _
+                                   as *const wl_interface,
+                                   1,
+                                   &*feedbacks as *const Feedbacks as *mut libc::c_void,
+                                   bind);
+        if global.is_null() {
+            return None
+        }
+        Some(Presentation { global, feedbacks })
+    }
+
+    /// Marks the current content of the surface as drawn on the output.
+    ///
+    /// The clients that asked for feedback about it are told it was shown
+    /// when the output's buffers are swapped. The surface functions of
+    /// `Renderer` do this already, so this is only needed when drawing the
+    /// texture of a surface directly.
+    pub fn surface_sampled(&self, surface: &Surface, output: &Output) {
+        unsafe { self.sampled(surface.as_ptr(), output.as_ptr()) }
+    }
+
+    unsafe fn sampled(&self, surface: *mut wlr_surface, output: *mut wlr_output) {
+        for &feedback in self.feedbacks.borrow().iter() {
+            if (*feedback).data.surface == surface {
+                (*feedback).sample(output)
+            }
+        }
+    }
+}
+
+impl Drop for Presentation {
+    fn drop(&mut self) {
+        unsafe { ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_global_destroy, self.global) }
+    }
+}
+
+/// Marks the current content of the surface as drawn on the output, if the
+/// compositor running on this thread has the presentation-time global.
+///
+/// Surfaces are drawn while the compositor is borrowed, so it is reached
+/// through its pointer.
+pub(crate) unsafe fn surface_sampled(surface: *mut wlr_surface, output: *mut wlr_output) {
+    let compositor = running_compositor();
+    if compositor.is_null() {
+        return
+    }
+    if let Some(ref presentation) = (*compositor).presentation {
+        presentation.sampled(surface, output)
+    }
+}
+
+impl PendingFeedback {
+    /// Ties the feedback to the first commit after it was asked for, and
+    /// discards it if a later commit replaces that one before it's drawn.
+    unsafe fn commit(&mut self) {
+        if !self.data.committed {
+            self.data.committed = true
+        } else if self.data.output.is_null() {
+            self.discard()
+        }
+    }
+
+    /// Waits for the output to swap its buffers, unless the commit wasn't
+    /// made yet or was drawn on another output already.
+    unsafe fn sample(&mut self, output: *mut wlr_output) {
+        if !self.data.committed || !self.data.output.is_null() {
+            return
+        }
+        self.data.output = output;
+        wl_signal_add(&mut (*output).events.swap_buffers as *mut _ as _,
+                      self.swap_buffers_listener() as *mut _ as _);
+        wl_signal_add(&mut (*output).events.destroy as *mut _ as _,
+                      self.output_destroy_listener() as *mut _ as _);
+    }
+
+    /// Tells the client its commit was shown on the output.
+    ///
+    /// This frees the feedback, which can't be used afterwards.
+    unsafe fn present(&mut self) {
+        let (resource, output) = (self.data.resource, self.data.output);
+        let client = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, resource);
+        let outputs = &mut (*output).resources as *mut _ as *mut wl_list;
+        let mut link = (*outputs).next;
+        while link != outputs {
+            let output_resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_from_link, link);
+            if ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_client, output_resource) ==
+               client
+            {
+                ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                              wl_resource_post_event,
+                              resource,
+                              SYNC_OUTPUT,
+                              output_resource);
+            }
+            link = (*link).next;
+        }
+        let time = current_time();
+        // A refresh of 0 means it isn't known.
+        let refresh = refresh_interval((*output).refresh).map(|refresh| {
+            let nanos = refresh.as_secs() * 1_000_000_000 + refresh.subsec_nanos() as u64;
+            cmp::min(nanos, u32::MAX as u64) as u32
+        });
+        // NOTE
+        // The output has no refresh counter to give as the sequence, and the
+        // time is when the buffers were swapped, so none of the flags apply.
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event,
+                      resource,
+                      PRESENTED,
+                      (time.as_secs() >> 32) as u32,
+                      time.as_secs() as u32,
+                      time.subsec_nanos(),
+                      refresh.unwrap_or(0),
+                      0u32,
+                      0u32,
+                      0u32);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, resource);
+    }
+
+    /// Tells the client its commit won't be shown.
+    ///
+    /// This frees the feedback, which can't be used afterwards.
+    unsafe fn discard(&mut self) {
+        let resource = self.data.resource;
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_post_event, resource, DISCARDED);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, resource);
+    }
+
+    /// Stops listening to the surface.
+    unsafe fn detach_surface(&mut self) {
+        if self.data.surface.is_null() {
+            return
+        }
+        self.data.surface = ptr::null_mut();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.commit_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.surface_destroy_listener()).link as *mut _ as _);
+    }
+
+    /// Stops listening to the output the commit was drawn on.
+    unsafe fn detach_output(&mut self) {
+        if self.data.output.is_null() {
+            return
+        }
+        self.data.output = ptr::null_mut();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.swap_buffers_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.output_destroy_listener()).link as *mut _ as _);
+    }
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, data: *mut libc::c_void, version: u32, id: u32) {
+    let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                 wl_resource_create,
+                                 client,
+                                 &interfaces::wp_presentation_interface as *const _
+                                 as *const wl_interface,
+                                 version as _,
+                                 id);
+    if resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    // NOTE Freed in `presentation_destroy`.
+    let feedbacks = Box::into_raw(Box::new((*(data as *const Feedbacks)).clone()));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  resource,
+                  &PRESENTATION_IMPL as *const _ as *const libc::c_void,
+                  feedbacks as *mut libc::c_void,
+                  Some(presentation_destroy));
+    // The times are from `utils::current_time`.
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_event,
+                  resource,
+                  CLOCK_ID,
+                  libc::CLOCK_MONOTONIC as u32);
+}
+
+unsafe extern "C" fn presentation_destroy(resource: *mut wl_resource) {
+    let feedbacks = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    Box::from_raw(feedbacks as *mut Feedbacks);
+}
+
+unsafe extern "C" fn destroy(_client: *mut wl_client, resource: *mut wl_resource) {
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, resource);
+}
+
+unsafe extern "C" fn feedback(client: *mut wl_client,
+                              resource: *mut wl_resource,
+                              surface_resource: *mut wl_resource,
+                              id: u32) {
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource);
+    let feedback_resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                          wl_resource_create,
+                                          client,
+                                          &interfaces::wp_presentation_feedback_interface
+                                          as *const _ as *const wl_interface,
+                                          version,
+                                          id);
+    if feedback_resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    // NOTE The user data of a `wl_surface` is its `wlr_surface`.
+    let surface =
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, surface_resource)
+        as *mut wlr_surface;
+    let feedbacks = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let feedbacks = (*(feedbacks as *const Feedbacks)).clone();
+    // NOTE Freed in `feedback_destroy`, when the feedback is sent or the
+    // client goes away.
+    let pending = Box::into_raw(PendingFeedback::new(Feedback { resource: feedback_resource,
+                                                                surface,
+                                                                committed: false,
+                                                                output: ptr::null_mut(),
+                                                                feedbacks:
+                                                                    feedbacks.clone() }));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  feedback_resource,
+                  ptr::null(),
+                  pending as *mut libc::c_void,
+                  Some(feedback_destroy));
+    wl_signal_add(&mut (*surface).events.commit as *mut _ as _,
+                  (*pending).commit_listener() as *mut _ as _);
+    wl_signal_add(&mut (*surface).events.destroy as *mut _ as _,
+                  (*pending).surface_destroy_listener() as *mut _ as _);
+    feedbacks.borrow_mut().push(pending);
+}
+
+unsafe extern "C" fn feedback_destroy(resource: *mut wl_resource) {
+    let pending = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let pending = pending as *mut PendingFeedback;
+    (*pending).data.feedbacks.borrow_mut().retain(|&feedback| feedback != pending);
+    let mut pending = Box::from_raw(pending);
+    pending.detach_surface();
+    pending.detach_output();
+}
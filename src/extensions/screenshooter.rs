@@ -0,0 +1,364 @@
+//! Support for the screenshooter protocol, which lets clients take
+//! screenshots of the outputs.
+//!
+//! Every screenshot a client asks for is approved or refused by the
+//! `ScreenshooterHandler`, which is told the client and the output.
+//!
+//! wlroots' screenshooter can't refuse a single capture, so the global is
+//! implemented here instead. Approved screenshots are read back with the
+//! compositor's renderer the next time the output swaps its buffers.
+//!
+//! The protocol has no way to tell a client that a screenshot won't be taken,
+//! so refused screenshots and invalid buffers are answered with a protocol
+//! error, which disconnects the client.
+
+use libc;
+use std::{panic, ptr, cell::RefCell, cmp::min, rc::Rc};
+
+use wayland_sys::common::wl_interface;
+use wayland_sys::server::{wl_client, wl_display, wl_global, wl_resource, WAYLAND_SERVER_HANDLE};
+use wayland_sys::server::signal::wl_signal_add;
+use wlroots_sys::{pid_t, wl_shm_format, wlr_output, wlr_output_from_resource,
+                  wlr_output_schedule_frame};
+use wlroots_sys::protocols::screenshooter::server::interfaces::{orbital_screenshooter_interface,
+                                                                orbital_screenshot_interface};
+
+use OutputHandle;
+use compositor::{compositor_handle, running_renderer, CompositorHandle};
+use utils::handle_unwind;
+
+/// Decides which screenshots clients can take.
+pub trait ScreenshooterHandler {
+    /// Called when a client asks for a screenshot of the output.
+    ///
+    /// Return `false` to refuse. As the protocol can't refuse a single
+    /// screenshot, the client is then disconnected with a protocol error.
+    fn allow_capture(&mut self, CompositorHandle, &ScreenshooterClient, OutputHandle) -> bool {
+        true
+    }
+}
+
+impl ScreenshooterHandler for () {}
+
+/// A client that wants to take a screenshot.
+#[derive(Debug)]
+pub struct ScreenshooterClient {
+    client: *mut wl_client,
+    pid: pid_t,
+    uid: libc::uid_t,
+    gid: libc::gid_t
+}
+
+/// The screenshooter global.
+///
+/// Enable it with `CompositorBuilder::screenshooter`.
+pub struct Screenshooter {
+    global: *mut wl_global,
+    _handler: Box<HandlerRef>
+}
+
+/// The handler, shared with the resources bound to the global so it outlives
+/// the global if clients still use it.
+type HandlerRef = Rc<RefCell<Box<ScreenshooterHandler>>>;
+
+/// The requests of `orbital_screenshooter`, in the order of the protocol.
+#[repr(C)]
+struct ScreenshooterImpl {
+    shoot: unsafe extern "C" fn(*mut wl_client,
+                                *mut wl_resource,
+                                u32,
+                                *mut wl_resource,
+                                *mut wl_resource)
+}
+
+static SCREENSHOOTER_IMPL: ScreenshooterImpl = ScreenshooterImpl { shoot };
+
+/// A screenshot that was approved and waits for the output to swap its
+/// buffers.
+struct Capture {
+    /// The `orbital_screenshot` resource, which owns this capture.
+    resource: *mut wl_resource,
+    /// The buffer to copy the frame into, null once the client destroys it.
+    buffer: *mut wl_resource,
+    /// The output to capture, null once it is destroyed or captured.
+    output: *mut wlr_output
+}
+
+wayland_listener!(PendingCapture, Capture, [
+    swap_buffers_listener => swap_buffers_notify: |this: &mut PendingCapture,
+                                                   _data: *mut libc::c_void,|
+    unsafe {
+        this.capture();
+    };
+    output_destroy_listener => output_destroy_notify: |this: &mut PendingCapture,
+                                                       _data: *mut libc::c_void,|
+    unsafe {
+        this.detach_output();
+    };
+    buffer_destroy_listener => buffer_destroy_notify: |this: &mut PendingCapture,
+                                                       _data: *mut libc::c_void,|
+    unsafe {
+        this.detach_buffer();
+    };
+]);
+
+impl ScreenshooterClient {
+    unsafe fn new(client: *mut wl_client) -> Self {
+        let (mut pid, mut uid, mut gid) = (0, 0, 0);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_client_get_credentials,
+                      client,
+                      &mut pid,
+                      &mut uid,
+                      &mut gid);
+        ScreenshooterClient { client,
+                              pid,
+                              uid,
+                              gid }
+    }
+
+    /// Get the process id of the client.
+    pub fn pid(&self) -> pid_t {
+        self.pid
+    }
+
+    /// Get the user id of the client.
+    pub fn uid(&self) -> libc::uid_t {
+        self.uid
+    }
+
+    /// Get the group id of the client.
+    pub fn gid(&self) -> libc::gid_t {
+        self.gid
+    }
+
+    pub unsafe fn wl_client(&self) -> *mut wl_client {
+        self.client
+    }
+}
+
+impl Screenshooter {
+    pub(crate) unsafe fn new(display: *mut wl_display,
+                             handler: Box<ScreenshooterHandler>)
+                             -> Option<Self> {
+        let handler: Box<HandlerRef> = Box::new(Rc::new(RefCell::new(handler)));
+        let global = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                   wl_global_create,
+                                   display,
+                                   &orbital_screenshooter_interface as *const _
+                                   as *const wl_interface,
+                                   1,
+                                   &*handler as *const HandlerRef as *mut libc::c_void,
+                                   bind);
+        if global.is_null() {
+            return None
+        }
+        Some(Screenshooter { global,
+                             _handler: handler })
+    }
+}
+
+impl Drop for Screenshooter {
+    fn drop(&mut self) {
+        unsafe { ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_global_destroy, self.global) }
+    }
+}
+
+impl PendingCapture {
+    /// Copies the output's frame into the client's buffer and tells the
+    /// client the screenshot is done.
+    ///
+    /// This frees the capture, which can't be used afterwards.
+    unsafe fn capture(&mut self) {
+        let output = self.data.output;
+        self.detach_output();
+        let buffer = self.data.buffer;
+        if output.is_null() || buffer.is_null() {
+            return
+        }
+        // NOTE
+        // The output is usually borrowed by whoever is rendering to it, so
+        // it is read back through its pointer.
+        let frame = running_renderer().and_then(|mut renderer| renderer.capture_ptr(output));
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return
+        };
+        let shm_buffer = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get, buffer);
+        if shm_buffer.is_null() {
+            return
+        }
+        let stride = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_stride, shm_buffer);
+        let width = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_width, shm_buffer);
+        let height = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_height, shm_buffer);
+        let row_len = min(width as usize, frame.width() as usize) * 4;
+        let rows = min(height as usize, frame.height() as usize);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_begin_access, shm_buffer);
+        let data = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_data, shm_buffer);
+        for row in 0..rows {
+            let source = &frame.pixels()[row * frame.width() as usize * 4..][..row_len];
+            ptr::copy_nonoverlapping(source.as_ptr(),
+                                     (data as *mut u8).offset(row as isize * stride as isize),
+                                     row_len);
+        }
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_end_access, shm_buffer);
+        // The `done` event has no arguments.
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_resource_post_event_array,
+                      self.data.resource,
+                      0,
+                      ptr::null_mut());
+        // NOTE
+        // The protocol has no destructor, so the screenshot is destroyed once
+        // it is done. This frees the capture.
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, self.data.resource);
+    }
+
+    /// Stops listening to the output, which is either captured or destroyed.
+    unsafe fn detach_output(&mut self) {
+        if self.data.output.is_null() {
+            return
+        }
+        self.data.output = ptr::null_mut();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.swap_buffers_listener()).link as *mut _ as _);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.output_destroy_listener()).link as *mut _ as _);
+    }
+
+    /// Stops listening to the buffer, which is destroyed or won't be used.
+    unsafe fn detach_buffer(&mut self) {
+        if self.data.buffer.is_null() {
+            return
+        }
+        self.data.buffer = ptr::null_mut();
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.buffer_destroy_listener()).link as *mut _ as _);
+    }
+}
+
+unsafe extern "C" fn bind(client: *mut wl_client, data: *mut libc::c_void, version: u32, id: u32) {
+    let resource = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                 wl_resource_create,
+                                 client,
+                                 &orbital_screenshooter_interface as *const _
+                                 as *const wl_interface,
+                                 version as _,
+                                 id);
+    if resource.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    // NOTE Released in `screenshooter_destroy`.
+    let handler = Rc::into_raw((*(data as *const HandlerRef)).clone());
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  resource,
+                  &SCREENSHOOTER_IMPL as *const _ as *const libc::c_void,
+                  handler as *mut libc::c_void,
+                  Some(screenshooter_destroy));
+}
+
+unsafe extern "C" fn screenshooter_destroy(resource: *mut wl_resource) {
+    let handler = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    Rc::from_raw(handler as *const RefCell<Box<ScreenshooterHandler>>);
+}
+
+unsafe extern "C" fn shoot(client: *mut wl_client,
+                           resource: *mut wl_resource,
+                           id: u32,
+                           output_resource: *mut wl_resource,
+                           buffer: *mut wl_resource) {
+    // NOTE
+    // The screenshot is created before anything is checked, so that the
+    // client gets an answer for it even when it is refused.
+    let version = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_version, resource);
+    let screenshot = ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                                   wl_resource_create,
+                                   client,
+                                   &orbital_screenshot_interface as *const _
+                                   as *const wl_interface,
+                                   version,
+                                   id);
+    if screenshot.is_null() {
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_client_post_no_memory, client);
+        return
+    }
+    let output = wlr_output_from_resource(output_resource as *mut _);
+    if output.is_null() || (*output).data.is_null() {
+        return refuse(screenshot, b"output does not exist anymore\0")
+    }
+    let shm_buffer = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get, buffer);
+    if shm_buffer.is_null() {
+        return refuse(screenshot, b"buffer is not a shared memory buffer\0")
+    }
+    let width = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_width, shm_buffer);
+    let height = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_height, shm_buffer);
+    if width < (*output).width || height < (*output).height {
+        return refuse(screenshot, b"buffer is smaller than the output\0")
+    }
+    let format = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_shm_buffer_get_format, shm_buffer);
+    if format != wl_shm_format::WL_SHM_FORMAT_XRGB8888 as u32 &&
+       format != wl_shm_format::WL_SHM_FORMAT_ARGB8888 as u32
+    {
+        return refuse(screenshot, b"buffer format is not XRGB8888 or ARGB8888\0")
+    }
+    let handler = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let handler = &*(handler as *const RefCell<Box<ScreenshooterHandler>>);
+    let compositor = compositor_handle().unwrap_or_else(CompositorHandle::new);
+    let requester = ScreenshooterClient::new(client);
+    let output_handle = OutputHandle::from_ptr(output);
+    let mut allowed = false;
+    handle_unwind(panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        allowed = handler.borrow_mut().allow_capture(compositor, &requester, output_handle)
+    })));
+    if !allowed {
+        return refuse(screenshot, b"screenshot was refused by the compositor\0")
+    }
+    // NOTE Freed in `screenshot_destroy`, when the screenshot is done or the
+    // client goes away.
+    let capture = Box::into_raw(PendingCapture::new(Capture { resource: screenshot,
+                                                              buffer,
+                                                              output }));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_set_implementation,
+                  screenshot,
+                  ptr::null(),
+                  capture as *mut libc::c_void,
+                  Some(screenshot_destroy));
+    wl_signal_add(&mut (*output).events.swap_buffers as *mut _ as _,
+                  (*capture).swap_buffers_listener() as *mut _ as _);
+    wl_signal_add(&mut (*output).events.destroy as *mut _ as _,
+                  (*capture).output_destroy_listener() as *mut _ as _);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_add_destroy_listener,
+                  buffer,
+                  (*capture).buffer_destroy_listener() as *mut _ as _);
+    wlr_output_schedule_frame(output);
+}
+
+/// Answers a screenshot that won't be taken with a protocol error, which
+/// disconnects the client, and destroys it.
+///
+/// `message` has to be nul terminated.
+unsafe fn refuse(screenshot: *mut wl_resource, message: &'static [u8]) {
+    wlr_log!(L_DEBUG,
+             "Refusing screenshot: {}",
+             String::from_utf8_lossy(&message[..message.len() - 1]));
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                  wl_resource_post_error,
+                  screenshot,
+                  0,
+                  message.as_ptr() as *const libc::c_char);
+    ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_destroy, screenshot);
+}
+
+unsafe extern "C" fn screenshot_destroy(resource: *mut wl_resource) {
+    let capture = ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_resource_get_user_data, resource);
+    let mut capture = Box::from_raw(capture as *mut PendingCapture);
+    capture.detach_output();
+    capture.detach_buffer();
+}
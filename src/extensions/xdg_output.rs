@@ -0,0 +1,56 @@
+//! Support for the xdg-output protocol, which tells clients (e.g bars or
+//! screenshot tools) where each output is in the layout and how big it is
+//! in logical pixels, i.e after its scale and transform.
+//!
+//! wlroots sends the geometry from the output layout it is given, and sends
+//! it again every time the layout changes, so clients are kept up to date
+//! when outputs are added, moved, rescaled or rotated without the
+//! compositor doing anything. The updates go out on the same `change`
+//! signal that calls `OutputLayoutHandler::on_change`.
+
+use wayland_sys::server::wl_display as wl_server_display;
+use wlroots_sys::{wl_display, wlr_xdg_output_manager, wlr_xdg_output_manager_create,
+                  wlr_xdg_output_manager_destroy};
+
+use {Handle, OutputLayoutHandle};
+
+/// The xdg-output global.
+///
+/// Enable it with `CompositorBuilder::xdg_output_manager`.
+#[derive(Debug)]
+pub struct XdgOutputManager {
+    manager: *mut wlr_xdg_output_manager,
+    layout: OutputLayoutHandle
+}
+
+impl XdgOutputManager {
+    pub(crate) unsafe fn new(display: *mut wl_server_display,
+                             layout: OutputLayoutHandle)
+                             -> Option<Self> {
+        if !layout.is_alive() {
+            return None
+        }
+        let manager = wlr_xdg_output_manager_create(display as *mut wl_display, layout.as_ptr());
+        if manager.is_null() {
+            return None
+        }
+        Some(XdgOutputManager { manager,
+                                layout })
+    }
+
+    /// Gets the layout the geometry sent to clients comes from.
+    pub fn layout(&self) -> OutputLayoutHandle {
+        self.layout.clone()
+    }
+}
+
+impl Drop for XdgOutputManager {
+    fn drop(&mut self) {
+        // NOTE
+        // wlroots destroys the manager with its output layout, so it is
+        // already gone if the layout was dropped first.
+        if self.layout.is_alive() {
+            unsafe { wlr_xdg_output_manager_destroy(self.manager) }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! The interface shared by all of the handles.
+
+use std::hash::Hash;
+
+use errors::HandleResult;
+
+/// A weak reference to a structure that can be destroyed at any time.
+///
+/// All handles can be cloned, compared and hashed, so they can be used as
+/// keys in a `HashMap` or `HashSet`. Two handles are equal when they refer
+/// to the same structure.
+pub trait Handle: Clone + Eq + Hash {
+    /// The structure this handle refers to.
+    type Target;
+
+    /// Run a function on the referenced structure, if it still exists.
+    ///
+    /// Returns the result of the function, if successful.
+    ///
+    /// # Panics
+    /// See the `run` method of the specific handle for when this panics.
+    fn run<F, R>(&self, runner: F) -> HandleResult<R> where F: FnOnce(&mut Self::Target) -> R;
+
+    /// Determines if the referenced structure still exists.
+    ///
+    /// Even if this returns `true`, `run` can fail if the structure is
+    /// already borrowed.
+    fn is_alive(&self) -> bool;
+
+    /// Identifies the referenced structure.
+    ///
+    /// All handles to the same structure have the same id, and equality and
+    /// hashing are based on it. The id of a destroyed structure can be
+    /// reused by a new one, so handles to destroyed structures should be
+    /// removed from any maps they are kept in.
+    fn id(&self) -> usize;
+}
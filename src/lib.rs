@@ -8,8 +8,11 @@
 #![allow(unused_unsafe)]
 #[macro_use]
 extern crate bitflags;
+extern crate ini;
+#[macro_use]
 extern crate lazy_static;
 extern crate libc;
+extern crate png;
 #[macro_use]
 pub extern crate wayland_sys;
 pub extern crate wlroots_sys;
@@ -17,18 +20,32 @@ pub extern crate xkbcommon;
 
 #[macro_use]
 mod macros;
+pub mod backend;
 mod manager;
 mod compositor;
 mod errors;
+mod event_loop;
 pub mod events;
 pub mod types;
 pub mod extensions;
+mod handle;
+mod listener;
 pub mod render;
+pub mod scene;
+mod sender;
+pub mod testing;
 pub mod utils;
+mod user_data;
 mod xwayland;
 
+pub use self::backend::{Backend, BackendConfig};
 pub use self::compositor::{compositor_handle, terminate, Compositor, CompositorBuilder,
-                           CompositorHandle, CompositorHandler};
+                           CompositorHandle, CompositorHandler, StateHandle};
+pub use self::handle::Handle;
+pub use self::listener::ListenerToken;
+pub use self::sender::CompositorSender;
+pub use self::user_data::UserData;
+pub use self::event_loop::{EventLoop, EventSource, FdMask, IdleHandle, TimerHandle};
 pub use self::events::{key_events, seat_events, tablet_pad_events, tablet_tool_events,
                        touch_events, xwayland_events,
                        pointer_events::{self, BTN_BACK, BTN_EXTRA, BTN_FORWARD, BTN_LEFT,
@@ -43,6 +60,7 @@ pub use self::types::cursor::*;
 pub use self::types::data_device::*;
 pub use self::types::input::*;
 pub use self::types::output::*;
+pub use self::types::region::*;
 pub use self::types::seat::*;
 pub use self::types::shell::*;
 pub use self::types::surface::*;
@@ -58,8 +76,10 @@ pub use wlroots_sys::{wlr_keyboard_modifiers, wlr_tablet_tool_axes, wl_shm_forma
                       wlr_tablet_pad_strip_source::{self, *},
                       wlr_tablet_tool_proximity_state::{self, *}};
 
+#[allow(deprecated)]
 pub use self::render::{matrix_identity, matrix_multiply, matrix_projection, matrix_rotate,
                        matrix_scale, matrix_transform, matrix_translate, matrix_transpose,
-                       project_box, GenericRenderer, Image, Renderer, Texture, TextureFormat};
+                       project_box, GenericRenderer, Image, Matrix3, Renderer, Texture,
+                       TextureFormat};
 
 pub use self::errors::*;
@@ -0,0 +1,156 @@
+//! Closures that are called when an event happens.
+//!
+//! This is an alternative to implementing one of the handler traits (e.g
+//! `OutputHandler`) when only a few events are interesting. Methods like
+//! `Output::on_frame` register a closure and return a `ListenerToken`, which
+//! unregisters the closure when it is dropped.
+
+use libc;
+use std::{panic, cell::Cell};
+
+use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
+use wlroots_sys::wl_signal;
+
+type Callback = Box<FnMut(*mut libc::c_void)>;
+
+struct ListenerState {
+    callback: Callback,
+    /// Set when the event is the destroy event itself, in which case there
+    /// is no separate destroy listener.
+    on_destroy: bool,
+    /// Whether the listeners are still registered.
+    attached: Cell<bool>,
+    /// Set while the callback is running.
+    running: Cell<bool>,
+    /// Set when the listener should free itself, because its token is gone.
+    orphaned: Cell<bool>
+}
+
+wayland_listener!(ClosureListener, ListenerState, [
+    listener => notify: |this: &mut ClosureListener, data: *mut libc::c_void,| unsafe {
+        if !this.data.attached.get() {
+            return
+        }
+        this.data.running.set(true);
+        // NOTE
+        // The panic is caught here so the listener is cleaned up the same way
+        // whether or not the callback returns, then it is passed on.
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| (this.data.callback)(data)));
+        this.data.running.set(false);
+        if this.data.on_destroy {
+            this.detach();
+        }
+        if this.data.orphaned.get() && !this.data.attached.get() {
+            Box::from_raw(this as *mut ClosureListener);
+        }
+        if let Err(err) = res {
+            panic::resume_unwind(err)
+        }
+    };
+    destroy_listener => destroy_notify: |this: &mut ClosureListener,
+                                         _data: *mut libc::c_void,|
+    unsafe {
+        this.detach();
+        if this.data.orphaned.get() && !this.data.running.get() {
+            Box::from_raw(this as *mut ClosureListener);
+        }
+    };
+]);
+
+/// Keeps a closure registered on an event.
+///
+/// The closure is unregistered when this is dropped, or once the object
+/// that emits the event is destroyed.
+#[must_use = "The closure is unregistered when the token is dropped"]
+pub struct ListenerToken {
+    listener: Option<Box<ClosureListener>>
+}
+
+impl ClosureListener {
+    /// Removes the listeners from their signals.
+    unsafe fn detach(&mut self) {
+        if !self.data.attached.get() {
+            return
+        }
+        self.data.attached.set(false);
+        ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                      wl_list_remove,
+                      &mut (*self.listener()).link as *mut _ as _);
+        if !self.data.on_destroy {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_list_remove,
+                          &mut (*self.destroy_listener()).link as *mut _ as _);
+        }
+    }
+
+    /// Gives up ownership of the listener.
+    ///
+    /// It is freed now if it's no longer needed, otherwise it frees itself
+    /// once it is.
+    unsafe fn orphan(listener: Box<ClosureListener>, detach: bool) {
+        let listener = Box::into_raw(listener);
+        if detach {
+            (*listener).detach();
+        }
+        (*listener).data.orphaned.set(true);
+        if !(*listener).data.attached.get() && !(*listener).data.running.get() {
+            Box::from_raw(listener);
+        }
+    }
+}
+
+impl ListenerToken {
+    /// Registers `callback` on `signal`.
+    ///
+    /// `destroy_signal` must be the destroy signal of the object that owns
+    /// `signal`, so that the closure is unregistered before the object goes
+    /// away. The two may be the same signal.
+    pub(crate) unsafe fn new<F>(signal: *mut wl_signal,
+                                destroy_signal: *mut wl_signal,
+                                callback: F)
+                                -> Self
+        where F: FnMut(*mut libc::c_void) + 'static
+    {
+        let on_destroy = signal == destroy_signal;
+        let mut listener = ClosureListener::new(ListenerState { callback: Box::new(callback),
+                                                                on_destroy,
+                                                                attached: Cell::new(true),
+                                                                running: Cell::new(false),
+                                                                orphaned: Cell::new(false) });
+        wl_signal_add(signal as *mut _, listener.listener() as *mut _ as _);
+        if !on_destroy {
+            wl_signal_add(destroy_signal as *mut _,
+                          listener.destroy_listener() as *mut _ as _);
+        }
+        ListenerToken { listener: Some(listener) }
+    }
+
+    /// Determines if the closure is still registered.
+    ///
+    /// This is `false` once the object that emits the event is destroyed.
+    pub fn is_attached(&self) -> bool {
+        self.listener
+            .as_ref()
+            .map(|listener| listener.data.attached.get())
+            .unwrap_or(false)
+    }
+
+    /// Keeps the closure registered until the object that emits the event
+    /// is destroyed, instead of until this token is dropped.
+    pub fn forget(mut self) {
+        if let Some(listener) = self.listener.take() {
+            unsafe { ClosureListener::orphan(listener, false) }
+        }
+    }
+}
+
+impl Drop for ListenerToken {
+    fn drop(&mut self) {
+        // NOTE
+        // The token can be dropped from inside its own closure, in which case
+        // the listener frees itself once the closure returns.
+        if let Some(listener) = self.listener.take() {
+            unsafe { ClosureListener::orphan(listener, true) }
+        }
+    }
+}
@@ -191,10 +191,55 @@ macro_rules! wayland_listener {
     }
 }
 
+/// Implements `PartialEq`, `Eq`, `Hash` and `Handle` for a handle.
+///
+/// `$ptr` is the field with the pointer to the wlroots structure, which
+/// identifies what the handle refers to. The handle must also have a
+/// `handle` field with the weak reference to the structure's liveliness,
+/// and its own `run` method.
+macro_rules! impl_handle {
+    ($handle: ident, $target: ty, $ptr: ident) => {
+        impl PartialEq for $handle {
+            fn eq(&self, other: &$handle) -> bool {
+                self.$ptr == other.$ptr
+            }
+        }
+
+        impl Eq for $handle {}
+
+        impl ::std::hash::Hash for $handle {
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                ::std::hash::Hash::hash(&self.$ptr, state)
+            }
+        }
+
+        impl $crate::handle::Handle for $handle {
+            type Target = $target;
+
+            fn run<F, R>(&self, runner: F) -> $crate::errors::HandleResult<R>
+                where F: FnOnce(&mut $target) -> R
+            {
+                $handle::run(self, runner)
+            }
+
+            fn is_alive(&self) -> bool {
+                self.handle.upgrade().is_some()
+            }
+
+            fn id(&self) -> usize {
+                self.$ptr as usize
+            }
+        }
+    }
+}
+
 /// Used to indicate what data is global compositor data.
 /// It will automatically implement the CompositorData trait for the struct,
 /// and also add a method to `Compositor` to unwrap the data from the fat
 /// pointer.
+///
+/// The cast panics if the compositor was built with data of another type.
+/// Prefer `CompositorBuilder::state`, whose handle is checked at compile time.
 #[macro_export]
 macro_rules! compositor_data {
     ($struct_name: ty) => {
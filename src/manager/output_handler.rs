@@ -84,7 +84,11 @@ wayland_listener!(UserOutput, (Output, Box<OutputHandler>), [
         Box::from_raw((*output_data).output as *mut UserOutput);
     };
     frame_listener => frame_notify: |this: &mut UserOutput, _output: *mut libc::c_void,| unsafe {
-        let (ref output, ref mut manager) = this.data;
+        let (ref mut output, ref mut manager) = this.data;
+        output.record_frame();
+        if output.render_mirror() {
+            return
+        }
         let compositor = match compositor_handle() {
             Some(handle) => handle,
             None => return
@@ -133,8 +137,12 @@ wayland_listener!(UserOutput, (Output, Box<OutputHandler>), [
     swap_buffers_listener => swap_buffers_notify: |this: &mut UserOutput,
                                                    _output: *mut libc::c_void,|
     unsafe {
-
-        let (ref output, ref mut manager) = this.data;
+        let (ref mut output, ref mut manager) = this.data;
+        output.record_swap();
+        // NOTE
+        // This is emitted before the buffers are swapped, so the frame can
+        // still be read back.
+        output.capture_for_mirrors();
         let compositor = match compositor_handle() {
             Some(handle) => handle,
             None => return
@@ -2,7 +2,7 @@
 //! Pass a struct that implements this trait to the `Compositor` during
 //! initialization.
 
-use {Output, OutputHandle, OutputState};
+use {Output, OutputConfig, OutputHandle, OutputState};
 use compositor::{compositor_handle, CompositorHandle};
 use libc;
 use manager::{OutputHandler, UserOutput};
@@ -73,6 +73,27 @@ impl<'output> OutputBuilder<'output> {
                               result: Box::new(data),
                               phantom: PhantomData }
     }
+
+    /// Build the output with the settings of the rule in `config` that
+    /// matches it.
+    ///
+    /// The best mode is chosen first, so outputs without a rule, or whose
+    /// rule doesn't set a mode, still get one.
+    ///
+    /// To complete construction, return this in your implementation of
+    /// `OutputManagerHandler::output_added`.
+    pub fn build_with_config<T: OutputHandler + 'static>(mut self,
+                                                         config: &OutputConfig,
+                                                         data: T)
+                                                         -> OutputBuilderResult<'output> {
+        with_handles!([(output: {&mut self.output})] => {
+            output.choose_best_mode();
+            config.apply(output);
+        }).expect("Output was borrowed");
+        OutputBuilderResult { output: self.output,
+                              result: Box::new(data),
+                              phantom: PhantomData }
+    }
 }
 
 impl OutputDestruction {
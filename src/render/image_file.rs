@@ -0,0 +1,606 @@
+//! Decoders for image files, to make textures out of e.g wallpapers and
+//! decoration themes.
+//!
+//! PNG, binary PPM and PGM, PAM and farbfeld files are supported.
+
+use std::{cmp, str, fs::File, io::Read, path::Path};
+
+use png;
+
+use {GenericRenderer, ImageError, Texture, TextureFormat};
+
+/// The image file formats that can be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png,
+    /// Binary PPM (`P6`), or its grayscale variant PGM (`P5`).
+    Ppm,
+    /// The arbitrary map format of netpbm (`P7`).
+    Pam,
+    Farbfeld
+}
+
+/// An image decoded from a file.
+///
+/// The pixels are stored row by row from the top left, as red, green, blue
+/// and alpha bytes. The alpha is not premultiplied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>
+}
+
+/// How the bits of a pixel are laid out in a `TextureFormat`.
+///
+/// Each channel is given as (shift, bits) in the little endian pixel value.
+struct Layout {
+    bytes: usize,
+    red: (u32, u32),
+    green: (u32, u32),
+    blue: (u32, u32),
+    alpha: Option<(u32, u32)>
+}
+
+impl ImageFormat {
+    /// Guesses the format of a file from its first bytes.
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if bytes.starts_with(b"P5") || bytes.starts_with(b"P6") {
+            Some(ImageFormat::Ppm)
+        } else if bytes.starts_with(b"P7") {
+            Some(ImageFormat::Pam)
+        } else if bytes.starts_with(b"farbfeld") {
+            Some(ImageFormat::Farbfeld)
+        } else {
+            None
+        }
+    }
+}
+
+impl DecodedImage {
+    /// Makes an image out of non premultiplied RGBA pixels.
+    ///
+    /// Returns `None` if there are not exactly `width * height` pixels.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Option<Self> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return None
+        }
+        Some(DecodedImage { width,
+                            height,
+                            pixels })
+    }
+
+    /// Decodes an image, guessing its format from its contents.
+    pub fn decode<R: Read>(mut reader: R) -> Result<Self, ImageError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let format = ImageFormat::from_magic(&bytes).ok_or(ImageError::UnknownFormat)?;
+        DecodedImage::decode_bytes(&bytes, format)
+    }
+
+    /// Decodes an image in the given format.
+    pub fn decode_bytes(bytes: &[u8], format: ImageFormat) -> Result<Self, ImageError> {
+        match format {
+            ImageFormat::Png => decode_png(bytes),
+            ImageFormat::Ppm => decode_ppm(bytes),
+            ImageFormat::Pam => decode_pam(bytes),
+            ImageFormat::Farbfeld => decode_farbfeld(bytes)
+        }
+    }
+
+    /// Opens and decodes an image file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        DecodedImage::decode(File::open(path)?)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Gets the raw pixels, as non premultiplied RGBA.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    /// Converts the pixels to the given format, with rows tightly packed.
+    ///
+    /// Colors are premultiplied by the alpha for formats with an alpha
+    /// channel, like Wayland clients do.
+    ///
+    /// Returns `None` for the formats that don't store whole pixels (`C8`
+    /// and the YUV formats).
+    pub fn to_format(&self, format: TextureFormat) -> Option<Vec<u8>> {
+        let layout = Layout::of(format)?;
+        let mut data = Vec::with_capacity(self.pixels.len() / 4 * layout.bytes);
+        for pixel in self.pixels.chunks(4) {
+            let (mut red, mut green, mut blue, alpha) = (pixel[0], pixel[1], pixel[2], pixel[3]);
+            if layout.alpha.is_some() {
+                red = premultiply(red, alpha);
+                green = premultiply(green, alpha);
+                blue = premultiply(blue, alpha);
+            }
+            let mut value = pack(red, layout.red) | pack(green, layout.green)
+                            | pack(blue, layout.blue);
+            if let Some(alpha_layout) = layout.alpha {
+                value |= pack(alpha, alpha_layout);
+            }
+            for byte in 0..layout.bytes {
+                data.push((value >> (byte * 8)) as u8);
+            }
+        }
+        Some(data)
+    }
+
+    /// Uploads the image to a texture in the given format.
+    ///
+    /// Returns `None` if the format can't hold the image, or if the renderer
+    /// does not support it.
+    pub fn to_texture(&self,
+                      renderer: &mut GenericRenderer,
+                      format: TextureFormat)
+                      -> Option<Texture> {
+        let data = self.to_format(format)?;
+        let stride = self.width * format.bytes_per_pixel()?;
+        renderer.create_texture_from_pixels(format.into(), stride, self.width, self.height, &data)
+    }
+}
+
+impl Texture {
+    /// Makes a texture out of a decoded image, see `DecodedImage::to_texture`.
+    pub fn from_image(renderer: &mut GenericRenderer,
+                      image: &DecodedImage,
+                      format: TextureFormat)
+                      -> Option<Texture> {
+        image.to_texture(renderer, format)
+    }
+
+    /// Decodes an image file and makes a texture out of it.
+    pub fn from_file<P: AsRef<Path>>(renderer: &mut GenericRenderer,
+                                     path: P,
+                                     format: TextureFormat)
+                                     -> Result<Texture, ImageError> {
+        let image = DecodedImage::open(path)?;
+        image.to_texture(renderer, format)
+             .ok_or(ImageError::TextureCreationFailed)
+    }
+}
+
+impl Layout {
+    fn of(format: TextureFormat) -> Option<Layout> {
+        use self::TextureFormat::*;
+        let (bytes, red, green, blue, alpha) = match format {
+            RGB332 => (1, (5, 3), (2, 3), (0, 2), None),
+            BGR233 => (1, (0, 3), (3, 3), (6, 2), None),
+            XRGB4444 => (2, (8, 4), (4, 4), (0, 4), None),
+            ARGB4444 => (2, (8, 4), (4, 4), (0, 4), Some((12, 4))),
+            XBGR4444 => (2, (0, 4), (4, 4), (8, 4), None),
+            ABGR4444 => (2, (0, 4), (4, 4), (8, 4), Some((12, 4))),
+            RGBX4444 => (2, (12, 4), (8, 4), (4, 4), None),
+            RGBA4444 => (2, (12, 4), (8, 4), (4, 4), Some((0, 4))),
+            BGRX4444 => (2, (4, 4), (8, 4), (12, 4), None),
+            BGRA4444 => (2, (4, 4), (8, 4), (12, 4), Some((0, 4))),
+            XRGB1555 => (2, (10, 5), (5, 5), (0, 5), None),
+            ARGB1555 => (2, (10, 5), (5, 5), (0, 5), Some((15, 1))),
+            XBGR1555 => (2, (0, 5), (5, 5), (10, 5), None),
+            ABGR1555 => (2, (0, 5), (5, 5), (10, 5), Some((15, 1))),
+            RGBX5551 => (2, (11, 5), (6, 5), (1, 5), None),
+            RGBA5551 => (2, (11, 5), (6, 5), (1, 5), Some((0, 1))),
+            BGRX5551 => (2, (1, 5), (6, 5), (11, 5), None),
+            BGRA5551 => (2, (1, 5), (6, 5), (11, 5), Some((0, 1))),
+            RGB565 => (2, (11, 5), (5, 6), (0, 5), None),
+            BGR565 => (2, (0, 5), (5, 6), (11, 5), None),
+            RGB888 => (3, (16, 8), (8, 8), (0, 8), None),
+            BGR888 => (3, (0, 8), (8, 8), (16, 8), None),
+            XRGB8888 => (4, (16, 8), (8, 8), (0, 8), None),
+            ARGB8888 => (4, (16, 8), (8, 8), (0, 8), Some((24, 8))),
+            XBGR8888 => (4, (0, 8), (8, 8), (16, 8), None),
+            ABGR8888 => (4, (0, 8), (8, 8), (16, 8), Some((24, 8))),
+            RGBX8888 => (4, (24, 8), (16, 8), (8, 8), None),
+            RGBA8888 => (4, (24, 8), (16, 8), (8, 8), Some((0, 8))),
+            BGRX8888 => (4, (8, 8), (16, 8), (24, 8), None),
+            BGRA8888 => (4, (8, 8), (16, 8), (24, 8), Some((0, 8))),
+            XRGB2101010 => (4, (20, 10), (10, 10), (0, 10), None),
+            ARGB2101010 => (4, (20, 10), (10, 10), (0, 10), Some((30, 2))),
+            XBGR2101010 => (4, (0, 10), (10, 10), (20, 10), None),
+            ABGR2101010 => (4, (0, 10), (10, 10), (20, 10), Some((30, 2))),
+            RGBX1010102 => (4, (22, 10), (12, 10), (2, 10), None),
+            RGBA1010102 => (4, (22, 10), (12, 10), (2, 10), Some((0, 2))),
+            BGRX1010102 => (4, (2, 10), (12, 10), (22, 10), None),
+            BGRA1010102 => (4, (2, 10), (12, 10), (22, 10), Some((0, 2))),
+            _ => return None
+        };
+        Some(Layout { bytes,
+                      red,
+                      green,
+                      blue,
+                      alpha })
+    }
+}
+
+/// Scales an 8 bit channel to the given number of bits, and moves it in
+/// place.
+fn pack(value: u8, (shift, bits): (u32, u32)) -> u32 {
+    let max = (1u32 << bits) - 1;
+    ((value as u32 * max + 127) / 255) << shift
+}
+
+fn premultiply(value: u8, alpha: u8) -> u8 {
+    ((value as u32 * alpha as u32 + 127) / 255) as u8
+}
+
+/// Gets the number of pixels in an image, refusing sizes that overflow.
+fn pixel_count(width: u32, height: u32) -> Result<usize, ImageError> {
+    (width as usize).checked_mul(height as usize)
+                    .ok_or(ImageError::Malformed("image size"))
+}
+
+/// Scales a sample with the given maximum value to 8 bits.
+fn scale_sample(value: u32, max: u32) -> u8 {
+    ((value * 255 + max / 2) / max) as u8
+}
+
+fn decode_png(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    // NOTE
+    // The decoder expands palettes and low bit depths, and strips 16 bit
+    // samples to 8 bits, so only the color type is left to handle.
+    let decoder = png::Decoder::new(bytes);
+    let (info, mut reader) = decoder.read_info()?;
+    if info.width == 0 || info.height == 0 {
+        return Err(ImageError::Malformed("empty PNG"))
+    }
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        png::ColorType::Indexed => return Err(ImageError::Malformed("unexpanded palette"))
+    };
+    if info.bit_depth != png::BitDepth::Eight {
+        return Err(ImageError::Unsupported("PNG bit depth"))
+    }
+    let mut pixels = Vec::with_capacity(info.width as usize * info.height as usize * 4);
+    for row in buffer.chunks(info.line_size) {
+        for pixel in row[..info.width as usize * channels].chunks(channels) {
+            let rgba = match channels {
+                1 => [pixel[0], pixel[0], pixel[0], 255],
+                2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                3 => [pixel[0], pixel[1], pixel[2], 255],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]]
+            };
+            pixels.extend_from_slice(&rgba);
+        }
+    }
+    DecodedImage::new(info.width, info.height, pixels).ok_or(ImageError::Malformed("PNG size"))
+}
+
+/// Reads the whitespace separated fields at the start of a PPM or PGM file,
+/// skipping comments.
+///
+/// Returns the fields and the offset of the pixel data.
+fn ppm_header(bytes: &[u8], count: usize) -> Result<(Vec<u32>, usize), ImageError> {
+    let mut fields = vec![];
+    let mut offset = 2;
+    while fields.len() < count {
+        match bytes.get(offset) {
+            Some(&b'#') => {
+                while bytes.get(offset).map(|&byte| byte != b'\n').unwrap_or(false) {
+                    offset += 1
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => offset += 1,
+            Some(byte) if byte.is_ascii_digit() => {
+                let start = offset;
+                while bytes.get(offset).map(|byte| byte.is_ascii_digit()).unwrap_or(false) {
+                    offset += 1
+                }
+                fields.push(parse_number(&bytes[start..offset])?);
+            }
+            _ => return Err(ImageError::Malformed("PPM header"))
+        }
+    }
+    // A single whitespace character separates the header from the pixels.
+    match bytes.get(offset) {
+        Some(byte) if byte.is_ascii_whitespace() => Ok((fields, offset + 1)),
+        _ => Err(ImageError::Malformed("PPM header"))
+    }
+}
+
+fn parse_number(bytes: &[u8]) -> Result<u32, ImageError> {
+    str::from_utf8(bytes).ok()
+                                .and_then(|number| number.parse().ok())
+                                .ok_or(ImageError::Malformed("number"))
+}
+
+fn decode_ppm(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    let channels = if bytes.starts_with(b"P5") { 1 } else { 3 };
+    let (fields, offset) = ppm_header(bytes, 3)?;
+    let (width, height, max) = (fields[0], fields[1], fields[2]);
+    let tuple = if channels == 1 { "GRAYSCALE" } else { "RGB" };
+    decode_samples(&bytes[offset..], width, height, channels, max, tuple)
+}
+
+fn decode_pam(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    let (mut width, mut height, mut depth, mut max) = (None, None, None, None);
+    let mut tuple = String::new();
+    let mut offset = 0;
+    loop {
+        let end = bytes[offset..].iter()
+                                 .position(|&byte| byte == b'\n')
+                                 .ok_or(ImageError::Malformed("PAM header"))?;
+        let line = str::from_utf8(&bytes[offset..offset + end])
+            .map_err(|_| ImageError::Malformed("PAM header"))?;
+        offset += end + 1;
+        let mut words = line.split_whitespace();
+        let value = |words: &mut str::SplitWhitespace| {
+            words.next()
+                 .and_then(|number| number.parse::<u32>().ok())
+                 .ok_or(ImageError::Malformed("PAM header"))
+        };
+        match words.next() {
+            Some("P7") | Some("#") | None => {}
+            Some(word) if word.starts_with('#') => {}
+            Some("WIDTH") => width = Some(value(&mut words)?),
+            Some("HEIGHT") => height = Some(value(&mut words)?),
+            Some("DEPTH") => depth = Some(value(&mut words)?),
+            Some("MAXVAL") => max = Some(value(&mut words)?),
+            Some("TUPLTYPE") => tuple.push_str(words.next().unwrap_or("")),
+            Some("ENDHDR") => break,
+            Some(_) => return Err(ImageError::Malformed("PAM header"))
+        }
+    }
+    match (width, height, depth, max) {
+        (Some(width), Some(height), Some(depth), Some(max)) => {
+            if tuple.is_empty() {
+                tuple.push_str(match depth {
+                                   1 => "GRAYSCALE",
+                                   2 => "GRAYSCALE_ALPHA",
+                                   3 => "RGB",
+                                   _ => "RGB_ALPHA"
+                               });
+            }
+            decode_samples(&bytes[offset..], width, height, depth as usize, max, &tuple)
+        }
+        _ => Err(ImageError::Malformed("PAM header"))
+    }
+}
+
+/// Decodes the samples of a netpbm image, which are bytes if the maximum
+/// value fits in one and big endian words otherwise.
+fn decode_samples(bytes: &[u8],
+                  width: u32,
+                  height: u32,
+                  channels: usize,
+                  max: u32,
+                  tuple: &str)
+                  -> Result<DecodedImage, ImageError> {
+    let expected = match tuple {
+        "GRAYSCALE" | "BLACKANDWHITE" => 1,
+        "GRAYSCALE_ALPHA" | "BLACKANDWHITE_ALPHA" => 2,
+        "RGB" => 3,
+        "RGB_ALPHA" => 4,
+        _ => return Err(ImageError::Unsupported("PAM tuple type"))
+    };
+    if channels != expected || max == 0 || max > 65535 {
+        return Err(ImageError::Malformed("netpbm header"))
+    }
+    let sample_size = if max < 256 { 1 } else { 2 };
+    let count = pixel_count(width, height)?;
+    let bytes = count.checked_mul(channels * sample_size)
+                     .and_then(|size| bytes.get(..size))
+                     .ok_or(ImageError::Malformed("truncated pixels"))?;
+    let mut pixels = Vec::with_capacity(count * 4);
+    for pixel in bytes.chunks(channels * sample_size) {
+        let mut samples = [0u8; 4];
+        for (channel, sample) in pixel.chunks(sample_size).enumerate() {
+            let value = if sample_size == 1 {
+                sample[0] as u32
+            } else {
+                (sample[0] as u32) << 8 | sample[1] as u32
+            };
+            samples[channel] = scale_sample(cmp::min(value, max), max);
+        }
+        let rgba = match channels {
+            1 => [samples[0], samples[0], samples[0], 255],
+            2 => [samples[0], samples[0], samples[0], samples[1]],
+            3 => [samples[0], samples[1], samples[2], 255],
+            _ => samples
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+    DecodedImage::new(width, height, pixels).ok_or(ImageError::Malformed("netpbm size"))
+}
+
+fn decode_farbfeld(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    let header = bytes.get(..16).ok_or(ImageError::Malformed("farbfeld header"))?;
+    let be32 = |bytes: &[u8]| {
+        (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8
+        | bytes[3] as u32
+    };
+    let (width, height) = (be32(&header[8..12]), be32(&header[12..16]));
+    let count = pixel_count(width, height)?;
+    let data = count.checked_mul(8)
+                    .and_then(|size| size.checked_add(16))
+                    .and_then(|end| bytes.get(16..end))
+                    .ok_or(ImageError::Malformed("truncated pixels"))?;
+    // farbfeld samples are 16 bit big endian, so the high byte is the
+    // closest 8 bit value.
+    let pixels = data.chunks(2).map(|sample| sample[0]).collect();
+    DecodedImage::new(width, height, pixels).ok_or(ImageError::Malformed("farbfeld size"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodedImage, ImageFormat};
+    use ImageError;
+
+    /// A 2x1 RGBA PNG, with an opaque red and a half transparent green pixel.
+    const RGBA_PNG: &[u8] =
+        b"\x89\x50\x4e\x47\x0d\x0a\x1a\x0a\x00\x00\x00\x0d\x49\x48\x44\x52\x00\x00\x00\x02\
+          \x00\x00\x00\x01\x08\x06\x00\x00\x00\xf4\x22\x7f\x8a\x00\x00\x00\x0f\x49\x44\x41\
+          \x54\x78\x9c\x63\xf8\xcf\xc0\xf0\x1f\x08\x1b\x00\x10\x79\x03\x7e\x7d\x63\xce\xd7\
+          \x00\x00\x00\x00\x49\x45\x4e\x44\xae\x42\x60\x82";
+
+    /// A 1x2 grayscale PNG, with a black and a white pixel.
+    const GRAY_PNG: &[u8] =
+        b"\x89\x50\x4e\x47\x0d\x0a\x1a\x0a\x00\x00\x00\x0d\x49\x48\x44\x52\x00\x00\x00\x01\
+          \x00\x00\x00\x02\x08\x00\x00\x00\x00\xbc\xea\xe9\xfb\x00\x00\x00\x0c\x49\x44\x41\
+          \x54\x78\x9c\x63\x60\x60\xf8\x0f\x00\x01\x03\x01\x00\x08\x89\xc2\xec\x00\x00\x00\
+          \x00\x49\x45\x4e\x44\xae\x42\x60\x82";
+
+    fn decode(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+        DecodedImage::decode(bytes)
+    }
+
+    fn is_malformed(res: Result<DecodedImage, ImageError>) -> bool {
+        match res {
+            Err(ImageError::Malformed(_)) => true,
+            _ => false
+        }
+    }
+
+    fn farbfeld(width: u32, height: u32, samples: &[u16]) -> Vec<u8> {
+        let mut bytes = b"farbfeld".to_vec();
+        for value in &[width, height] {
+            bytes.extend_from_slice(&[(value >> 24) as u8,
+                                      (value >> 16) as u8,
+                                      (value >> 8) as u8,
+                                      *value as u8]);
+        }
+        for sample in samples {
+            bytes.extend_from_slice(&[(sample >> 8) as u8, *sample as u8]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn guesses_format_from_magic() {
+        assert_eq!(ImageFormat::from_magic(RGBA_PNG), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_magic(b"P5\n"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_magic(b"P6\n"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_magic(b"P7\n"), Some(ImageFormat::Pam));
+        assert_eq!(ImageFormat::from_magic(b"farbfeld"), Some(ImageFormat::Farbfeld));
+        assert_eq!(ImageFormat::from_magic(b"GIF89a"), None);
+        assert_eq!(ImageFormat::from_magic(b""), None);
+        match decode(b"GIF89a") {
+            Err(ImageError::UnknownFormat) => {}
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decodes_png() {
+        let image = decode(RGBA_PNG).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixels(), &[255, 0, 0, 255, 0, 255, 0, 128]);
+        let image = decode(GRAY_PNG).unwrap();
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_eq!(image.pixels(), &[0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn rejects_broken_png() {
+        for len in &[8, 20, 40, 50] {
+            assert!(decode(&RGBA_PNG[..*len]).is_err(), "Truncated at {}", len);
+        }
+        let mut corrupted = RGBA_PNG.to_vec();
+        // Inside the compressed pixels, which breaks the chunk's checksum.
+        corrupted[45] ^= 0xff;
+        assert!(decode(&corrupted).is_err());
+    }
+
+    #[test]
+    fn decodes_ppm_and_pgm() {
+        let image = decode(b"P6\n2 1\n255\n\xff\x00\x00\x00\x80\xff").unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixels(), &[255, 0, 0, 255, 0, 128, 255, 255]);
+        let image = decode(b"P5\n# A comment\n1 2\n# Another\n255\n\x00\xff").unwrap();
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_eq!(image.pixels(), &[0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn decodes_wide_ppm_samples() {
+        let image = decode(b"P6 1 1 65535 \xff\xff\x80\x00\x00\x00").unwrap();
+        assert_eq!(image.pixels(), &[255, 128, 0, 255]);
+        // Samples above the maximum are clamped.
+        let image = decode(b"P5 1 1 15 \x20").unwrap();
+        assert_eq!(image.pixels(), &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn rejects_broken_ppm() {
+        assert!(is_malformed(decode(b"P6\n2 1\n255\n\xff\x00\x00")));
+        assert!(is_malformed(decode(b"P6\n2 x\n255\n")));
+        assert!(is_malformed(decode(b"P6\n2 1\n0\n\x00\x00\x00\x00\x00\x00")));
+        assert!(is_malformed(decode(b"P6\n2 1\n70000\n")));
+        assert!(is_malformed(decode(b"P6\n1 1 255")));
+        assert!(is_malformed(decode(b"P6")));
+        assert!(is_malformed(decode(b"P5 99999999999 1 255 ")));
+        assert!(is_malformed(decode(b"P5 4294967295 4294967295 255 \x00")));
+    }
+
+    #[test]
+    fn decodes_pam() {
+        let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n\
+                    \x10\x20\x30\x40";
+        assert_eq!(decode(pam).unwrap().pixels(), &[16, 32, 48, 64]);
+        // Without a tuple type, it is guessed from the depth.
+        let pam = b"P7\n# Gray with alpha\nWIDTH 2\nHEIGHT 1\nDEPTH 2\nMAXVAL 255\nENDHDR\n\
+                    \x00\xff\xff\x00";
+        assert_eq!(decode(pam).unwrap().pixels(), &[0, 0, 0, 255, 255, 255, 255, 0]);
+    }
+
+    #[test]
+    fn rejects_broken_pam() {
+        let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n\x10";
+        assert!(is_malformed(decode(pam)));
+        assert!(is_malformed(decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\n")));
+        assert!(is_malformed(decode(b"P7\nWIDTH 1\nDEPTH 3\nMAXVAL 255\nENDHDR\n\x00\x00\x00")));
+        assert!(is_malformed(decode(b"P7\nWIDTH one\nENDHDR\n")));
+        assert!(is_malformed(decode(b"P7\nCOLORS 3\nENDHDR\n")));
+        let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 255\nTUPLTYPE RGB\nENDHDR\n\x00\x00";
+        assert!(is_malformed(decode(pam)));
+        let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nTUPLTYPE CMYK\nENDHDR\n\x00";
+        match decode(pam) {
+            Err(ImageError::Unsupported(_)) => {}
+            other => panic!("Unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn decodes_farbfeld() {
+        let bytes = farbfeld(2, 1, &[0xffff, 0, 0, 0xffff, 0x1234, 0x5678, 0x9abc, 0x8000]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.pixels(), &[255, 0, 0, 255, 0x12, 0x56, 0x9a, 0x80]);
+    }
+
+    #[test]
+    fn rejects_broken_farbfeld() {
+        let bytes = farbfeld(2, 1, &[0xffff, 0, 0, 0xffff, 0, 0, 0]);
+        assert!(is_malformed(decode(&bytes)));
+        assert!(is_malformed(decode(&bytes[..12])));
+        assert!(is_malformed(decode(&farbfeld(0xffff_ffff, 0xffff_ffff, &[]))));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn rejects_farbfeld_whose_end_overflows() {
+        // The pixels take exactly 16 bytes less than the address space, so
+        // only adding the header overflows.
+        let bytes = farbfeld(537_040_218, 4_293_613_275, &[]);
+        assert!(is_malformed(decode(&bytes)));
+    }
+}
@@ -1,17 +1,24 @@
-//! TODO Documentation
+//! 3x3 matrices, used to place things on an output when rendering.
+//!
+//! `Matrix3` does the math in Rust. The free functions are thin wrappers
+//! around the wlroots matrix functions, kept for compatibility.
 
-use Area;
+use std::ops::{Index, IndexMut, Mul, MulAssign};
+
+use {Area, Origin, Size};
 use wlroots_sys::{wl_output_transform, wlr_matrix_identity, wlr_matrix_multiply,
                   wlr_matrix_project_box, wlr_matrix_projection, wlr_matrix_rotate,
                   wlr_matrix_scale, wlr_matrix_transform, wlr_matrix_translate,
                   wlr_matrix_transpose};
 
 /// Modifies the matrix to become the identity matrix.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_identity(output: &mut [f32; 9]) {
     unsafe { wlr_matrix_identity(output.as_mut_ptr()) }
 }
 
 /// Translate the matrix in x, and y.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_translate(x: f32, y: f32) -> [f32; 9] {
     let mut output = [0.0; 9];
     unsafe {
@@ -21,6 +28,7 @@ pub fn matrix_translate(x: f32, y: f32) -> [f32; 9] {
 }
 
 /// Scale the output in the x, and y.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_scale(x: f32, y: f32) -> [f32; 9] {
     let mut output = [0.0; 9];
     unsafe {
@@ -30,6 +38,7 @@ pub fn matrix_scale(x: f32, y: f32) -> [f32; 9] {
 }
 
 /// Rotate the matrix by some amount of radians.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_rotate(mut matrix: [f32; 9], radians: f32) -> [f32; 9] {
     unsafe {
         wlr_matrix_rotate(matrix.as_mut_ptr(), radians);
@@ -38,6 +47,7 @@ pub fn matrix_rotate(mut matrix: [f32; 9], radians: f32) -> [f32; 9] {
 }
 
 /// Multiply two matrices together.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_multiply(x: [f32; 9], y: [f32; 9]) -> [f32; 9] {
     let mut output = [0.0; 9];
     unsafe {
@@ -47,6 +57,7 @@ pub fn matrix_multiply(x: [f32; 9], y: [f32; 9]) -> [f32; 9] {
 }
 
 /// Transform the matrix based on the given Wayland output transform mode.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_transform(mut matrix: [f32; 9], transform: wl_output_transform) -> [f32; 9] {
     unsafe {
         wlr_matrix_transform(matrix.as_mut_ptr(), transform);
@@ -56,6 +67,7 @@ pub fn matrix_transform(mut matrix: [f32; 9], transform: wl_output_transform) ->
 
 /// Transform the matrix based on the given Wayland output transform mode and
 /// the width and height of a texture.
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_projection(mut matrix: [f32; 9],
                          width: i32,
                          height: i32,
@@ -67,6 +79,7 @@ pub fn matrix_projection(mut matrix: [f32; 9],
     matrix
 }
 
+#[deprecated(note = "use Matrix3 instead")]
 pub fn matrix_transpose(matrix: [f32; 9]) -> [f32; 9] {
     let mut result = [0.0; 9];
     unsafe {
@@ -75,6 +88,7 @@ pub fn matrix_transpose(matrix: [f32; 9]) -> [f32; 9] {
     result
 }
 
+#[deprecated(note = "use Matrix3 instead")]
 pub fn project_box(area: Area,
                    transform: wl_output_transform,
                    rotation: f32,
@@ -90,3 +104,356 @@ pub fn project_box(area: Area,
         output
     }
 }
+
+/// A 3x3 matrix in row major order, the layout wlroots uses.
+///
+/// Multiplying `a * b` makes a matrix that applies `b` first, then `a`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix3(pub [f32; 9]);
+
+impl Matrix3 {
+    pub const IDENTITY: Matrix3 = Matrix3([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+
+    /// Makes a matrix that moves points by (x, y).
+    pub fn translation(x: f32, y: f32) -> Self {
+        Matrix3([1.0, 0.0, x, 0.0, 1.0, y, 0.0, 0.0, 1.0])
+    }
+
+    /// Makes a matrix that scales points by x horizontally and y vertically.
+    pub fn scaling(x: f32, y: f32) -> Self {
+        Matrix3([x, 0.0, 0.0, 0.0, y, 0.0, 0.0, 0.0, 1.0])
+    }
+
+    /// Makes a matrix that rotates points clockwise around the origin, in a
+    /// coordinate system where y grows downwards.
+    pub fn rotation(radians: f32) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Matrix3([cos, -sin, 0.0, sin, cos, 0.0, 0.0, 0.0, 1.0])
+    }
+
+    /// Makes a matrix that applies an output transform around the origin.
+    pub fn from_transform(transform: wl_output_transform) -> Self {
+        use self::wl_output_transform::*;
+        let (a, b, c, d) = match transform {
+            WL_OUTPUT_TRANSFORM_NORMAL => (1.0, 0.0, 0.0, 1.0),
+            WL_OUTPUT_TRANSFORM_90 => (0.0, -1.0, 1.0, 0.0),
+            WL_OUTPUT_TRANSFORM_180 => (-1.0, 0.0, 0.0, -1.0),
+            WL_OUTPUT_TRANSFORM_270 => (0.0, 1.0, -1.0, 0.0),
+            WL_OUTPUT_TRANSFORM_FLIPPED => (-1.0, 0.0, 0.0, 1.0),
+            WL_OUTPUT_TRANSFORM_FLIPPED_90 => (0.0, 1.0, 1.0, 0.0),
+            WL_OUTPUT_TRANSFORM_FLIPPED_180 => (1.0, 0.0, 0.0, -1.0),
+            WL_OUTPUT_TRANSFORM_FLIPPED_270 => (0.0, -1.0, -1.0, 0.0)
+        };
+        Matrix3([a, b, 0.0, c, d, 0.0, 0.0, 0.0, 1.0])
+    }
+
+    /// Makes the matrix that maps the pixels of an output of the given
+    /// size and transform to the coordinates the renderer draws in.
+    ///
+    /// This is how `Output::transform_matrix` is made.
+    pub fn projection(width: i32, height: i32, transform: wl_output_transform) -> Self {
+        let t = Matrix3::from_transform(transform).0;
+        let x = 2.0 / width as f32;
+        let y = 2.0 / height as f32;
+        let mut matrix = [0.0; 9];
+        matrix[0] = x * t[0];
+        matrix[1] = x * t[1];
+        matrix[3] = y * -t[3];
+        matrix[4] = y * -t[4];
+        // The sign of the translation is the opposite of the sign of the sum
+        // of the rotation and reflection, to keep the output on screen.
+        let sign = |value: f32| if value.is_sign_negative() { -1.0 } else { 1.0 };
+        matrix[2] = -sign(matrix[0] + matrix[1]);
+        matrix[5] = -sign(matrix[3] + matrix[4]);
+        matrix[8] = 1.0;
+        Matrix3(matrix)
+    }
+
+    /// Makes the matrix to draw something in `area` of an output, given the
+    /// output's projection (e.g `Output::transform_matrix`).
+    ///
+    /// The contents are transformed by `transform` within the area, and the
+    /// area is rotated by `rotation` radians around its center.
+    pub fn project_box(area: Area,
+                       transform: wl_output_transform,
+                       rotation: f32,
+                       projection: Matrix3)
+                       -> Self {
+        let Area { origin: Origin { x, y },
+                   size: Size { width, height } } = area;
+        let mut matrix = Matrix3::translation(x as f32, y as f32);
+        if rotation != 0.0 {
+            // NOTE Halved as integers, like wlroots does.
+            let (half_width, half_height) = ((width / 2) as f32, (height / 2) as f32);
+            matrix = matrix.translate(half_width, half_height)
+                           .rotate(rotation)
+                           .translate(-half_width, -half_height);
+        }
+        matrix = matrix.scale(width as f32, height as f32);
+        if transform != wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL {
+            matrix = matrix.translate(0.5, 0.5)
+                           .transform(transform)
+                           .translate(-0.5, -0.5);
+        }
+        projection * matrix
+    }
+
+    /// Moves by (x, y) before applying this matrix.
+    pub fn translate(self, x: f32, y: f32) -> Self {
+        self * Matrix3::translation(x, y)
+    }
+
+    /// Scales by (x, y) before applying this matrix.
+    pub fn scale(self, x: f32, y: f32) -> Self {
+        self * Matrix3::scaling(x, y)
+    }
+
+    /// Rotates by `radians` before applying this matrix.
+    pub fn rotate(self, radians: f32) -> Self {
+        self * Matrix3::rotation(radians)
+    }
+
+    /// Applies the output transform before applying this matrix.
+    pub fn transform(self, transform: wl_output_transform) -> Self {
+        self * Matrix3::from_transform(transform)
+    }
+
+    pub fn transpose(self) -> Self {
+        let m = self.0;
+        Matrix3([m[0], m[3], m[6], m[1], m[4], m[7], m[2], m[5], m[8]])
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = self.0;
+        m[0] * (m[4] * m[8] - m[5] * m[7]) - m[1] * (m[3] * m[8] - m[5] * m[6])
+        + m[2] * (m[3] * m[7] - m[4] * m[6])
+    }
+
+    /// Gets the matrix that undoes this one.
+    ///
+    /// Returns `None` if the matrix can't be inverted, e.g because it scales
+    /// by 0.
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None
+        }
+        let m = self.0;
+        let adjugate = [m[4] * m[8] - m[5] * m[7],
+                        m[2] * m[7] - m[1] * m[8],
+                        m[1] * m[5] - m[2] * m[4],
+                        m[5] * m[6] - m[3] * m[8],
+                        m[0] * m[8] - m[2] * m[6],
+                        m[2] * m[3] - m[0] * m[5],
+                        m[3] * m[7] - m[4] * m[6],
+                        m[1] * m[6] - m[0] * m[7],
+                        m[0] * m[4] - m[1] * m[3]];
+        let mut inverse = [0.0; 9];
+        for (value, adjugate) in inverse.iter_mut().zip(adjugate.iter()) {
+            *value = adjugate / determinant;
+        }
+        Some(Matrix3(inverse))
+    }
+
+    /// Applies the matrix to a point.
+    pub fn transform_point(&self, x: f32, y: f32) -> (f32, f32) {
+        let m = self.0;
+        let w = m[6] * x + m[7] * y + m[8];
+        let (x, y) = (m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5]);
+        if w != 0.0 && w != 1.0 {
+            (x / w, y / w)
+        } else {
+            (x, y)
+        }
+    }
+
+    /// Applies the matrix to an area, giving the smallest area that contains
+    /// all of its transformed corners.
+    pub fn transform_area(&self, area: Area) -> Area {
+        let Area { origin: Origin { x, y },
+                   size: Size { width, height } } = area;
+        let (x, y, width, height) = (x as f32, y as f32, width as f32, height as f32);
+        let corners = [self.transform_point(x, y),
+                       self.transform_point(x + width, y),
+                       self.transform_point(x, y + height),
+                       self.transform_point(x + width, y + height)];
+        let (mut x1, mut y1) = corners[0];
+        let (mut x2, mut y2) = corners[0];
+        for &(x, y) in &corners[1..] {
+            x1 = x1.min(x);
+            y1 = y1.min(y);
+            x2 = x2.max(x);
+            y2 = y2.max(y);
+        }
+        let (x1, y1) = (x1.floor() as i32, y1.floor() as i32);
+        let (x2, y2) = (x2.ceil() as i32, y2.ceil() as i32);
+        Area::new(Origin::new(x1, y1), Size::new(x2 - x1, y2 - y1))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const f32 {
+        self.0.as_ptr()
+    }
+}
+
+impl Default for Matrix3 {
+    fn default() -> Self {
+        Matrix3::IDENTITY
+    }
+}
+
+impl From<[f32; 9]> for Matrix3 {
+    fn from(matrix: [f32; 9]) -> Self {
+        Matrix3(matrix)
+    }
+}
+
+impl From<Matrix3> for [f32; 9] {
+    fn from(matrix: Matrix3) -> Self {
+        matrix.0
+    }
+}
+
+impl Mul for Matrix3 {
+    type Output = Matrix3;
+
+    fn mul(self, other: Matrix3) -> Matrix3 {
+        let (a, b) = (self.0, other.0);
+        let mut product = [0.0; 9];
+        for row in 0..3 {
+            for column in 0..3 {
+                product[row * 3 + column] = a[row * 3] * b[column]
+                                            + a[row * 3 + 1] * b[3 + column]
+                                            + a[row * 3 + 2] * b[6 + column];
+            }
+        }
+        Matrix3(product)
+    }
+}
+
+impl MulAssign for Matrix3 {
+    fn mul_assign(&mut self, other: Matrix3) {
+        *self = *self * other
+    }
+}
+
+impl Index<(usize, usize)> for Matrix3 {
+    type Output = f32;
+
+    /// Gets the value at (row, column).
+    fn index(&self, (row, column): (usize, usize)) -> &f32 {
+        assert!(row < 3 && column < 3, "Matrix3 index out of bounds");
+        &self.0[row * 3 + column]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix3 {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut f32 {
+        assert!(row < 3 && column < 3, "Matrix3 index out of bounds");
+        &mut self.0[row * 3 + column]
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use super::*;
+    use self::wl_output_transform::*;
+
+    const TRANSFORMS: [wl_output_transform; 8] = [WL_OUTPUT_TRANSFORM_NORMAL,
+                                                  WL_OUTPUT_TRANSFORM_90,
+                                                  WL_OUTPUT_TRANSFORM_180,
+                                                  WL_OUTPUT_TRANSFORM_270,
+                                                  WL_OUTPUT_TRANSFORM_FLIPPED,
+                                                  WL_OUTPUT_TRANSFORM_FLIPPED_90,
+                                                  WL_OUTPUT_TRANSFORM_FLIPPED_180,
+                                                  WL_OUTPUT_TRANSFORM_FLIPPED_270];
+
+    fn assert_close(a: Matrix3, b: Matrix3) {
+        for (x, y) in a.0.iter().zip(b.0.iter()) {
+            assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn area(x: i32, y: i32, width: i32, height: i32) -> Area {
+        Area::new(Origin::new(x, y), Size::new(width, height))
+    }
+
+    #[test]
+    fn inverse_undoes_matrix() {
+        let matrices = [Matrix3::IDENTITY,
+                        Matrix3::translation(12.0, -7.5),
+                        Matrix3::scaling(2.0, 0.25),
+                        Matrix3::rotation(0.3).translate(4.0, 5.0).scale(3.0, 2.0),
+                        Matrix3::projection(1920, 1080, WL_OUTPUT_TRANSFORM_90),
+                        Matrix3::project_box(area(10, 20, 300, 200),
+                                             WL_OUTPUT_TRANSFORM_FLIPPED_270,
+                                             1.0,
+                                             Matrix3::projection(800, 600,
+                                                                 WL_OUTPUT_TRANSFORM_NORMAL))];
+        for &matrix in &matrices {
+            let inverse = matrix.inverse().expect("Matrix should be invertible");
+            assert_close(inverse * matrix, Matrix3::IDENTITY);
+            assert_close(matrix * inverse, Matrix3::IDENTITY);
+        }
+        assert_eq!(Matrix3::scaling(0.0, 1.0).inverse(), None);
+    }
+
+    #[test]
+    fn from_transform_matches_wlroots() {
+        // wlroots' transforms[] table, in the same order as TRANSFORMS.
+        let table = [[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+                     [0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                     [-1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 1.0],
+                     [0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                     [-1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+                     [0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                     [1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 1.0],
+                     [0.0, -1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0]];
+        for (&transform, &expected) in TRANSFORMS.iter().zip(table.iter()) {
+            assert_eq!(Matrix3::from_transform(transform), Matrix3(expected));
+            let identity = Matrix3::IDENTITY.0;
+            assert_close(Matrix3::from_transform(transform),
+                         Matrix3(matrix_transform(identity, transform)));
+        }
+    }
+
+    #[test]
+    fn projection_matches_wlroots() {
+        for &transform in &TRANSFORMS {
+            for &(width, height) in &[(1920, 1080), (800, 1280), (1, 1)] {
+                let expected = matrix_projection([0.0; 9], width, height, transform);
+                assert_close(Matrix3::projection(width, height, transform), Matrix3(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn project_box_matches_wlroots() {
+        let boxes = [area(0, 0, 1920, 1080), area(10, 20, 301, 199), area(-5, 7, 64, 64)];
+        for &output_transform in &TRANSFORMS {
+            let projection = Matrix3::projection(1920, 1080, output_transform);
+            for &transform in &TRANSFORMS {
+                for &rotation in &[0.0, 0.5, -2.0] {
+                    for &area in &boxes {
+                        let expected = project_box(area, transform, rotation, projection.0);
+                        assert_close(Matrix3::project_box(area, transform, rotation, projection),
+                                     Matrix3(expected));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transform_area_turns_and_flips() {
+        let turned = Matrix3::from_transform(WL_OUTPUT_TRANSFORM_90);
+        // (x, y) goes to (-y, x).
+        assert_eq!(turned.transform_area(area(1, 2, 3, 4)), area(-6, 1, 4, 3));
+        let flipped = Matrix3::from_transform(WL_OUTPUT_TRANSFORM_FLIPPED);
+        // (x, y) goes to (-x, y).
+        assert_eq!(flipped.transform_area(area(1, 2, 3, 4)), area(-4, 2, 3, 4));
+        // Turning a 1080x1920 buffer back into the layout.
+        let matrix = Matrix3::translation(1080.0, 0.0).transform(WL_OUTPUT_TRANSFORM_90);
+        assert_eq!(matrix.transform_area(area(0, 0, 1920, 1080)), area(0, 0, 1080, 1920));
+    }
+}
@@ -2,8 +2,15 @@ mod renderer;
 mod texture;
 mod matrix;
 mod image;
+mod image_file;
+mod pixman;
+mod screenshot;
+mod wallpaper;
 
 pub use self::image::*;
+pub use self::image_file::{DecodedImage, ImageFormat};
 pub use self::matrix::*;
 pub use self::renderer::{GenericRenderer, Renderer};
+pub use self::screenshot::Screenshot;
 pub use self::texture::{Texture, TextureFormat};
+pub use self::wallpaper::{WallpaperMode, MAX_WALLPAPER_TILES};
@@ -0,0 +1,535 @@
+//! A renderer that draws on the CPU with pixman.
+//!
+//! It implements the same interface as the renderers in wlroots, so the
+//! textures of client surfaces are created by it too. Instead of drawing into
+//! the buffer of an output it draws into an image in memory, which means it
+//! works without a GPU (e.g on a CI machine running the headless backend).
+
+use libc::{self, c_int, c_void, size_t};
+use std::{cmp, mem, ptr, f64::consts::PI};
+
+use wlroots_sys::{pixman_color_t, pixman_composite_triangles, pixman_f_transform,
+                  pixman_f_transform_invert, pixman_filter_t, pixman_format_code_t,
+                  pixman_image_composite32, pixman_image_create_bits,
+                  pixman_image_create_solid_fill, pixman_image_fill_rectangles,
+                  pixman_image_get_data, pixman_image_get_format, pixman_image_get_height,
+                  pixman_image_get_stride, pixman_image_get_width,
+                  pixman_image_set_clip_region32, pixman_image_set_filter,
+                  pixman_image_set_transform, pixman_image_t,
+                  pixman_image_unref, pixman_op_t, pixman_point_fixed_t, pixman_rectangle16_t,
+                  pixman_region32_fini, pixman_region32_init_rect, pixman_region32_t,
+                  pixman_transform, pixman_transform_from_pixman_f_transform,
+                  pixman_triangle_t, wl_shm_format, wlr_box, wlr_renderer, wlr_renderer_impl,
+                  wlr_renderer_init, wlr_texture, wlr_texture_impl, wlr_texture_init};
+
+/// The number of triangles an ellipse is made of.
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// The formats textures can be made from, and what they are in pixman.
+const FORMATS: [(wl_shm_format, pixman_format_code_t); 4] =
+    [(wl_shm_format::WL_SHM_FORMAT_ARGB8888, pixman_format_code_t::PIXMAN_a8r8g8b8),
+     (wl_shm_format::WL_SHM_FORMAT_XRGB8888, pixman_format_code_t::PIXMAN_x8r8g8b8),
+     (wl_shm_format::WL_SHM_FORMAT_ABGR8888, pixman_format_code_t::PIXMAN_a8b8g8r8),
+     (wl_shm_format::WL_SHM_FORMAT_XBGR8888, pixman_format_code_t::PIXMAN_x8b8g8r8)];
+
+/// The same formats as `FORMATS`, in the layout wlroots expects.
+static SHM_FORMATS: [wl_shm_format; 4] = [wl_shm_format::WL_SHM_FORMAT_ARGB8888,
+                                          wl_shm_format::WL_SHM_FORMAT_XRGB8888,
+                                          wl_shm_format::WL_SHM_FORMAT_ABGR8888,
+                                          wl_shm_format::WL_SHM_FORMAT_XBGR8888];
+
+lazy_static! {
+    static ref RENDERER_IMPL: wlr_renderer_impl =
+        wlr_renderer_impl { begin: Some(begin),
+                            end: Some(end),
+                            clear: Some(clear),
+                            scissor: Some(scissor),
+                            render_texture_with_matrix: Some(render_texture_with_matrix),
+                            render_quad_with_matrix: Some(render_quad_with_matrix),
+                            render_ellipse_with_matrix: Some(render_ellipse_with_matrix),
+                            formats: Some(formats),
+                            format_supported: Some(format_supported),
+                            texture_from_pixels: Some(texture_from_pixels),
+                            read_pixels: Some(read_pixels),
+                            destroy: Some(destroy),
+                            ..Default::default() };
+    static ref TEXTURE_IMPL: wlr_texture_impl =
+        wlr_texture_impl { get_size: Some(texture_get_size),
+                           is_opaque: Some(texture_is_opaque),
+                           write_pixels: Some(texture_write_pixels),
+                           destroy: Some(texture_destroy),
+                           ..Default::default() };
+}
+
+#[repr(C)]
+struct PixmanRenderer {
+    /// NOTE This must be the first field, wlroots only knows about this part.
+    renderer: wlr_renderer,
+    /// The image that is drawn into, sized to the output being rendered.
+    target: *mut pixman_image_t
+}
+
+#[repr(C)]
+struct PixmanTexture {
+    /// NOTE This must be the first field, wlroots only knows about this part.
+    texture: wlr_texture,
+    image: *mut pixman_image_t,
+    opaque: bool
+}
+
+/// Makes a new pixman renderer.
+///
+/// It is freed with `wlr_renderer_destroy`.
+pub(crate) unsafe fn create() -> *mut wlr_renderer {
+    let renderer = Box::into_raw(Box::new(PixmanRenderer { renderer: mem::zeroed(),
+                                                           target: ptr::null_mut() }));
+    wlr_renderer_init(&mut (*renderer).renderer, &*RENDERER_IMPL);
+    &mut (*renderer).renderer
+}
+
+unsafe fn pixman_renderer<'a>(renderer: *mut wlr_renderer) -> &'a mut PixmanRenderer {
+    &mut *(renderer as *mut PixmanRenderer)
+}
+
+/// Gets the pixman texture, if the texture was made by this renderer.
+unsafe fn pixman_texture<'a>(texture: *const wlr_texture) -> Option<&'a mut PixmanTexture> {
+    if (*texture).impl_ != &*TEXTURE_IMPL as *const _ {
+        return None
+    }
+    Some(&mut *(texture as *mut PixmanTexture))
+}
+
+fn pixman_format(format: wl_shm_format) -> Option<pixman_format_code_t> {
+    FORMATS.iter()
+           .find(|&&(shm_format, _)| shm_format == format)
+           .map(|&(_, pixman_format)| pixman_format)
+}
+
+fn pixman_color(color: *const f32) -> pixman_color_t {
+    let channel = |index| unsafe {
+        let value = (*color.offset(index)).max(0.0).min(1.0);
+        (value * 65535.0).round() as u16
+    };
+    // NOTE Colors are premultiplied, both in wlroots and in pixman.
+    pixman_color_t { red: channel(0),
+                     green: channel(1),
+                     blue: channel(2),
+                     alpha: channel(3) }
+}
+
+fn to_fixed((x, y): (f64, f64)) -> pixman_point_fixed_t {
+    pixman_point_fixed_t { x: (x * 65536.0).round() as i32,
+                           y: (y * 65536.0).round() as i32 }
+}
+
+fn multiply(a: &[f64; 9], b: &[f64; 9]) -> [f64; 9] {
+    let mut product = [0.0; 9];
+    for row in 0..3 {
+        for column in 0..3 {
+            product[row * 3 + column] = (0..3).map(|i| a[row * 3 + i] * b[i * 3 + column])
+                                              .sum();
+        }
+    }
+    product
+}
+
+fn apply(matrix: &[f64; 9], (x, y): (f64, f64)) -> (f64, f64) {
+    (matrix[0] * x + matrix[1] * y + matrix[2], matrix[3] * x + matrix[4] * y + matrix[5])
+}
+
+/// Maps the unit square to pixels of the target.
+///
+/// The matrices given to the renderer map the unit square to normalized
+/// device coordinates, where the top left of the output is (-1, 1).
+unsafe fn unit_to_target(target: *mut pixman_image_t, matrix: *const f32) -> [f64; 9] {
+    let width = pixman_image_get_width(target) as f64;
+    let height = pixman_image_get_height(target) as f64;
+    let viewport = [width / 2.0,
+                    0.0,
+                    width / 2.0,
+                    0.0,
+                    -height / 2.0,
+                    height / 2.0,
+                    0.0,
+                    0.0,
+                    1.0];
+    let mut matrix_f64 = [0.0; 9];
+    for (index, value) in matrix_f64.iter_mut().enumerate() {
+        *value = *matrix.offset(index as isize) as f64
+    }
+    multiply(&viewport, &matrix_f64)
+}
+
+/// Composites a solid color over the target through the given triangles.
+unsafe fn fill_triangles(target: *mut pixman_image_t, color: *const f32, points: &[(f64, f64)]) {
+    let triangles = points.chunks(3)
+                          .map(|triangle| {
+                                   pixman_triangle_t { p1: to_fixed(triangle[0]),
+                                                       p2: to_fixed(triangle[1]),
+                                                       p3: to_fixed(triangle[2]) }
+                               })
+                          .collect::<Vec<_>>();
+    let color = pixman_color(color);
+    let source = pixman_image_create_solid_fill(&color);
+    pixman_composite_triangles(pixman_op_t::PIXMAN_OP_OVER,
+                               source,
+                               target,
+                               pixman_format_code_t::PIXMAN_a8,
+                               0,
+                               0,
+                               0,
+                               0,
+                               triangles.len() as c_int,
+                               triangles.as_ptr());
+    pixman_image_unref(source);
+}
+
+unsafe extern "C" fn begin(renderer: *mut wlr_renderer, width: u32, height: u32) {
+    let renderer = pixman_renderer(renderer);
+    let target = renderer.target;
+    if !target.is_null() && pixman_image_get_width(target) == width as c_int
+       && pixman_image_get_height(target) == height as c_int
+    {
+        pixman_image_set_clip_region32(target, ptr::null_mut());
+        return
+    }
+    if !target.is_null() {
+        pixman_image_unref(target);
+    }
+    renderer.target = pixman_image_create_bits(pixman_format_code_t::PIXMAN_a8r8g8b8,
+                                               width as c_int,
+                                               height as c_int,
+                                               ptr::null_mut(),
+                                               0);
+    if renderer.target.is_null() {
+        wlr_log!(L_ERROR, "Could not allocate a {}x{} render target", width, height);
+    }
+}
+
+unsafe extern "C" fn end(_renderer: *mut wlr_renderer) {}
+
+unsafe extern "C" fn clear(renderer: *mut wlr_renderer, color: *const f32) {
+    let target = pixman_renderer(renderer).target;
+    if target.is_null() {
+        return
+    }
+    let color = pixman_color(color);
+    let rectangle = pixman_rectangle16_t { x: 0,
+                                           y: 0,
+                                           width: pixman_image_get_width(target) as u16,
+                                           height: pixman_image_get_height(target) as u16 };
+    pixman_image_fill_rectangles(pixman_op_t::PIXMAN_OP_SRC, target, &color, 1, &rectangle);
+}
+
+unsafe extern "C" fn scissor(renderer: *mut wlr_renderer, area: *mut wlr_box) {
+    let target = pixman_renderer(renderer).target;
+    if target.is_null() {
+        return
+    }
+    if area.is_null() {
+        pixman_image_set_clip_region32(target, ptr::null_mut());
+        return
+    }
+    let mut region: pixman_region32_t = mem::zeroed();
+    pixman_region32_init_rect(&mut region,
+                              (*area).x,
+                              (*area).y,
+                              cmp::max((*area).width, 0) as u32,
+                              cmp::max((*area).height, 0) as u32);
+    pixman_image_set_clip_region32(target, &mut region);
+    pixman_region32_fini(&mut region);
+}
+
+unsafe extern "C" fn render_texture_with_matrix(renderer: *mut wlr_renderer,
+                                                texture: *mut wlr_texture,
+                                                matrix: *const f32,
+                                                alpha: f32)
+                                                -> bool {
+    let target = pixman_renderer(renderer).target;
+    let texture = match pixman_texture(texture) {
+        Some(texture) => texture,
+        None => {
+            wlr_log!(L_ERROR, "Texture was not made by the pixman renderer");
+            return false
+        }
+    };
+    if target.is_null() {
+        return false
+    }
+    let image = texture.image;
+    let (texture_width, texture_height) =
+        (pixman_image_get_width(image) as f64, pixman_image_get_height(image) as f64);
+    // Maps the pixels of the texture to the pixels of the target.
+    let texture_to_unit = [1.0 / texture_width,
+                           0.0,
+                           0.0,
+                           0.0,
+                           1.0 / texture_height,
+                           0.0,
+                           0.0,
+                           0.0,
+                           1.0];
+    let texture_to_target = multiply(&unit_to_target(target, matrix), &texture_to_unit);
+    // pixman maps from the target to the source, so the matrix is inverted.
+    let mut forward: pixman_f_transform = mem::zeroed();
+    for row in 0..3 {
+        forward.m[row].copy_from_slice(&texture_to_target[row * 3..row * 3 + 3]);
+    }
+    let mut inverse: pixman_f_transform = mem::zeroed();
+    let mut transform: pixman_transform = mem::zeroed();
+    if pixman_f_transform_invert(&mut inverse, &forward) == 0
+       || pixman_transform_from_pixman_f_transform(&mut transform, &inverse) == 0
+    {
+        // The texture is squashed to nothing, so there is nothing to draw.
+        return true
+    }
+    pixman_image_set_transform(image, &transform);
+    pixman_image_set_filter(image, pixman_filter_t::PIXMAN_FILTER_BILINEAR, ptr::null(), 0);
+    // Only composite the part of the target the texture ends up on.
+    let corners = [(0.0, 0.0),
+                   (texture_width, 0.0),
+                   (0.0, texture_height),
+                   (texture_width, texture_height)].iter()
+                                                   .map(|&corner| {
+                                                            apply(&texture_to_target, corner)
+                                                        })
+                                                   .collect::<Vec<_>>();
+    let min_x = corners.iter().fold(::std::f64::MAX, |min, &(x, _)| min.min(x));
+    let min_y = corners.iter().fold(::std::f64::MAX, |min, &(_, y)| min.min(y));
+    let max_x = corners.iter().fold(::std::f64::MIN, |max, &(x, _)| max.max(x));
+    let max_y = corners.iter().fold(::std::f64::MIN, |max, &(_, y)| max.max(y));
+    let x = cmp::max(min_x.floor() as i32, 0);
+    let y = cmp::max(min_y.floor() as i32, 0);
+    let width = cmp::min(max_x.ceil() as i32, pixman_image_get_width(target)) - x;
+    let height = cmp::min(max_y.ceil() as i32, pixman_image_get_height(target)) - y;
+    if width > 0 && height > 0 {
+        let mask = if alpha < 1.0 {
+            let color = [alpha; 4];
+            pixman_image_create_solid_fill(&pixman_color(color.as_ptr()))
+        } else {
+            ptr::null_mut()
+        };
+        pixman_image_composite32(pixman_op_t::PIXMAN_OP_OVER,
+                                 image,
+                                 mask,
+                                 target,
+                                 x,
+                                 y,
+                                 0,
+                                 0,
+                                 x,
+                                 y,
+                                 width,
+                                 height);
+        if !mask.is_null() {
+            pixman_image_unref(mask);
+        }
+    }
+    pixman_image_set_transform(image, ptr::null());
+    true
+}
+
+unsafe extern "C" fn render_quad_with_matrix(renderer: *mut wlr_renderer,
+                                             color: *const f32,
+                                             matrix: *const f32) {
+    let target = pixman_renderer(renderer).target;
+    if target.is_null() {
+        return
+    }
+    let matrix = unit_to_target(target, matrix);
+    let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter()
+                                                                  .map(|&corner| {
+                                                                           apply(&matrix, corner)
+                                                                       })
+                                                                  .collect::<Vec<_>>();
+    fill_triangles(target,
+                   color,
+                   &[corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]]);
+}
+
+unsafe extern "C" fn render_ellipse_with_matrix(renderer: *mut wlr_renderer,
+                                                color: *const f32,
+                                                matrix: *const f32) {
+    let target = pixman_renderer(renderer).target;
+    if target.is_null() {
+        return
+    }
+    // The ellipse fills the unit square, and is drawn as a fan around its center.
+    let matrix = unit_to_target(target, matrix);
+    let point = |segment: usize| {
+        let angle = 2.0 * PI * segment as f64 / ELLIPSE_SEGMENTS as f64;
+        apply(&matrix, (0.5 + 0.5 * angle.cos(), 0.5 + 0.5 * angle.sin()))
+    };
+    let center = apply(&matrix, (0.5, 0.5));
+    let mut points = Vec::with_capacity(ELLIPSE_SEGMENTS * 3);
+    for segment in 0..ELLIPSE_SEGMENTS {
+        points.push(center);
+        points.push(point(segment));
+        points.push(point(segment + 1));
+    }
+    fill_triangles(target, color, &points);
+}
+
+unsafe extern "C" fn formats(_renderer: *mut wlr_renderer,
+                             len: *mut size_t)
+                             -> *const wl_shm_format {
+    *len = SHM_FORMATS.len();
+    SHM_FORMATS.as_ptr()
+}
+
+unsafe extern "C" fn format_supported(_renderer: *mut wlr_renderer,
+                                      format: wl_shm_format)
+                                      -> bool {
+    pixman_format(format).is_some()
+}
+
+unsafe extern "C" fn texture_from_pixels(_renderer: *mut wlr_renderer,
+                                         format: wl_shm_format,
+                                         stride: u32,
+                                         width: u32,
+                                         height: u32,
+                                         data: *const c_void)
+                                         -> *mut wlr_texture {
+    let pixman_format = match pixman_format(format) {
+        Some(pixman_format) => pixman_format,
+        None => {
+            wlr_log!(L_ERROR, "Unsupported texture format {:?}", format);
+            return ptr::null_mut()
+        }
+    };
+    let image = pixman_image_create_bits(pixman_format,
+                                         width as c_int,
+                                         height as c_int,
+                                         ptr::null_mut(),
+                                         0);
+    if image.is_null() {
+        wlr_log!(L_ERROR, "Could not allocate a {}x{} texture", width, height);
+        return ptr::null_mut()
+    }
+    let opaque = match pixman_format {
+        pixman_format_code_t::PIXMAN_x8r8g8b8 | pixman_format_code_t::PIXMAN_x8b8g8r8 => true,
+        _ => false
+    };
+    let texture = Box::into_raw(Box::new(PixmanTexture { texture: mem::zeroed(),
+                                                         image,
+                                                         opaque }));
+    wlr_texture_init(&mut (*texture).texture, &*TEXTURE_IMPL);
+    texture_write_pixels(&mut (*texture).texture,
+                         format,
+                         stride,
+                         width,
+                         height,
+                         0,
+                         0,
+                         0,
+                         0,
+                         data);
+    &mut (*texture).texture
+}
+
+unsafe extern "C" fn read_pixels(renderer: *mut wlr_renderer,
+                                 format: wl_shm_format,
+                                 stride: u32,
+                                 width: u32,
+                                 height: u32,
+                                 src_x: u32,
+                                 src_y: u32,
+                                 dst_x: u32,
+                                 dst_y: u32,
+                                 data: *mut c_void)
+                                 -> bool {
+    let target = pixman_renderer(renderer).target;
+    let pixman_format = match pixman_format(format) {
+        Some(pixman_format) => pixman_format,
+        None => return false
+    };
+    if target.is_null() {
+        return false
+    }
+    // Wrap the destination so pixman can convert to its format while copying.
+    let destination = pixman_image_create_bits(pixman_format,
+                                               (dst_x + width) as c_int,
+                                               (dst_y + height) as c_int,
+                                               data as *mut u32,
+                                               stride as c_int);
+    if destination.is_null() {
+        return false
+    }
+    pixman_image_composite32(pixman_op_t::PIXMAN_OP_SRC,
+                             target,
+                             ptr::null_mut(),
+                             destination,
+                             src_x as i32,
+                             src_y as i32,
+                             0,
+                             0,
+                             dst_x as i32,
+                             dst_y as i32,
+                             width as i32,
+                             height as i32);
+    pixman_image_unref(destination);
+    true
+}
+
+unsafe extern "C" fn destroy(renderer: *mut wlr_renderer) {
+    let renderer = Box::from_raw(renderer as *mut PixmanRenderer);
+    if !renderer.target.is_null() {
+        pixman_image_unref(renderer.target);
+    }
+}
+
+unsafe extern "C" fn texture_get_size(texture: *const wlr_texture,
+                                      width: *mut c_int,
+                                      height: *mut c_int) {
+    let image = (*(texture as *const PixmanTexture)).image;
+    *width = pixman_image_get_width(image);
+    *height = pixman_image_get_height(image);
+}
+
+unsafe extern "C" fn texture_is_opaque(texture: *mut wlr_texture) -> bool {
+    (*(texture as *mut PixmanTexture)).opaque
+}
+
+unsafe extern "C" fn texture_write_pixels(texture: *mut wlr_texture,
+                                          format: wl_shm_format,
+                                          stride: u32,
+                                          width: u32,
+                                          height: u32,
+                                          src_x: u32,
+                                          src_y: u32,
+                                          dst_x: u32,
+                                          dst_y: u32,
+                                          data: *const c_void)
+                                          -> bool {
+    let texture = &mut *(texture as *mut PixmanTexture);
+    let image = texture.image;
+    if pixman_format(format) != Some(pixman_image_get_format(image)) {
+        wlr_log!(L_ERROR, "Can't change the format of a texture");
+        return false
+    }
+    if dst_x + width > pixman_image_get_width(image) as u32
+       || dst_y + height > pixman_image_get_height(image) as u32
+    {
+        wlr_log!(L_ERROR, "Pixels written outside of the texture");
+        return false
+    }
+    // All of the supported formats are 4 bytes per pixel.
+    let image_stride = pixman_image_get_stride(image) as usize;
+    let image_data = pixman_image_get_data(image) as *mut u8;
+    let data = data as *const u8;
+    for row in 0..height as usize {
+        let source = data.offset(((src_y as usize + row) * stride as usize
+                                  + src_x as usize * 4) as isize);
+        let destination = image_data.offset(((dst_y as usize + row) * image_stride
+                                             + dst_x as usize * 4) as isize);
+        libc::memcpy(destination as *mut c_void,
+                     source as *const c_void,
+                     width as usize * 4);
+    }
+    true
+}
+
+unsafe extern "C" fn texture_destroy(texture: *mut wlr_texture) {
+    let texture = Box::from_raw(texture as *mut PixmanTexture);
+    pixman_image_unref(texture.image);
+}
@@ -1,16 +1,19 @@
 //! TODO Documentation
 
-use std::time::Duration;
+use std::{ptr, mem::ManuallyDrop, time::Duration};
 
 use libc::{c_float, c_int, c_void};
 
-use {Area, Output, PixmanRegion};
-use render::Texture;
-use wlroots_sys::{wl_shm_format, wlr_backend, wlr_backend_get_renderer,
+use {Area, Origin, Output, PixmanRegion, Size, Surface, SurfaceHandle, XWaylandSurface,
+     XdgShellSurface, XdgV6ShellSurface};
+use extensions::presentation;
+use render::{pixman, Matrix3, Screenshot, Texture, TextureFormat, WallpaperMode};
+use wlroots_sys::{wl_shm_format, wlr_backend, wlr_backend_get_renderer, wlr_box,
                   wlr_render_ellipse_with_matrix, wlr_render_quad_with_matrix, wlr_render_rect,
                   wlr_render_texture, wlr_render_texture_with_matrix, wlr_renderer,
                   wlr_renderer_begin, wlr_renderer_clear, wlr_renderer_destroy, wlr_renderer_end,
-                  wlr_texture_from_pixels};
+                  wlr_output, wlr_output_make_current, wlr_output_transform_invert,
+                  wlr_renderer_read_pixels, wlr_renderer_scissor, wlr_texture_from_pixels};
 
 /// A generic interface for rendering to the screen.
 ///
@@ -18,29 +21,64 @@ use wlroots_sys::{wl_shm_format, wlr_backend, wlr_backend_get_renderer,
 /// at the same time.
 #[derive(Debug)]
 pub struct GenericRenderer {
-    renderer: *mut wlr_renderer
+    renderer: *mut wlr_renderer,
+    /// Set for the pixman renderer, which is owned by this structure
+    /// instead of by the backend.
+    software: bool
 }
 
 /// The state machine type that allows you to manipulate a screen and
 /// its buffer.
 ///
 /// When this structure is dropped it automatically calls wlr_renderer_end
-/// and swaps the buffers.
+/// and swaps the buffers. The software renderer doesn't draw into the
+/// output's buffers, so for it nothing is swapped.
 #[derive(Debug)]
 pub struct Renderer<'output> {
     renderer: *mut wlr_renderer,
+    software: bool,
+    /// Whether the buffers are swapped when this is dropped.
+    swap: bool,
     pub damage: Option<(PixmanRegion, Duration)>,
     pub output: &'output mut Output
 }
 
 impl GenericRenderer {
     /// Make a gles2 renderer.
-    pub(crate) unsafe fn gles2_renderer(backend: *mut wlr_backend) -> Self {
+    ///
+    /// Returns `None` if the backend could not provide a renderer.
+    pub(crate) unsafe fn gles2_renderer(backend: *mut wlr_backend) -> Option<Self> {
         let renderer = wlr_backend_get_renderer(backend);
         if renderer.is_null() {
-            panic!("Could not construct GLES2 renderer");
+            None
+        } else {
+            Some(GenericRenderer { renderer,
+                                   software: false })
+        }
+    }
+
+    /// Make a renderer that draws on the CPU using pixman.
+    ///
+    /// It doesn't need a GPU or even a backend, because it draws into an
+    /// image in memory instead of into the output. That image is sized to the
+    /// output passed to `render` and holds the last frame that was rendered.
+    ///
+    /// Textures made by this renderer can only be drawn by it, and vice versa.
+    pub fn pixman_renderer() -> Option<Self> {
+        unsafe {
+            let renderer = pixman::create();
+            if renderer.is_null() {
+                None
+            } else {
+                Some(GenericRenderer { renderer,
+                                       software: true })
+            }
         }
-        GenericRenderer { renderer }
+    }
+
+    /// Determines if this renderer draws on the CPU instead of the GPU.
+    pub fn is_software(&self) -> bool {
+        self.software
     }
 
     /// Make the `Renderer` state machine type.
@@ -53,15 +91,39 @@ impl GenericRenderer {
         where T: Into<Option<(PixmanRegion, Duration)>>
     {
         unsafe {
-            output.make_current();
+            if !self.software {
+                output.make_current();
+            }
             let (width, height) = output.size();
             wlr_renderer_begin(self.renderer, width, height);
             Renderer { renderer: self.renderer,
+                       software: self.software,
+                       swap: true,
                        damage: damage.into(),
                        output }
         }
     }
 
+    /// Make the `Renderer` state machine type, for an output whose buffers
+    /// are managed by the caller.
+    ///
+    /// The output must already be current (e.g through
+    /// `OutputDamage::make_current`), and the buffers are not swapped when
+    /// the `Renderer` is dropped.
+    pub(crate) fn render_unswapped<'output>(&mut self,
+                                            output: &'output mut Output)
+                                            -> Renderer<'output> {
+        unsafe {
+            let (width, height) = output.size();
+            wlr_renderer_begin(self.renderer, width, height);
+            Renderer { renderer: self.renderer,
+                       software: self.software,
+                       swap: false,
+                       damage: None,
+                       output }
+        }
+    }
+
     /// Create a texture using this renderer.
     pub fn create_texture_from_pixels(&mut self,
                                       format: wl_shm_format,
@@ -80,14 +142,41 @@ impl GenericRenderer {
         }
     }
 
-    pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_renderer {
-        self.renderer
+    /// Reads back the whole output, see `Output::capture`.
+    pub(crate) fn capture(&mut self, output: &mut Output) -> Option<Screenshot> {
+        unsafe { self.capture_ptr(output.as_ptr()) }
     }
-}
 
-impl Drop for GenericRenderer {
-    fn drop(&mut self) {
-        unsafe { wlr_renderer_destroy(self.renderer) }
+    /// Makes a copy of this renderer that doesn't own it.
+    ///
+    /// This is for signals that need the renderer while it is already
+    /// borrowed, e.g while an output swaps its buffers.
+    pub(crate) unsafe fn alias(&self) -> ManuallyDrop<GenericRenderer> {
+        ManuallyDrop::new(GenericRenderer { renderer: self.renderer,
+                                            software: self.software })
+    }
+
+    /// Reads back the whole output, without borrowing it.
+    ///
+    /// This is for when the `Output` is already borrowed, e.g while it swaps
+    /// its buffers.
+    pub(crate) unsafe fn capture_ptr(&mut self, output: *mut wlr_output) -> Option<Screenshot> {
+        if !self.software {
+            let mut buffer_age = -1;
+            wlr_output_make_current(output, &mut buffer_age);
+        }
+        let (width, height) = ((*output).width, (*output).height);
+        let area = Area::new(Origin::new(0, 0), Size::new(width, height));
+        let pixels = read_pixels(self.renderer,
+                                 self.software,
+                                 height,
+                                 area,
+                                 TextureFormat::ARGB8888)?;
+        Screenshot::new(width as u32, height as u32, pixels)
+    }
+
+    pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_renderer {
+        self.renderer
     }
 }
 
@@ -110,6 +199,43 @@ impl<'output> Renderer<'output> {
         }
     }
 
+    /// Reads back the pixels in `area` of the output, which is in output
+    /// buffer coordinates.
+    ///
+    /// The rows are returned from the top down, tightly packed.
+    ///
+    /// Returns `None` if the format is not supported by the renderer or
+    /// the pixels could not be read.
+    pub fn read_pixels(&mut self, area: Area, format: TextureFormat) -> Option<Vec<u8>> {
+        let (_, height) = self.output.size();
+        unsafe { read_pixels(self.renderer, self.software, height, area, format) }
+    }
+
+    /// Restricts drawing to `area` of the output, in output buffer
+    /// coordinates.
+    ///
+    /// Passing `None` lets the whole output be drawn to again.
+    pub fn scissor<T: Into<Option<Area>>>(&mut self, area: T) {
+        unsafe {
+            match area.into() {
+                Some(area) => {
+                    // NOTE
+                    // OpenGL counts rows from the bottom of the buffer, so
+                    // the area is flipped for the gles2 renderer.
+                    let area = if self.software {
+                        area
+                    } else {
+                        let (_, height) = self.output.size();
+                        flip_rows(area, height)
+                    };
+                    let mut area: wlr_box = area.into();
+                    wlr_renderer_scissor(self.renderer, &mut area)
+                }
+                None => wlr_renderer_scissor(self.renderer, ptr::null_mut())
+            }
+        }
+    }
+
     pub fn clear(&mut self, float: [f32; 4]) {
         unsafe { wlr_renderer_clear(self.renderer, float.as_ptr()) }
     }
@@ -117,7 +243,7 @@ impl<'output> Renderer<'output> {
     /// Renders the requseted texture.
     pub fn render_texture(&mut self,
                           texture: &Texture,
-                          projection: [f32; 9],
+                          projection: Matrix3,
                           x: c_int,
                           y: c_int,
                           alpha: c_float)
@@ -147,36 +273,161 @@ impl<'output> Renderer<'output> {
     /// ```
     ///
     /// This will render the texture at <123, 321>.
-    pub fn render_texture_with_matrix(&mut self, texture: &Texture, matrix: [f32; 9]) -> bool {
+    pub fn render_texture_with_matrix(&mut self, texture: &Texture, matrix: Matrix3) -> bool {
         // TODO FIXME Add alpha as param
         unsafe {
             wlr_render_texture_with_matrix(self.renderer, texture.as_ptr(), matrix.as_ptr(), 1.0)
         }
     }
 
+    /// Draws the texture across the output, see `WallpaperMode`.
+    ///
+    /// With `Fit` and `Center` only the image is drawn, so clear the output
+    /// first if it isn't covered by something else.
+    pub fn render_wallpaper(&mut self, texture: &Texture, mode: WallpaperMode) {
+        let (width, height) = texture.size();
+        for matrix in mode.matrices(Size::new(width, height), self.output) {
+            self.render_texture_with_matrix(texture, matrix);
+        }
+    }
+
+    /// Renders a surface along with its subsurfaces, with the top left
+    /// corner of the surface at `layout_pos` in the output layout.
+    ///
+    /// Each surface is drawn at its size in the layout, taking its buffer
+    /// scale and transform into account, and is then scaled and transformed
+    /// to match the output.
+    ///
+    /// Subsurfaces that are already borrowed (e.g through `run`) are skipped.
+    pub fn render_surface_tree(&mut self, surface: &mut Surface, layout_pos: Origin) {
+        let origin = self.output_local(layout_pos);
+        self.render_surface_tree_at(surface, origin)
+    }
+
+    /// Renders an xdg shell surface along with its subsurfaces and popups.
+    ///
+    /// `layout_pos` is where the top left corner of the main surface goes,
+    /// which is not the top left corner of its window geometry if the client
+    /// draws decorations such as shadows.
+    pub fn render_xdg_surface_tree(&mut self,
+                                   shell_surface: &mut XdgShellSurface,
+                                   layout_pos: Origin) {
+        let origin = self.output_local(layout_pos);
+        shell_surface.for_each_surface(&mut |surface: SurfaceHandle, sx, sy| {
+            let origin = Origin::new(origin.x + sx, origin.y + sy);
+            surface.run(|surface| self.render_surface_at(surface, origin)).ok();
+        })
+    }
+
+    /// Renders an xdg shell v6 surface along with its subsurfaces and popups.
+    ///
+    /// `layout_pos` is where the top left corner of the main surface goes,
+    /// see `render_xdg_surface_tree`.
+    pub fn render_xdg_v6_surface_tree(&mut self,
+                                      shell_surface: &mut XdgV6ShellSurface,
+                                      layout_pos: Origin) {
+        let origin = self.output_local(layout_pos);
+        shell_surface.for_each_surface(&mut |surface: SurfaceHandle, sx, sy| {
+            let origin = Origin::new(origin.x + sx, origin.y + sy);
+            surface.run(|surface| self.render_surface_at(surface, origin)).ok();
+        })
+    }
+
+    /// Renders an XWayland surface along with its subsurfaces.
+    ///
+    /// X11 has no popups, menus and tooltips are override redirect windows
+    /// instead. The ones that are mapped children of this surface are drawn
+    /// on top of it, placed relative to it.
+    pub fn render_xwayland_surface_tree(&mut self,
+                                        surface: &mut XWaylandSurface,
+                                        layout_pos: Origin) {
+        let (x, y) = surface.coords();
+        surface.surface()
+               .run(|surface| self.render_surface_tree(surface, layout_pos))
+               .ok();
+        for child in surface.children() {
+            child.run(|child| {
+                          if !child.mapped() || !child.override_redirect() {
+                              return
+                          }
+                          let (child_x, child_y) = child.coords();
+                          let child_pos = Origin::new(layout_pos.x + child_x as i32 - x as i32,
+                                                      layout_pos.y + child_y as i32 - y as i32);
+                          self.render_xwayland_surface_tree(child, child_pos)
+                      })
+                 .ok();
+        }
+    }
+
+    /// Renders a surface along with its subsurfaces, with its top left corner
+    /// at `origin` relative to the output, in layout coordinates.
+    pub(crate) fn render_surface_tree_at(&mut self, surface: &mut Surface, origin: Origin) {
+        surface.for_each_surface(|surface, sx, sy| {
+                                     let origin = Origin::new(origin.x + sx, origin.y + sy);
+                                     self.render_surface_at(surface, origin)
+                                 })
+    }
+
+    /// Renders only the given surface, with its top left corner at `origin`
+    /// relative to the output, in layout coordinates.
+    fn render_surface_at(&mut self, surface: &mut Surface, origin: Origin) {
+        if !surface.has_buffer() {
+            return
+        }
+        let ((width, height), transform) = {
+            let state = surface.current_state();
+            (state.size(), state.transform())
+        };
+        let area = Area::new(origin, Size::new(width, height)).scale(self.output.scale());
+        // The surface transform says how the buffer was transformed by the
+        // client, drawing it means undoing that.
+        let transform = unsafe { wlr_output_transform_invert(transform) };
+        let matrix = Matrix3::project_box(area, transform, 0.0, self.output.transform_matrix());
+        self.render_texture_with_matrix(&surface.texture(), matrix);
+        if !self.software {
+            unsafe { presentation::surface_sampled(surface.as_ptr(), self.output.as_ptr()) }
+        }
+    }
+
+    /// Converts a position in the output layout to one relative to the output.
+    fn output_local(&self, layout_pos: Origin) -> Origin {
+        let (output_x, output_y) = self.output.layout_space_pos();
+        Origin::new(layout_pos.x - output_x, layout_pos.y - output_y)
+    }
+
     /// Renders a solid quad in the specified color.
-    pub fn render_colored_quad(&mut self, color: [f32; 4], matrix: [f32; 9]) {
+    pub fn render_colored_quad(&mut self, color: [f32; 4], matrix: Matrix3) {
         unsafe { wlr_render_quad_with_matrix(self.renderer, color.as_ptr(), matrix.as_ptr()) }
     }
 
     /// Renders a solid ellipse in the specified color.
-    pub fn render_colored_ellipse(&mut self, color: [f32; 4], matrix: [f32; 9]) {
+    pub fn render_colored_ellipse(&mut self, color: [f32; 4], matrix: Matrix3) {
         unsafe { wlr_render_ellipse_with_matrix(self.renderer, color.as_ptr(), matrix.as_ptr()) }
     }
 
     /// Renders a solid rectangle in the specified color.
-    pub fn render_colored_rect(&mut self, area: Area, color: [f32; 4], matrix: [f32; 9]) {
+    pub fn render_colored_rect(&mut self, area: Area, color: [f32; 4], matrix: Matrix3) {
         unsafe { wlr_render_rect(self.renderer, &area.into(), color.as_ptr(), matrix.as_ptr()) }
     }
 }
 
+impl Drop for GenericRenderer {
+    fn drop(&mut self) {
+        if self.software {
+            unsafe { wlr_renderer_destroy(self.renderer) }
+        }
+    }
+}
+
 impl<'output> Drop for Renderer<'output> {
     fn drop(&mut self) {
         unsafe {
-            if let Some((mut damage, when)) = self.damage.take() {
-                self.output.swap_buffers(Some(when), Some(&mut damage));
-            } else {
-                self.output.swap_buffers(None, None);
+            if self.swap && !self.software {
+                if let Some((mut damage, when)) = self.damage.take() {
+                    self.output.swap_buffers(Some(when), Some(&mut damage));
+                } else {
+                    self.output.swap_buffers(None, None);
+                }
             }
             wlr_renderer_end(self.renderer);
         }
@@ -198,3 +449,54 @@ unsafe fn create_texture_from_pixels(renderer: *mut wlr_renderer,
         Some(Texture::from_ptr(texture))
     }
 }
+
+/// Flips an area of a buffer `output_height` rows high upside down, to go
+/// between rows counted from the top and from the bottom.
+fn flip_rows(area: Area, output_height: c_int) -> Area {
+    let y = output_height - area.origin.y - area.size.height;
+    area.with_origin(Origin::new(area.origin.x, y))
+}
+
+unsafe fn read_pixels(renderer: *mut wlr_renderer,
+                      software: bool,
+                      output_height: c_int,
+                      area: Area,
+                      format: TextureFormat)
+                      -> Option<Vec<u8>> {
+    let Area { origin: Origin { x, y },
+               size: Size { width, height } } = area;
+    if x < 0 || y < 0 || width <= 0 || height <= 0 {
+        return None
+    }
+    let stride = width as usize * format.bytes_per_pixel()? as usize;
+    let mut pixels = vec![0u8; stride * height as usize];
+    // NOTE
+    // OpenGL counts rows from the bottom of the buffer, so the area is
+    // flipped for the gles2 renderer and the rows are put back in order below.
+    let src_y = if software {
+        y
+    } else {
+        flip_rows(area, output_height).origin.y
+    };
+    if src_y < 0 {
+        return None
+    }
+    if !wlr_renderer_read_pixels(renderer,
+                                 format.into(),
+                                 stride as u32,
+                                 width as u32,
+                                 height as u32,
+                                 x as u32,
+                                 src_y as u32,
+                                 0,
+                                 0,
+                                 pixels.as_mut_ptr() as *mut c_void)
+    {
+        return None
+    }
+    if !software {
+        let rows = pixels.chunks(stride).rev().flat_map(|row| row.iter().cloned());
+        pixels = rows.collect();
+    }
+    Some(pixels)
+}
@@ -0,0 +1,86 @@
+//! Frames read back from an output, and writers to save them as images.
+
+use std::io::{self, Write};
+
+use png::{self, HasParameters};
+
+/// The contents of an output, read back with `Output::capture`.
+///
+/// The pixels are stored row by row from the top left, in the
+/// `TextureFormat::ARGB8888` format (i.e the bytes of a pixel are blue,
+/// green, red and alpha).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>
+}
+
+impl Screenshot {
+    /// Makes a screenshot out of pixels in the `ARGB8888` format.
+    ///
+    /// Returns `None` if there are not exactly `width * height` pixels.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Option<Self> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return None
+        }
+        Some(Screenshot { width,
+                          height,
+                          pixels })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Gets the raw pixels, in the `ARGB8888` format.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    /// Gets the pixel at the given position as `[red, green, blue, alpha]`.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None
+        }
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+        let pixel = &self.pixels[index..index + 4];
+        Some([pixel[2], pixel[1], pixel[0], pixel[3]])
+    }
+
+    /// Writes the screenshot as a binary PPM image.
+    ///
+    /// PPM has no alpha channel, so it is dropped.
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.rgb())?;
+        writer.flush()
+    }
+
+    /// Writes the screenshot as a PNG image.
+    ///
+    /// Outputs are opaque, so the alpha channel is dropped.
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgb())?;
+        Ok(())
+    }
+
+    fn rgb(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.width as usize * self.height as usize * 3);
+        for pixel in self.pixels.chunks(4) {
+            rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        rgb
+    }
+}
@@ -2,6 +2,7 @@ use libc::c_int;
 use wlroots_sys::{wl_shm_format, wlr_texture, wlr_texture_get_size};
 
 /// Wrapper around wl_shm_format, to make it easier and nicer to type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum TextureFormat {
     ARGB8888 = wl_shm_format::WL_SHM_FORMAT_ARGB8888 as u32,
@@ -64,6 +65,26 @@ pub enum TextureFormat {
     YVU444 = wl_shm_format::WL_SHM_FORMAT_YVU444 as u32
 }
 
+impl TextureFormat {
+    /// Gets the size of one pixel in this format.
+    ///
+    /// Returns `None` for the YUV formats, which don't store whole pixels.
+    pub fn bytes_per_pixel(&self) -> Option<u32> {
+        use self::TextureFormat::*;
+        match *self {
+            C8 | RGB332 | BGR233 => Some(1),
+            XRGB4444 | XBGR4444 | RGBX4444 | BGRX4444 | ARGB4444 | ABGR4444 | RGBA4444
+            | BGRA4444 | XRGB1555 | XBGR1555 | RGBX5551 | BGRX5551 | ARGB1555 | ABGR1555
+            | RGBA5551 | BGRA5551 | RGB565 | BGR565 => Some(2),
+            RGB888 | BGR888 => Some(3),
+            ARGB8888 | XRGB8888 | XBGR8888 | RGBX8888 | BGRX8888 | ABGR8888 | RGBA8888
+            | BGRA8888 | XRGB2101010 | XBGR2101010 | RGBX1010102 | BGRX1010102 | ARGB2101010
+            | ABGR2101010 | RGBA1010102 | BGRA1010102 => Some(4),
+            _ => None
+        }
+    }
+}
+
 impl Into<wl_shm_format> for TextureFormat {
     fn into(self) -> wl_shm_format {
         // NOTE Rationale for transmute:
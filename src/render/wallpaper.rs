@@ -0,0 +1,126 @@
+//! Laying out a texture across a whole output, e.g for wallpapers.
+
+use std::cmp;
+
+use wlroots_sys::wl_output_transform;
+
+use {Area, Origin, Output, Size};
+use render::Matrix3;
+
+/// The most copies of the image `Tile` draws.
+///
+/// Smaller images are scaled up by a whole factor until they fit in this
+/// many tiles, so that a tiny pattern doesn't cost thousands of draws.
+pub const MAX_WALLPAPER_TILES: i32 = 256;
+
+/// How a wallpaper is fitted to an output.
+///
+/// The sizes are in pixels of the output's buffer, so an image is shown at
+/// its own resolution by `Center` and `Tile` whatever the output's scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WallpaperMode {
+    /// Scale the image to cover the whole output, keeping its aspect ratio.
+    /// The parts that don't fit are cut off.
+    Fill,
+    /// Scale the image to fit in the output, keeping its aspect ratio.
+    ///
+    /// The rest of the output is not drawn, so it keeps whatever was in the
+    /// buffer. Clear the output first to get bars of a solid color.
+    Fit,
+    /// Show the image at its size, in the middle of the output.
+    ///
+    /// Like with `Fit`, the rest of the output is not drawn.
+    Center,
+    /// Repeat the image at its size from the top left of the output.
+    ///
+    /// Images too small to cover the output in `MAX_WALLPAPER_TILES` copies
+    /// are scaled up by a whole factor first.
+    Tile
+}
+
+impl WallpaperMode {
+    /// Gets where copies of an image of the given size go on an output of
+    /// the given size.
+    pub fn areas(self, image: Size, output: Size) -> Vec<Area> {
+        if image.width <= 0 || image.height <= 0 || output.width <= 0 || output.height <= 0 {
+            return vec![]
+        }
+        let centered = |size: Size| {
+            Area::new(Origin::new((output.width - size.width) / 2,
+                                  (output.height - size.height) / 2),
+                      size)
+        };
+        let scaled = |scale: f64| {
+            Size::new(cmp::max((image.width as f64 * scale).round() as i32, 1),
+                      cmp::max((image.height as f64 * scale).round() as i32, 1))
+        };
+        let scale_x = output.width as f64 / image.width as f64;
+        let scale_y = output.height as f64 / image.height as f64;
+        match self {
+            WallpaperMode::Fill => vec![centered(scaled(scale_x.max(scale_y)))],
+            WallpaperMode::Fit => vec![centered(scaled(scale_x.min(scale_y)))],
+            WallpaperMode::Center => vec![centered(image)],
+            WallpaperMode::Tile => {
+                let tiles = |scale: i32| {
+                    let columns = (output.width + image.width * scale - 1) / (image.width * scale);
+                    let rows = (output.height + image.height * scale - 1) / (image.height * scale);
+                    columns as i64 * rows as i64
+                };
+                let mut scale = 1;
+                while tiles(scale) > MAX_WALLPAPER_TILES as i64 {
+                    scale += 1;
+                }
+                let image = Size::new(image.width * scale, image.height * scale);
+                let mut areas = vec![];
+                let mut y = 0;
+                while y < output.height {
+                    let mut x = 0;
+                    while x < output.width {
+                        areas.push(Area::new(Origin::new(x, y), image));
+                        x += image.width;
+                    }
+                    y += image.height;
+                }
+                areas
+            }
+        }
+    }
+
+    /// Gets the matrices to draw a texture of the given size across the
+    /// output with `Renderer::render_texture_with_matrix`.
+    ///
+    /// Draw the texture once per matrix.
+    pub fn matrices(self, texture: Size, output: &Output) -> Vec<Matrix3> {
+        let (width, height) = output.transformed_resolution();
+        let projection = output.transform_matrix();
+        let transform = wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL;
+        self.areas(texture, Size::new(width, height))
+            .into_iter()
+            .map(|area| Matrix3::project_box(area, transform, 0.0, projection))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_covers_output() {
+        let areas = WallpaperMode::Tile.areas(Size::new(300, 200), Size::new(1000, 500));
+        assert_eq!(areas.len(), 4 * 3);
+        assert_eq!(areas[0], Area::new(Origin::new(0, 0), Size::new(300, 200)));
+        assert_eq!(areas[11], Area::new(Origin::new(900, 400), Size::new(300, 200)));
+    }
+
+    #[test]
+    fn tile_count_is_clamped() {
+        let areas = WallpaperMode::Tile.areas(Size::new(1, 1), Size::new(3840, 2160));
+        assert!(areas.len() <= MAX_WALLPAPER_TILES as usize);
+        let size = areas[0].size;
+        assert_eq!(size.width, size.height);
+        let last = areas[areas.len() - 1];
+        assert!(last.origin.x + last.size.width >= 3840);
+        assert!(last.origin.y + last.size.height >= 2160);
+    }
+}
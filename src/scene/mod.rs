@@ -0,0 +1,868 @@
+//! A retained scene graph, which draws the outputs for you.
+//!
+//! Instead of drawing every frame by hand, the things to show are kept in a
+//! tree of nodes in a `Scene`. The scene keeps track of which parts of each
+//! output change when nodes are added, moved or removed, or when one of its
+//! surfaces commits, and `Scene::render_output` only redraws those parts.
+//!
+//! Positions are in output layout coordinates: an output added at (x, y)
+//! shows the part of the scene whose top left corner is at (x, y).
+//!
+//! Children are drawn on top of their parent, and later children on top of
+//! earlier ones.
+
+mod node;
+
+use std::{cmp, cell::{Cell, RefCell}, collections::HashSet, rc::Rc, time::Duration};
+
+use wlroots_sys::wl_output_transform;
+
+use {Area, GenericRenderer, Origin, Output, OutputHandle, PixmanRegion, Renderer, Size, Surface,
+     SurfaceHandle};
+use handle::Handle;
+use render::{Matrix3, Texture};
+use self::node::{Kind, Node, SurfaceNode};
+
+pub use self::node::NodeId;
+
+/// A tree of things to draw on the outputs.
+///
+/// See the module documentation for how it is laid out.
+pub struct Scene {
+    nodes: Vec<Slot>,
+    /// Slots in `nodes` that can be reused.
+    free: Vec<usize>,
+    root: NodeId,
+    outputs: Vec<SceneOutput>,
+    /// The outputs, shared with the surfaces so a commit schedules a frame.
+    output_handles: Rc<RefCell<Vec<OutputHandle>>>,
+    background: [f32; 4]
+}
+
+struct Slot {
+    generation: u64,
+    node: Option<Node>
+}
+
+/// An output showing the scene.
+struct SceneOutput {
+    output: OutputHandle,
+    origin: Origin,
+    /// The size of the output in the layout.
+    size: Size,
+    /// The size of the output's buffer.
+    buffer_size: Size,
+    scale: f32,
+    transform: wl_output_transform,
+    /// Damage that hasn't been rendered yet, in output buffer coordinates.
+    damage: PixmanRegion,
+    /// The surfaces that have been sent an enter event for this output.
+    entered: HashSet<SurfaceHandle>
+}
+
+/// Something to draw, with its position in the layout.
+enum Draw<'scene> {
+    Rect(Area, [f32; 4]),
+    Texture(Area, &'scene Texture),
+    Surface(Origin, SurfaceHandle)
+}
+
+impl Scene {
+    /// Makes an empty scene, which only has a root node.
+    pub fn new() -> Self {
+        let root = Node::new(None, Kind::Tree);
+        Scene { nodes: vec![Slot { generation: 0,
+                                   node: Some(root) }],
+                free: vec![],
+                root: NodeId { index: 0,
+                               generation: 0 },
+                outputs: vec![],
+                output_handles: Rc::new(RefCell::new(vec![])),
+                background: [0.0, 0.0, 0.0, 1.0] }
+    }
+
+    /// Gets the root node, which all other nodes descend from.
+    ///
+    /// It can't be removed or moved.
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Determines if the node is still in the scene.
+    pub fn contains(&self, node: NodeId) -> bool {
+        self.node(node).is_some()
+    }
+
+    /// Sets the color the outputs are cleared with.
+    pub fn set_background(&mut self, color: [f32; 4]) {
+        self.background = color;
+        self.damage_outputs();
+    }
+
+    /// Adds a node that only groups its children, so that they can be moved,
+    /// hidden and restacked together.
+    ///
+    /// Returns `None` if `parent` is not in the scene.
+    pub fn add_tree(&mut self, parent: NodeId) -> Option<NodeId> {
+        self.add(parent, Kind::Tree)
+    }
+
+    /// Adds a rectangle filled with a solid color.
+    ///
+    /// Returns `None` if `parent` is not in the scene.
+    pub fn add_rect(&mut self, parent: NodeId, size: Size, color: [f32; 4]) -> Option<NodeId> {
+        self.add(parent, Kind::Rect { size, color })
+    }
+
+    /// Adds a texture, drawn at its own size.
+    ///
+    /// The texture must have been made by the renderer that draws the scene.
+    ///
+    /// Returns `None` if `parent` is not in the scene.
+    pub fn add_texture(&mut self, parent: NodeId, texture: Texture) -> Option<NodeId> {
+        self.add(parent, Kind::Texture(texture))
+    }
+
+    /// Adds a surface along with its subsurfaces.
+    ///
+    /// The surface is redrawn when it commits, and the node is removed once
+    /// the surface is destroyed.
+    ///
+    /// Returns `None` if `parent` is not in the scene.
+    pub fn add_surface_tree(&mut self, parent: NodeId, surface: &mut Surface) -> Option<NodeId> {
+        if !self.contains(parent) {
+            return None
+        }
+        let committed = Rc::new(Cell::new(false));
+        let commit = {
+            let committed = committed.clone();
+            let outputs = self.output_handles.clone();
+            surface.on_commit(move |_, _| {
+                                  committed.set(true);
+                                  for output in outputs.borrow().iter() {
+                                      output.run(|output| output.schedule_frame()).ok();
+                                  }
+                              })
+        };
+        let bounds = surface_tree_bounds(surface);
+        self.add(parent,
+                 Kind::SurfaceTree(SurfaceNode { surface: surface.weak_reference(),
+                                                 committed,
+                                                 bounds,
+                                                 _commit: commit }))
+    }
+
+    /// Removes a node and all of its children from the scene.
+    ///
+    /// Returns `false` if the node is not in the scene, or is the root.
+    pub fn remove(&mut self, node: NodeId) -> bool {
+        if node == self.root || !self.contains(node) {
+            return false
+        }
+        self.damage_node(node);
+        self.unlink(node);
+        self.free_subtree(node);
+        true
+    }
+
+    /// Gets the position of the node, relative to its parent.
+    pub fn position(&self, node: NodeId) -> Option<Origin> {
+        self.node(node).map(|node| node.position)
+    }
+
+    /// Moves the node, relative to its parent.
+    pub fn set_position(&mut self, node: NodeId, position: Origin) -> bool {
+        if node == self.root {
+            return false
+        }
+        self.update(node, |node| node.position = position)
+    }
+
+    /// Shows or hides the node along with its children.
+    pub fn set_enabled(&mut self, node: NodeId, enabled: bool) -> bool {
+        self.update(node, |node| node.enabled = enabled)
+    }
+
+    /// Changes the color of a rectangle node.
+    ///
+    /// Returns `false` if the node isn't a rectangle.
+    pub fn set_rect_color(&mut self, node: NodeId, color: [f32; 4]) -> bool {
+        match self.node(node).map(|node| &node.kind) {
+            Some(&Kind::Rect { .. }) => {}
+            _ => return false
+        }
+        self.update(node, |node| {
+            if let Kind::Rect { color: ref mut old_color, .. } = node.kind {
+                *old_color = color
+            }
+        })
+    }
+
+    /// Changes the size of a rectangle node.
+    ///
+    /// Returns `false` if the node isn't a rectangle.
+    pub fn set_rect_size(&mut self, node: NodeId, size: Size) -> bool {
+        match self.node(node).map(|node| &node.kind) {
+            Some(&Kind::Rect { .. }) => {}
+            _ => return false
+        }
+        self.update(node, |node| {
+            if let Kind::Rect { size: ref mut old_size, .. } = node.kind {
+                *old_size = size
+            }
+        })
+    }
+
+    /// Moves the node to a new parent, putting it on top of its new siblings.
+    ///
+    /// Returns `false` if either node isn't in the scene, or if `parent` is
+    /// the node itself or one of its children.
+    pub fn reparent(&mut self, node: NodeId, parent: NodeId) -> bool {
+        if node == self.root || !self.contains(node) || !self.contains(parent) {
+            return false
+        }
+        let mut ancestor = Some(parent);
+        while let Some(id) = ancestor {
+            if id == node {
+                return false
+            }
+            ancestor = self.node(id).and_then(|node| node.parent);
+        }
+        self.damage_node(node);
+        self.unlink(node);
+        self.node_mut(parent).map(|parent| parent.children.push(node));
+        self.node_mut(node).map(|node| node.parent = Some(parent));
+        self.damage_node(node);
+        true
+    }
+
+    /// Draws the node above all of its siblings.
+    pub fn raise_to_top(&mut self, node: NodeId) -> bool {
+        self.restack(node, true)
+    }
+
+    /// Draws the node below all of its siblings.
+    pub fn lower_to_bottom(&mut self, node: NodeId) -> bool {
+        self.restack(node, false)
+    }
+
+    /// Redraws the part of the outputs the node and its children are on,
+    /// e.g when the contents of a texture node changed.
+    pub fn damage_node(&mut self, node: NodeId) {
+        let mut areas = vec![];
+        if let Some(origin) = self.layout_origin(node) {
+            self.collect_areas(node, origin, &mut areas);
+        }
+        for area in areas {
+            self.damage_area(area);
+        }
+    }
+
+    /// Shows the scene on the output, with the top left corner of the output
+    /// at `origin` in the layout.
+    ///
+    /// If the output already shows the scene it is moved instead.
+    pub fn add_output(&mut self, output: &mut Output, origin: Origin) {
+        let handle = output.weak_reference();
+        let index = match self.output_index(&handle) {
+            Some(index) => {
+                self.outputs[index].origin = origin;
+                index
+            }
+            None => {
+                self.output_handles.borrow_mut().push(handle.clone());
+                let transform = wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL;
+                self.outputs.push(SceneOutput { output: handle,
+                                                origin,
+                                                size: Size::default(),
+                                                buffer_size: Size::default(),
+                                                scale: 1.0,
+                                                transform,
+                                                damage: PixmanRegion::new(),
+                                                entered: HashSet::new() });
+                self.outputs.len() - 1
+            }
+        };
+        self.update_output(index, output);
+        self.damage_whole_output(index);
+        output.schedule_frame();
+    }
+
+    /// Stops showing the scene on the output.
+    ///
+    /// The output's surfaces aren't sent leave events, as the output is
+    /// usually going away.
+    pub fn remove_output(&mut self, output: &OutputHandle) -> bool {
+        self.output_handles.borrow_mut().retain(|handle| handle != output);
+        let count = self.outputs.len();
+        self.outputs.retain(|scene_output| scene_output.output != *output);
+        self.outputs.len() != count
+    }
+
+    /// Draws the damaged parts of the output, and sends the surfaces on it
+    /// their enter, leave and frame done events.
+    ///
+    /// Call this when the output emits a frame event. `renderer` is the
+    /// renderer the output is drawn with, and `when` is the time of the frame
+    /// (e.g `utils::current_time()`).
+    ///
+    /// Outputs that are transformed are redrawn whole whenever they are
+    /// damaged.
+    ///
+    /// Returns `false` if the output doesn't show the scene.
+    pub fn render_output(&mut self,
+                         output: &mut Output,
+                         renderer: &mut GenericRenderer,
+                         when: Duration)
+                         -> bool {
+        let handle = output.weak_reference();
+        let index = match self.output_index(&handle) {
+            Some(index) => index,
+            None => return false
+        };
+        self.collect_surface_damage();
+        self.update_output(index, output);
+        let visible = self.update_entered(index, output);
+        let mut damage = PixmanRegion::new();
+        let needs_swap = if renderer.is_software() {
+            // NOTE
+            // The software renderer shares one image between all outputs,
+            // so whatever was drawn for another output has to be replaced.
+            let Size { width, height } = self.outputs[index].buffer_size;
+            damage.rectangle(0, 0, width as u32, height as u32);
+            true
+        } else {
+            let pending = &mut self.outputs[index].damage;
+            output.damage().add(pending);
+            output.damage().make_current(&mut damage)
+        };
+        self.outputs[index].damage = PixmanRegion::new();
+        if needs_swap {
+            {
+                let renderer = if renderer.is_software() {
+                    renderer.render(output, None)
+                } else {
+                    renderer.render_unswapped(output)
+                };
+                self.draw(index, renderer, &damage);
+            }
+            if !renderer.is_software() {
+                output.damage().swap_buffers(when, &mut damage);
+            }
+        }
+        for surface in visible {
+            surface.run(|surface| surface.send_frame_done(when)).ok();
+        }
+        true
+    }
+
+    fn output_index(&self, output: &OutputHandle) -> Option<usize> {
+        self.outputs.iter().position(|scene_output| scene_output.output == *output)
+    }
+
+    fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    fn add(&mut self, parent: NodeId, kind: Kind) -> Option<NodeId> {
+        if !self.contains(parent) {
+            return None
+        }
+        let node = Node::new(Some(parent), kind);
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.nodes[index];
+                slot.node = Some(node);
+                NodeId { index,
+                         generation: slot.generation }
+            }
+            None => {
+                self.nodes.push(Slot { generation: 0,
+                                       node: Some(node) });
+                NodeId { index: self.nodes.len() - 1,
+                         generation: 0 }
+            }
+        };
+        self.node_mut(parent).map(|parent| parent.children.push(id));
+        self.damage_node(id);
+        Some(id)
+    }
+
+    /// Changes a node, damaging where it was and where it ends up.
+    fn update<F>(&mut self, id: NodeId, change: F) -> bool
+        where F: FnOnce(&mut Node)
+    {
+        if !self.contains(id) {
+            return false
+        }
+        self.damage_node(id);
+        self.node_mut(id).map(change);
+        self.damage_node(id);
+        true
+    }
+
+    fn restack(&mut self, id: NodeId, top: bool) -> bool {
+        let parent = match self.node(id).and_then(|node| node.parent) {
+            Some(parent) => parent,
+            None => return false
+        };
+        self.damage_node(id);
+        if let Some(parent) = self.node_mut(parent) {
+            parent.children.retain(|&child| child != id);
+            if top {
+                parent.children.push(id)
+            } else {
+                parent.children.insert(0, id)
+            }
+        }
+        true
+    }
+
+    /// Removes the node from the children of its parent.
+    fn unlink(&mut self, id: NodeId) {
+        let parent = self.node(id).and_then(|node| node.parent);
+        if let Some(parent) = parent.and_then(|parent| self.node_mut(parent)) {
+            parent.children.retain(|&child| child != id);
+        }
+    }
+
+    fn free_subtree(&mut self, id: NodeId) {
+        let node = match self.nodes.get_mut(id.index) {
+            Some(slot) => {
+                slot.generation += 1;
+                slot.node.take()
+            }
+            None => None
+        };
+        self.free.push(id.index);
+        if let Some(node) = node {
+            for child in node.children {
+                self.free_subtree(child);
+            }
+        }
+    }
+
+    /// Gets where the node is in the layout, if it and all of its parents
+    /// are enabled.
+    fn layout_origin(&self, id: NodeId) -> Option<Origin> {
+        let mut origin = Origin::default();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let node = self.node(id)?;
+            if !node.enabled {
+                return None
+            }
+            origin.x += node.position.x;
+            origin.y += node.position.y;
+            current = node.parent;
+        }
+        Some(origin)
+    }
+
+    /// Collects the areas in the layout covered by the node and its
+    /// enabled children.
+    fn collect_areas(&self, id: NodeId, origin: Origin, areas: &mut Vec<Area>) {
+        let node = match self.node(id) {
+            Some(node) => node,
+            None => return
+        };
+        if let Some(area) = node.area(origin) {
+            areas.push(area);
+        }
+        for &child in &node.children {
+            if let Some(child_node) = self.node(child) {
+                if child_node.enabled {
+                    let child_origin = Origin::new(origin.x + child_node.position.x,
+                                                   origin.y + child_node.position.y);
+                    self.collect_areas(child, child_origin, areas);
+                }
+            }
+        }
+    }
+
+    /// Collects what to draw, from the bottom up.
+    fn collect_draws<'scene>(&'scene self,
+                             id: NodeId,
+                             origin: Origin,
+                             draws: &mut Vec<Draw<'scene>>) {
+        let node = match self.node(id) {
+            Some(node) if node.enabled => node,
+            _ => return
+        };
+        match node.kind {
+            Kind::Tree => {}
+            Kind::Rect { size, color } => draws.push(Draw::Rect(Area::new(origin, size), color)),
+            Kind::Texture(ref texture) => {
+                let (width, height) = texture.size();
+                draws.push(Draw::Texture(Area::new(origin, Size::new(width, height)), texture))
+            }
+            Kind::SurfaceTree(ref surface) => {
+                draws.push(Draw::Surface(origin, surface.surface.clone()))
+            }
+        }
+        for &child in &node.children {
+            if let Some(child_node) = self.node(child) {
+                let child_origin = Origin::new(origin.x + child_node.position.x,
+                                               origin.y + child_node.position.y);
+                self.collect_draws(child, child_origin, draws);
+            }
+        }
+    }
+
+    /// Damages the surfaces that committed, and removes the ones that have
+    /// been destroyed.
+    fn collect_surface_damage(&mut self) {
+        let mut surface_nodes = vec![];
+        for (index, slot) in self.nodes.iter().enumerate() {
+            if let Some(Node { kind: Kind::SurfaceTree(_), .. }) = slot.node {
+                surface_nodes.push(NodeId { index,
+                                            generation: slot.generation });
+            }
+        }
+        for id in surface_nodes {
+            let (surface, committed) = match self.node(id).map(|node| &node.kind) {
+                Some(&Kind::SurfaceTree(ref surface)) => {
+                    (surface.surface.clone(), surface.committed.replace(false))
+                }
+                _ => continue
+            };
+            if !surface.is_alive() {
+                self.remove(id);
+                continue
+            }
+            if !committed {
+                continue
+            }
+            // Damage where the surface was, and where it is now.
+            self.damage_node(id);
+            if let Ok(bounds) = surface.run(|surface| surface_tree_bounds(surface)) {
+                if let Some(&mut Node { kind: Kind::SurfaceTree(ref mut surface), .. }) =
+                    self.node_mut(id)
+                {
+                    surface.bounds = bounds;
+                }
+                self.damage_node(id);
+            }
+        }
+    }
+
+    /// Adds damage in layout coordinates to the outputs it's on.
+    fn damage_area(&mut self, area: Area) {
+        for scene_output in &mut self.outputs {
+            let output_area = Area::new(scene_output.origin, scene_output.size);
+            let area = match intersect(area, output_area) {
+                Some(area) => area,
+                None => continue
+            };
+            if scene_output.transform != wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL {
+                let Size { width, height } = scene_output.buffer_size;
+                scene_output.damage.rectangle(0, 0, width as u32, height as u32);
+            } else {
+                let area = to_buffer(area, scene_output.origin, scene_output.scale);
+                scene_output.damage.rectangle(area.origin.x,
+                                              area.origin.y,
+                                              area.size.width as u32,
+                                              area.size.height as u32);
+            }
+            scene_output.output.run(|output| output.schedule_frame()).ok();
+        }
+    }
+
+    fn damage_whole_output(&mut self, index: usize) {
+        let scene_output = &mut self.outputs[index];
+        let Size { width, height } = scene_output.buffer_size;
+        scene_output.damage.rectangle(0, 0, width as u32, height as u32);
+        scene_output.output.run(|output| output.schedule_frame()).ok();
+    }
+
+    fn damage_outputs(&mut self) {
+        for index in 0..self.outputs.len() {
+            self.damage_whole_output(index);
+        }
+    }
+
+    /// Updates the cached geometry of the output, redrawing all of it if it
+    /// changed.
+    fn update_output(&mut self, index: usize, output: &Output) {
+        let (width, height) = output.effective_resolution();
+        let (buffer_width, buffer_height) = output.size();
+        let size = Size::new(width, height);
+        let buffer_size = Size::new(buffer_width, buffer_height);
+        let scale = output.scale();
+        let transform = output.get_transform();
+        let changed = {
+            let scene_output = &mut self.outputs[index];
+            let changed = scene_output.size != size || scene_output.buffer_size != buffer_size
+                          || scene_output.scale != scale
+                          || scene_output.transform != transform;
+            scene_output.size = size;
+            scene_output.buffer_size = buffer_size;
+            scene_output.scale = scale;
+            scene_output.transform = transform;
+            changed
+        };
+        if changed {
+            self.damage_whole_output(index);
+        }
+    }
+
+    /// Sends enter and leave events to the surfaces on the output.
+    ///
+    /// Returns the surfaces that are on the output.
+    fn update_entered(&mut self, index: usize, output: &mut Output) -> Vec<SurfaceHandle> {
+        let output_area = Area::new(self.outputs[index].origin, self.outputs[index].size);
+        let mut visible = vec![];
+        {
+            let mut draws = vec![];
+            self.collect_draws(self.root, Origin::default(), &mut draws);
+            for draw in draws {
+                let (origin, root) = match draw {
+                    Draw::Surface(origin, surface) => (origin, surface),
+                    _ => continue
+                };
+                root.run(|surface| {
+                        surface.for_each_surface(|surface, x, y| {
+                            let (width, height) = surface.current_state().size();
+                            let area = Area::new(Origin::new(origin.x + x, origin.y + y),
+                                                 Size::new(width, height));
+                            if intersect(area, output_area).is_some() {
+                                visible.push(surface.weak_reference());
+                            }
+                        })
+                    })
+                    .ok();
+            }
+        }
+        let entered = &mut self.outputs[index].entered;
+        for surface in &visible {
+            if !entered.contains(surface) {
+                surface.run(|surface| surface.send_enter(output)).ok();
+            }
+        }
+        for surface in entered.iter() {
+            if !visible.contains(surface) {
+                surface.run(|surface| surface.send_leave(output)).ok();
+            }
+        }
+        *entered = visible.iter().cloned().collect();
+        visible
+    }
+
+    /// Draws the damaged parts of the output.
+    fn draw(&self, index: usize, mut renderer: Renderer, damage: &PixmanRegion) {
+        let mut draws = vec![];
+        self.collect_draws(self.root, Origin::default(), &mut draws);
+        let scene_output = &self.outputs[index];
+        let (origin, scale) = (scene_output.origin, scene_output.scale);
+        let projection = renderer.output.transform_matrix();
+        for area in damage.rectangles() {
+            renderer.scissor(area);
+            renderer.clear(self.background);
+            for draw in &draws {
+                match *draw {
+                    Draw::Rect(area, color) => {
+                        let area = to_buffer(area, origin, scale);
+                        renderer.render_colored_rect(area, color, projection)
+                    }
+                    Draw::Texture(area, texture) => {
+                        let transform = wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL;
+                        let matrix = Matrix3::project_box(to_buffer(area, origin, scale),
+                                                          transform,
+                                                          0.0,
+                                                          projection);
+                        renderer.render_texture_with_matrix(texture, matrix);
+                    }
+                    Draw::Surface(surface_origin, ref surface) => {
+                        let surface_origin = Origin::new(surface_origin.x - origin.x,
+                                                         surface_origin.y - origin.y);
+                        surface.run(|surface| {
+                                        renderer.render_surface_tree_at(surface, surface_origin)
+                                    })
+                               .ok();
+                    }
+                }
+            }
+        }
+        renderer.scissor(None);
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
+}
+
+/// Gets the area covered by a surface and its subsurfaces, relative to it.
+fn surface_tree_bounds(surface: &mut Surface) -> Option<Area> {
+    let mut bounds: Option<Area> = None;
+    surface.for_each_surface(|surface, x, y| {
+        let (width, height) = surface.current_state().size();
+        let area = Area::new(Origin::new(x, y), Size::new(width, height));
+        bounds = Some(match bounds {
+                          Some(bounds) => union(bounds, area),
+                          None => area
+                      });
+    });
+    bounds
+}
+
+fn intersect(a: Area, b: Area) -> Option<Area> {
+    let x1 = cmp::max(a.origin.x, b.origin.x);
+    let y1 = cmp::max(a.origin.y, b.origin.y);
+    let x2 = cmp::min(a.origin.x + a.size.width, b.origin.x + b.size.width);
+    let y2 = cmp::min(a.origin.y + a.size.height, b.origin.y + b.size.height);
+    if x2 <= x1 || y2 <= y1 {
+        None
+    } else {
+        Some(Area::new(Origin::new(x1, y1), Size::new(x2 - x1, y2 - y1)))
+    }
+}
+
+fn union(a: Area, b: Area) -> Area {
+    let x1 = cmp::min(a.origin.x, b.origin.x);
+    let y1 = cmp::min(a.origin.y, b.origin.y);
+    let x2 = cmp::max(a.origin.x + a.size.width, b.origin.x + b.size.width);
+    let y2 = cmp::max(a.origin.y + a.size.height, b.origin.y + b.size.height);
+    Area::new(Origin::new(x1, y1), Size::new(x2 - x1, y2 - y1))
+}
+
+/// Converts an area in the layout to the buffer of an output at `origin`.
+fn to_buffer(area: Area, origin: Origin, scale: f32) -> Area {
+    let origin = Origin::new(area.origin.x - origin.x, area.origin.y - origin.y);
+    area.with_origin(origin).scale(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, collections::HashSet};
+
+    use wlroots_sys::wl_output_transform;
+
+    use {Area, Origin, OutputHandle, PixmanRegion, Size};
+    use super::{to_buffer, Scene, SceneOutput};
+
+    fn area(x: i32, y: i32, width: i32, height: i32) -> Area {
+        Area::new(Origin::new(x, y), Size::new(width, height))
+    }
+
+    /// Makes a scene shown on one output, which isn't backed by a real one.
+    fn scene_with_output(origin: Origin, size: Size, scale: f32) -> Scene {
+        let mut scene = Scene::new();
+        let buffer_size = area(0, 0, size.width, size.height).scale(scale).size;
+        scene.outputs.push(SceneOutput { output: OutputHandle::new(),
+                                         origin,
+                                         size,
+                                         buffer_size,
+                                         scale,
+                                         transform:
+                                             wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL,
+                                         damage: PixmanRegion::new(),
+                                         entered: HashSet::new() });
+        scene
+    }
+
+    fn take_damage(scene: &mut Scene) -> Vec<Area> {
+        mem::replace(&mut scene.outputs[0].damage, PixmanRegion::new()).areas()
+    }
+
+    #[test]
+    fn to_buffer_translates_then_scales() {
+        assert_eq!(to_buffer(area(110, 220, 50, 30), Origin::new(100, 200), 1.0),
+                   area(10, 20, 50, 30));
+        assert_eq!(to_buffer(area(110, 220, 50, 30), Origin::new(100, 200), 2.0),
+                   area(20, 40, 100, 60));
+        assert_eq!(to_buffer(area(-10, 5, 20, 20), Origin::new(0, 0), 1.0),
+                   area(-10, 5, 20, 20));
+    }
+
+    #[test]
+    fn to_buffer_rounds_edges_at_fractional_scales() {
+        // Adjacent areas still touch once scaled.
+        let left = to_buffer(area(0, 0, 1, 1), Origin::new(0, 0), 1.5);
+        let right = to_buffer(area(1, 0, 1, 1), Origin::new(0, 0), 1.5);
+        assert_eq!(left, area(0, 0, 2, 2));
+        assert_eq!(right, area(2, 0, 1, 2));
+        assert_eq!(left.origin.x + left.size.width, right.origin.x);
+    }
+
+    #[test]
+    fn adding_node_damages_its_area() {
+        let mut scene = scene_with_output(Origin::new(0, 0), Size::new(100, 100), 1.0);
+        let root = scene.root();
+        scene.add_rect(root, Size::new(10, 20), [1.0; 4]).unwrap();
+        assert_eq!(take_damage(&mut scene), vec![area(0, 0, 10, 20)]);
+    }
+
+    #[test]
+    fn moving_node_damages_old_and_new_area() {
+        let mut scene = scene_with_output(Origin::new(0, 0), Size::new(100, 100), 1.0);
+        let root = scene.root();
+        let rect = scene.add_rect(root, Size::new(10, 10), [1.0; 4]).unwrap();
+        take_damage(&mut scene);
+        assert!(scene.set_position(rect, Origin::new(50, 60)));
+        assert_eq!(take_damage(&mut scene),
+                   vec![area(0, 0, 10, 10), area(50, 60, 10, 10)]);
+    }
+
+    #[test]
+    fn moving_parent_damages_children() {
+        let mut scene = scene_with_output(Origin::new(0, 0), Size::new(100, 100), 1.0);
+        let root = scene.root();
+        let tree = scene.add_tree(root).unwrap();
+        let rect = scene.add_rect(tree, Size::new(10, 10), [1.0; 4]).unwrap();
+        assert!(scene.set_position(rect, Origin::new(5, 5)));
+        take_damage(&mut scene);
+        assert!(scene.set_position(tree, Origin::new(30, 0)));
+        assert_eq!(take_damage(&mut scene),
+                   vec![area(5, 5, 10, 10), area(35, 5, 10, 10)]);
+    }
+
+    #[test]
+    fn removing_node_damages_its_area() {
+        let mut scene = scene_with_output(Origin::new(0, 0), Size::new(100, 100), 1.0);
+        let root = scene.root();
+        let tree = scene.add_tree(root).unwrap();
+        let rect = scene.add_rect(tree, Size::new(10, 10), [1.0; 4]).unwrap();
+        assert!(scene.set_position(tree, Origin::new(20, 30)));
+        take_damage(&mut scene);
+        assert!(scene.remove(tree));
+        assert_eq!(take_damage(&mut scene), vec![area(20, 30, 10, 10)]);
+        assert!(!scene.contains(tree));
+        assert!(!scene.contains(rect));
+        assert!(!scene.remove(rect));
+        assert!(take_damage(&mut scene).is_empty());
+    }
+
+    #[test]
+    fn hidden_nodes_cause_no_damage() {
+        let mut scene = scene_with_output(Origin::new(0, 0), Size::new(100, 100), 1.0);
+        let root = scene.root();
+        let rect = scene.add_rect(root, Size::new(10, 10), [1.0; 4]).unwrap();
+        assert!(scene.set_enabled(rect, false));
+        assert_eq!(take_damage(&mut scene), vec![area(0, 0, 10, 10)]);
+        assert!(scene.set_position(rect, Origin::new(50, 50)));
+        assert!(take_damage(&mut scene).is_empty());
+    }
+
+    #[test]
+    fn damage_is_clipped_and_converted_to_output_buffer() {
+        let mut scene = scene_with_output(Origin::new(100, 100), Size::new(50, 50), 2.0);
+        let root = scene.root();
+        let rect = scene.add_rect(root, Size::new(20, 20), [1.0; 4]).unwrap();
+        // Outside of the output.
+        assert!(take_damage(&mut scene).is_empty());
+        assert!(scene.set_position(rect, Origin::new(140, 90)));
+        assert_eq!(take_damage(&mut scene), vec![area(80, 0, 20, 20)]);
+    }
+}
@@ -0,0 +1,74 @@
+//! The nodes that make up a `Scene`.
+
+use std::{cell::Cell, rc::Rc};
+
+use {Area, ListenerToken, Origin, Size, SurfaceHandle};
+use render::Texture;
+
+/// Refers to a node in a `Scene`.
+///
+/// Ids are not reused, so an id of a node that was removed doesn't refer to
+/// any node even if a new one was added in its place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    pub(crate) index: usize,
+    pub(crate) generation: u64
+}
+
+pub(crate) struct Node {
+    pub kind: Kind,
+    pub parent: Option<NodeId>,
+    /// From the bottom of the stack to the top.
+    pub children: Vec<NodeId>,
+    /// Relative to the parent.
+    pub position: Origin,
+    pub enabled: bool
+}
+
+pub(crate) enum Kind {
+    Tree,
+    Rect { size: Size, color: [f32; 4] },
+    Texture(Texture),
+    SurfaceTree(SurfaceNode)
+}
+
+pub(crate) struct SurfaceNode {
+    pub surface: SurfaceHandle,
+    /// Set when the surface commits, until the scene redraws it.
+    pub committed: Rc<Cell<bool>>,
+    /// The area covered by the surface and its subsurfaces when it was last
+    /// drawn, relative to the node.
+    pub bounds: Option<Area>,
+    pub _commit: ListenerToken
+}
+
+impl Node {
+    pub fn new(parent: Option<NodeId>, kind: Kind) -> Self {
+        Node { kind,
+               parent,
+               children: vec![],
+               position: Origin::default(),
+               enabled: true }
+    }
+
+    /// Gets the area the node itself covers, if the node is at `origin`.
+    ///
+    /// Children are not included.
+    pub fn area(&self, origin: Origin) -> Option<Area> {
+        match self.kind {
+            Kind::Tree => None,
+            Kind::Rect { size, .. } => Some(Area::new(origin, size)),
+            Kind::Texture(ref texture) => {
+                let (width, height) = texture.size();
+                Some(Area::new(origin, Size::new(width, height)))
+            }
+            Kind::SurfaceTree(ref surface) => {
+                surface.bounds.map(|bounds| {
+                                       Area::new(Origin::new(origin.x + bounds.origin.x,
+                                                             origin.y + bounds.origin.y),
+                                                 bounds.size)
+                                   })
+            }
+        }
+    }
+}
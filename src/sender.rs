@@ -0,0 +1,155 @@
+//! A channel for running code on the compositor from other threads.
+//!
+//! Everything in the compositor has to be accessed from the thread running
+//! the event loop. A `CompositorSender` can be sent to other threads and be
+//! used to queue closures, which are then run on the event loop thread.
+
+use libc;
+use std::{mem, collections::VecDeque, os::unix::io::RawFd, sync::{Arc, Mutex}};
+
+use {Compositor, CompositorHandle, EventLoop, EventSource, FdMask};
+
+type Command = Box<FnMut(&mut Compositor) + Send>;
+
+/// State shared between the senders and the compositor.
+struct Channel {
+    queue: Mutex<Queue>,
+    /// The eventfd used to wake up the event loop.
+    fd: RawFd
+}
+
+/// The commands waiting to be run.
+///
+/// `closed` lives behind the same lock as the commands, so no command can be
+/// queued after the receiver has been dropped.
+struct Queue {
+    commands: VecDeque<Command>,
+    /// Set when the compositor no longer accepts commands.
+    closed: bool
+}
+
+/// Queues closures to be run on the compositor from any thread.
+///
+/// Get one with `Compositor::sender`.
+#[derive(Clone)]
+pub struct CompositorSender {
+    channel: Arc<Channel>
+}
+
+/// The compositor's end of the channel.
+///
+/// When this is dropped no more commands are accepted.
+pub(crate) struct CommandReceiver {
+    channel: Arc<Channel>,
+    /// Keeps the eventfd registered on the event loop.
+    _source: EventSource
+}
+
+impl CompositorSender {
+    /// Queues `command` to be run on the compositor's event loop thread.
+    ///
+    /// Returns `false` if the compositor has shut down, in which case the
+    /// command will never run.
+    pub fn send<F>(&self, command: F) -> bool
+        where F: FnOnce(&mut Compositor) + Send + 'static
+    {
+        let mut command = Some(command);
+        let command = move |compositor: &mut Compositor| {
+            if let Some(command) = command.take() {
+                command(compositor)
+            }
+        };
+        match self.channel.queue.lock() {
+            Ok(mut queue) => {
+                if queue.closed {
+                    return false
+                }
+                queue.commands.push_back(Box::new(command))
+            }
+            Err(_) => return false
+        }
+        // Wake up the event loop.
+        let value: u64 = 1;
+        unsafe {
+            libc::write(self.channel.fd,
+                        &value as *const u64 as *const libc::c_void,
+                        mem::size_of::<u64>());
+        }
+        true
+    }
+}
+
+impl CommandReceiver {
+    /// Makes a new channel whose commands are run on `event_loop`.
+    ///
+    /// Returns `None` if the eventfd could not be created or registered.
+    pub(crate) fn new(event_loop: &EventLoop) -> Option<(CommandReceiver, CompositorSender)> {
+        unsafe {
+            let fd = libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK);
+            if fd < 0 {
+                wlr_log!(L_ERROR, "Could not create eventfd for compositor sender");
+                return None
+            }
+            let queue = Queue { commands: VecDeque::new(),
+                                closed: false };
+            let channel = Arc::new(Channel { queue: Mutex::new(queue),
+                                             fd });
+            let receiver_channel = channel.clone();
+            let source = event_loop.add_fd(fd, FdMask::READABLE, move |compositor, fd, _| {
+                                                 receiver_channel.dispatch(compositor, fd)
+                                             })?;
+            Some((CommandReceiver { channel: channel.clone(),
+                                    _source: source },
+                  CompositorSender { channel }))
+        }
+    }
+
+    /// Makes a new sender for this channel.
+    pub(crate) fn sender(&self) -> CompositorSender {
+        CompositorSender { channel: self.channel.clone() }
+    }
+}
+
+impl Drop for CommandReceiver {
+    fn drop(&mut self) {
+        // NOTE
+        // The commands are dropped after the lock is released, in case
+        // dropping one of them sends another command.
+        let _commands = match self.channel.queue.lock() {
+            Ok(mut queue) => {
+                queue.closed = true;
+                mem::replace(&mut queue.commands, VecDeque::new())
+            }
+            Err(_) => return
+        };
+    }
+}
+
+impl Channel {
+    /// Runs all of the queued commands.
+    fn dispatch(&self, compositor: CompositorHandle, fd: RawFd) {
+        let mut value: u64 = 0;
+        unsafe {
+            libc::read(fd,
+                       &mut value as *mut u64 as *mut libc::c_void,
+                       mem::size_of::<u64>());
+        }
+        let commands = match self.queue.lock() {
+            Ok(mut queue) => mem::replace(&mut queue.commands, VecDeque::new()),
+            Err(_) => return
+        };
+        for mut command in commands {
+            if let Err(err) = compositor.run(|compositor| command(compositor)) {
+                wlr_log!(L_ERROR, "Could not run command on compositor: {:?}", err);
+            }
+        }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
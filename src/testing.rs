@@ -0,0 +1,423 @@
+//! Utilities for testing a compositor without a GPU or a seat.
+//!
+//! `run` builds the compositor on the headless backend and runs it
+//! in-process. From inside the test you can add virtual outputs and
+//! synthetic keyboards, pointers and touch devices. Events sent through
+//! those devices reach the same `InputManagerHandler`, `KeyboardHandler`,
+//! `PointerHandler` and `TouchHandler` callbacks that real devices use.
+//!
+//! Each compositor runs on the thread that called `run`, so tests that run
+//! in parallel on separate threads do not interfere with each other.
+//!
+//! ```rust,no_run
+//! # extern crate wlroots;
+//! use wlroots::{CompositorBuilder, Size, testing};
+//!
+//! # fn main() {
+//! let builder = CompositorBuilder::new();
+//! testing::run(builder, (), |harness| {
+//!     harness.add_output(Size::new(1280, 720), 60000);
+//!     let mut keyboard = harness.add_keyboard().expect("Could not add keyboard");
+//!     // KEY_A
+//!     keyboard.tap(30);
+//!     harness.dispatch();
+//! }).expect("Could not run compositor");
+//! # }
+//! ```
+
+use std::{panic, any::Any, time::Duration};
+
+use libc::{c_int, c_void};
+use wayland_sys::server::{wl_display, wl_event_loop, WAYLAND_SERVER_HANDLE,
+                          signal::wl_signal_emit};
+use wlroots_sys::{wlr_axis_orientation, wlr_axis_source, wlr_button_state,
+                  wlr_event_keyboard_key, wlr_event_pointer_axis, wlr_event_pointer_button,
+                  wlr_event_pointer_motion, wlr_event_pointer_motion_absolute,
+                  wlr_event_touch_cancel, wlr_event_touch_down, wlr_event_touch_motion,
+                  wlr_event_touch_up, wlr_keyboard_notify_key};
+use wlroots_sys::wlr_input_device_type::*;
+use wlroots_sys::wlr_key_state::*;
+
+use {Backend, BackendConfig, CompositorBuilder, CompositorError, CompositorHandle, InputDevice,
+     OutputHandle, Size};
+use backend::HeadlessBackend;
+use compositor::compositor_handle;
+use utils::current_time;
+
+/// Controls a compositor running on the headless backend.
+///
+/// Obtained in the closure passed to `run`.
+pub struct Harness {
+    backend: HeadlessBackend,
+    display: *mut wl_display,
+    event_loop: *mut wl_event_loop
+}
+
+/// A synthetic keyboard.
+#[derive(Debug)]
+pub struct VirtualKeyboard {
+    device: InputDevice
+}
+
+/// A synthetic pointer.
+#[derive(Debug)]
+pub struct VirtualPointer {
+    device: InputDevice
+}
+
+/// A synthetic touch device.
+#[derive(Debug)]
+pub struct VirtualTouch {
+    device: InputDevice
+}
+
+/// Builds the compositor on the headless backend and runs `test` while it
+/// is running.
+///
+/// The backend has already been started when `test` is called, so the
+/// handlers given to the builder will see the outputs and input devices
+/// added through the `Harness`. The compositor is shut down once `test`
+/// returns.
+///
+/// If `test` panics, the compositor is still shut down before the panic is
+/// resumed.
+pub fn run<D, F>(builder: CompositorBuilder, data: D, test: F) -> Result<(), CompositorError>
+    where D: Any + 'static,
+          F: FnOnce(&mut Harness)
+{
+    let compositor = builder.try_build(BackendConfig::Headless, data)?;
+    let mut test_result = Ok(());
+    compositor.try_run_with(|compositor| unsafe {
+        let backend = match *compositor.backend() {
+            Backend::Headless(ref backend) => HeadlessBackend::from_ptr(backend.as_ptr()),
+            // NOTE Rationale for panicking:
+            // * We just built the compositor with the headless backend,
+            //   so this is unreachable.
+            _ => panic!("Test compositor was not using the headless backend")
+        };
+        let mut harness = Harness { backend,
+                                    display: compositor.display(),
+                                    event_loop: compositor.event_loop().as_ptr() };
+        harness.dispatch();
+        test_result = panic::catch_unwind(panic::AssertUnwindSafe(|| test(&mut harness)));
+    })?;
+    match test_result {
+        Ok(()) => Ok(()),
+        Err(err) => panic::resume_unwind(err)
+    }
+}
+
+impl Harness {
+    /// Gets a handle to the running compositor.
+    pub fn compositor(&self) -> CompositorHandle {
+        compositor_handle().unwrap_or_else(CompositorHandle::new)
+    }
+
+    /// Adds a virtual output with the given size and refresh rate (in mHz).
+    ///
+    /// Returns a handle to the output if it was accepted by the
+    /// `OutputManagerHandler`.
+    pub fn add_output(&mut self, size: Size, refresh: i32) -> Option<OutputHandle> {
+        let output = self.backend.add_output(size)?;
+        match output.run(|output| output.set_custom_mode(size, refresh)) {
+            Ok(true) => {}
+            _ => wlr_log!(L_ERROR, "Could not set mode of virtual output")
+        }
+        self.dispatch();
+        Some(output)
+    }
+
+    /// Adds a synthetic keyboard.
+    ///
+    /// The keyboard is announced to the `InputManagerHandler` before this returns.
+    pub fn add_keyboard(&mut self) -> Option<VirtualKeyboard> {
+        let device = self.backend.add_input_device(WLR_INPUT_DEVICE_KEYBOARD)?;
+        self.dispatch();
+        Some(VirtualKeyboard { device })
+    }
+
+    /// Adds a synthetic pointer.
+    ///
+    /// The pointer is announced to the `InputManagerHandler` before this returns.
+    pub fn add_pointer(&mut self) -> Option<VirtualPointer> {
+        let device = self.backend.add_input_device(WLR_INPUT_DEVICE_POINTER)?;
+        self.dispatch();
+        Some(VirtualPointer { device })
+    }
+
+    /// Adds a synthetic touch device.
+    ///
+    /// The device is announced to the `InputManagerHandler` before this returns.
+    pub fn add_touch(&mut self) -> Option<VirtualTouch> {
+        let device = self.backend.add_input_device(WLR_INPUT_DEVICE_TOUCH)?;
+        self.dispatch();
+        Some(VirtualTouch { device })
+    }
+
+    /// Gets the headless backend the compositor is running on.
+    pub fn backend(&mut self) -> &mut HeadlessBackend {
+        &mut self.backend
+    }
+
+    /// Dispatches any pending events without blocking, such as idle
+    /// callbacks, timers and client requests.
+    pub fn dispatch(&mut self) {
+        self.dispatch_timeout(Duration::from_millis(0))
+    }
+
+    /// Waits up to `timeout` for events and dispatches them.
+    pub fn dispatch_timeout(&mut self, timeout: Duration) {
+        let timeout = (timeout.as_secs() * 1000) as c_int +
+                      (timeout.subsec_nanos() / 1_000_000) as c_int;
+        unsafe {
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_flush_clients, self.display);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE,
+                          wl_event_loop_dispatch,
+                          self.event_loop,
+                          timeout);
+            ffi_dispatch!(WAYLAND_SERVER_HANDLE, wl_display_flush_clients, self.display);
+        }
+    }
+}
+
+impl VirtualKeyboard {
+    /// Presses the key with the given evdev keycode (e.g `KEY_A` is 30).
+    pub fn press(&mut self, keycode: u32) {
+        self.notify_key(keycode, true)
+    }
+
+    /// Releases the key with the given evdev keycode.
+    pub fn release(&mut self, keycode: u32) {
+        self.notify_key(keycode, false)
+    }
+
+    /// Presses and then releases the key with the given evdev keycode.
+    pub fn tap(&mut self, keycode: u32) {
+        self.press(keycode);
+        self.release(keycode);
+    }
+
+    fn notify_key(&mut self, keycode: u32, pressed: bool) {
+        unsafe {
+            let mut event = wlr_event_keyboard_key { time_msec: time_msec(),
+                                                     keycode,
+                                                     update_state: true,
+                                                     state: if pressed {
+                                                         WLR_KEY_PRESSED
+                                                     } else {
+                                                         WLR_KEY_RELEASED
+                                                     } };
+            let keyboard = self.device.dev_union().keyboard;
+            wlr_keyboard_notify_key(keyboard, &mut event);
+        }
+    }
+
+    pub fn input_device(&self) -> &InputDevice {
+        &self.device
+    }
+}
+
+impl VirtualPointer {
+    /// Moves the pointer relative to its current position.
+    pub fn move_by(&mut self, delta_x: f64, delta_y: f64) {
+        unsafe {
+            let mut event = wlr_event_pointer_motion { device: self.device.as_ptr(),
+                                                       time_msec: time_msec(),
+                                                       delta_x,
+                                                       delta_y };
+            let pointer = self.device.dev_union().pointer;
+            emit(&mut (*pointer).events.motion as *mut _ as _, &mut event);
+        }
+    }
+
+    /// Moves the pointer to an absolute position.
+    ///
+    /// The coordinates are normalized to the range 0 to 1.
+    pub fn move_to(&mut self, x: f64, y: f64) {
+        unsafe {
+            let mut event = wlr_event_pointer_motion_absolute { device: self.device.as_ptr(),
+                                                                time_msec: time_msec(),
+                                                                x,
+                                                                y };
+            let pointer = self.device.dev_union().pointer;
+            emit(&mut (*pointer).events.motion_absolute as *mut _ as _, &mut event);
+        }
+    }
+
+    /// Presses or releases a button (e.g `BTN_LEFT`).
+    pub fn button(&mut self, button: u32, state: wlr_button_state) {
+        unsafe {
+            let mut event = wlr_event_pointer_button { device: self.device.as_ptr(),
+                                                       time_msec: time_msec(),
+                                                       button,
+                                                       state };
+            let pointer = self.device.dev_union().pointer;
+            emit(&mut (*pointer).events.button as *mut _ as _, &mut event);
+        }
+    }
+
+    /// Presses and then releases a button (e.g `BTN_LEFT`).
+    pub fn click(&mut self, button: u32) {
+        self.button(button, wlr_button_state::WLR_BUTTON_PRESSED);
+        self.button(button, wlr_button_state::WLR_BUTTON_RELEASED);
+    }
+
+    /// Scrolls along the given axis, as if done with a mouse wheel.
+    pub fn scroll(&mut self, orientation: wlr_axis_orientation, delta: f64) {
+        unsafe {
+            let source = wlr_axis_source::WLR_AXIS_SOURCE_WHEEL;
+            let mut event = wlr_event_pointer_axis { device: self.device.as_ptr(),
+                                                     time_msec: time_msec(),
+                                                     source,
+                                                     orientation,
+                                                     delta };
+            let pointer = self.device.dev_union().pointer;
+            emit(&mut (*pointer).events.axis as *mut _ as _, &mut event);
+        }
+    }
+
+    pub fn input_device(&self) -> &InputDevice {
+        &self.device
+    }
+}
+
+impl VirtualTouch {
+    /// Puts a touch point down.
+    ///
+    /// The coordinates are normalized to the range 0 to 1.
+    pub fn down(&mut self, touch_id: i32, x: f64, y: f64) {
+        unsafe {
+            let mut event = wlr_event_touch_down { device: self.device.as_ptr(),
+                                                   time_msec: time_msec(),
+                                                   touch_id,
+                                                   x,
+                                                   y };
+            let touch = self.device.dev_union().touch;
+            emit(&mut (*touch).events.down as *mut _ as _, &mut event);
+        }
+    }
+
+    /// Moves a touch point that is down.
+    ///
+    /// The coordinates are normalized to the range 0 to 1.
+    pub fn motion(&mut self, touch_id: i32, x: f64, y: f64) {
+        unsafe {
+            let mut event = wlr_event_touch_motion { device: self.device.as_ptr(),
+                                                     time_msec: time_msec(),
+                                                     touch_id,
+                                                     x,
+                                                     y };
+            let touch = self.device.dev_union().touch;
+            emit(&mut (*touch).events.motion as *mut _ as _, &mut event);
+        }
+    }
+
+    /// Lifts a touch point.
+    pub fn up(&mut self, touch_id: i32) {
+        unsafe {
+            let mut event = wlr_event_touch_up { device: self.device.as_ptr(),
+                                                 time_msec: time_msec(),
+                                                 touch_id };
+            let touch = self.device.dev_union().touch;
+            emit(&mut (*touch).events.up as *mut _ as _, &mut event);
+        }
+    }
+
+    /// Cancels a touch point.
+    pub fn cancel(&mut self, touch_id: i32) {
+        unsafe {
+            let mut event = wlr_event_touch_cancel { device: self.device.as_ptr(),
+                                                     time_msec: time_msec(),
+                                                     touch_id };
+            let touch = self.device.dev_union().touch;
+            emit(&mut (*touch).events.cancel as *mut _ as _, &mut event);
+        }
+    }
+
+    pub fn input_device(&self) -> &InputDevice {
+        &self.device
+    }
+}
+
+/// Emits a signal with an event, the same way a backend would.
+unsafe fn emit<T>(signal: *mut ::wayland_sys::server::wl_signal, event: &mut T) {
+    wl_signal_emit(signal, event as *mut T as *mut c_void)
+}
+
+/// The current time in milliseconds, as expected in input events.
+fn time_msec() -> u32 {
+    let now = current_time();
+    (now.as_secs() * 1000 + (now.subsec_nanos() / 1_000_000) as u64) as u32
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use wlroots_sys::wlr_key_state::{self, *};
+
+    use super::run;
+    use {CompositorBuilder, CompositorHandle, InputManagerHandler, KeyboardHandle,
+         KeyboardHandler, OutputBuilder, OutputBuilderResult, OutputHandler,
+         OutputManagerHandler, Size, key_events::KeyEvent};
+
+    #[derive(Debug, Default)]
+    struct Events {
+        outputs: usize,
+        keyboards: usize,
+        keys: Vec<(u32, wlr_key_state)>
+    }
+
+    struct Outputs(Rc<RefCell<Events>>);
+    struct Inputs(Rc<RefCell<Events>>);
+    struct Keyboard(Rc<RefCell<Events>>);
+    struct TestOutput;
+
+    impl OutputHandler for TestOutput {}
+
+    impl OutputManagerHandler for Outputs {
+        fn output_added<'output>(&mut self,
+                                 _: CompositorHandle,
+                                 builder: OutputBuilder<'output>)
+                                 -> Option<OutputBuilderResult<'output>> {
+            self.0.borrow_mut().outputs += 1;
+            Some(builder.build_best_mode(TestOutput))
+        }
+    }
+
+    impl InputManagerHandler for Inputs {
+        fn keyboard_added(&mut self,
+                          _: CompositorHandle,
+                          _: KeyboardHandle)
+                          -> Option<Box<KeyboardHandler>> {
+            self.0.borrow_mut().keyboards += 1;
+            Some(Box::new(Keyboard(self.0.clone())))
+        }
+    }
+
+    impl KeyboardHandler for Keyboard {
+        fn on_key(&mut self, _: CompositorHandle, _: KeyboardHandle, event: &KeyEvent) {
+            self.0.borrow_mut().keys.push((event.keycode(), event.key_state()));
+        }
+    }
+
+    #[test]
+    fn output_and_keyboard_reach_handlers() {
+        let events = Rc::new(RefCell::new(Events::default()));
+        let builder =
+            CompositorBuilder::new().output_manager(Box::new(Outputs(events.clone())))
+                                    .input_manager(Box::new(Inputs(events.clone())));
+        run(builder, (), |harness| {
+            harness.add_output(Size::new(1280, 720), 60000)
+                   .expect("Could not add output");
+            let mut keyboard = harness.add_keyboard().expect("Could not add keyboard");
+            keyboard.tap(30);
+            harness.dispatch();
+        }).expect("Could not run compositor");
+        let events = events.borrow();
+        assert_eq!(events.outputs, 1);
+        assert_eq!(events.keyboards, 1);
+        assert_eq!(events.keys, vec![(30, WLR_KEY_PRESSED), (30, WLR_KEY_RELEASED)]);
+    }
+}
@@ -148,4 +148,20 @@ impl Area {
             dest
         }
     }
+
+    /// Scales the box, e.g from layout coordinates to the buffer of an output
+    /// with that scale.
+    ///
+    /// The edges are scaled and rounded rather than the origin and size, so
+    /// boxes that touch before scaling still touch afterwards, without seams
+    /// or overlaps at fractional scales.
+    pub fn scale(self, scale: c_float) -> Area {
+        let x1 = (self.origin.x as c_float * scale).round() as c_int;
+        let y1 = (self.origin.y as c_float * scale).round() as c_int;
+        let x2 = ((self.origin.x + self.size.width) as c_float * scale).round() as c_int;
+        let y2 = ((self.origin.y + self.size.height) as c_float * scale).round() as c_int;
+        Area { origin: Origin { x: x1, y: y1 },
+               size: Size { width: x2 - x1,
+                            height: y2 - y1 } }
+    }
 }
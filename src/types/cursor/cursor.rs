@@ -397,7 +397,9 @@ impl Cursor {
         unsafe {
             let dev_ptr = dev.into().map(|input_device| input_device.as_ptr())
                              .unwrap_or(ptr::null_mut());
-            wlr_cursor_warp(self.data.0, dev_ptr, x, y)
+            let warped = wlr_cursor_warp(self.data.0, dev_ptr, x, y);
+            self.cursor_changed();
+            warped
         }
     }
 
@@ -408,7 +410,8 @@ impl Cursor {
         unsafe {
             let dev_ptr = dev.into().map(|input_device| input_device.as_ptr())
                              .unwrap_or(ptr::null_mut());
-            wlr_cursor_warp_absolute(self.data.0, dev_ptr, x_mm, y_mm)
+            wlr_cursor_warp_absolute(self.data.0, dev_ptr, x_mm, y_mm);
+            self.cursor_changed()
         }
     }
 
@@ -423,7 +426,8 @@ impl Cursor {
         unsafe {
             let dev_ptr = dev.into().map(|dev| dev.as_ptr())
                              .unwrap_or(ptr::null_mut());
-            wlr_cursor_move(self.data.0, dev_ptr, delta_x, delta_y)
+            wlr_cursor_move(self.data.0, dev_ptr, delta_x, delta_y);
+            self.cursor_changed()
         }
     }
 
@@ -443,7 +447,8 @@ impl Cursor {
                                  image.height,
                                  image.hotspot_x as _,
                                  image.hotspot_y as _,
-                                 1.0)
+                                 1.0);
+            self.cursor_changed()
         }
     }
 
@@ -458,10 +463,26 @@ impl Cursor {
             let surface_ptr = surface.into()
                                      .map(|surface| surface.as_ptr())
                                      .unwrap_or(ptr::null_mut());
-            wlr_cursor_set_surface(self.data.0, surface_ptr, hotspot_x, hotspot_y)
+            wlr_cursor_set_surface(self.data.0, surface_ptr, hotspot_x, hotspot_y);
+            self.cursor_changed()
         }
     }
 
+    /// Lets the outputs of the layout update their mirrors, which draw the
+    /// hardware cursor themselves.
+    unsafe fn cursor_changed(&self) {
+        let layout = match self.data.2 {
+            Some(ref layout) => layout.clone(),
+            None => return
+        };
+        layout.run(|layout| {
+                       for (output, _) in layout.outputs() {
+                           output.run(|output| output.cursor_changed()).ok();
+                       }
+                   })
+              .ok();
+    }
+
     /// Attaches this input device to this cursor. The input device must be one of:
     ///
     /// - WLR_INPUT_DEVICE_POINTER
@@ -742,3 +763,5 @@ impl Default for CursorHandle {
         CursorHandle::new()
     }
 }
+
+impl_handle!(CursorHandle, Cursor, cursor);
@@ -1,9 +1,14 @@
 //! TODO Documentation
 use std::{fmt, panic, ptr, cell::Cell, rc::{Rc, Weak}};
 
+use libc;
+use compositor::{compositor_handle, CompositorHandle};
 use errors::{HandleErr, HandleResult};
-use wlroots_sys::{wlr_input_device, wlr_keyboard, wlr_keyboard_get_modifiers, wlr_keyboard_led,
-                  wlr_keyboard_led_update, wlr_keyboard_modifier, wlr_keyboard_set_keymap};
+use events::key_events::KeyEvent;
+use listener::ListenerToken;
+use wlroots_sys::{wl_signal, wlr_input_device, wlr_keyboard, wlr_keyboard_get_modifiers,
+                  wlr_keyboard_led, wlr_keyboard_led_update, wlr_keyboard_modifier,
+                  wlr_keyboard_set_keymap};
 pub use wlroots_sys::{wlr_key_state, wlr_keyboard_modifiers};
 
 use xkbcommon::xkb::{self, Keycode, Keymap, LedIndex, ModIndex};
@@ -184,6 +189,74 @@ impl Keyboard {
         unsafe { (*self.keyboard).modifiers }
     }
 
+    /// Calls `callback` every time a key is pressed or released.
+    pub fn on_key<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, KeyboardHandle, &KeyEvent) + 'static
+    {
+        unsafe {
+            let keyboard_ptr = self.keyboard;
+            self.listen(&mut (*self.keyboard).events.key, move |compositor, keyboard, data| {
+                let event = KeyEvent::new(data as *mut _, (*keyboard_ptr).xkb_state);
+                callback(compositor, keyboard, &event)
+            })
+        }
+    }
+
+    /// Calls `callback` every time the modifiers change.
+    pub fn on_modifiers<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, KeyboardHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.keyboard).events.modifiers,
+                        move |compositor, keyboard, _| callback(compositor, keyboard))
+        }
+    }
+
+    /// Calls `callback` every time the keymap is updated.
+    pub fn on_keymap<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, KeyboardHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.keyboard).events.keymap,
+                        move |compositor, keyboard, _| callback(compositor, keyboard))
+        }
+    }
+
+    /// Calls `callback` every time the repeat info is updated.
+    pub fn on_repeat_info<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, KeyboardHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.keyboard).events.repeat_info,
+                        move |compositor, keyboard, _| callback(compositor, keyboard))
+        }
+    }
+
+    /// Calls `callback` when the keyboard is destroyed.
+    ///
+    /// The handle passed to `callback` might not be usable anymore, but it can
+    /// still be compared with other handles to the keyboard.
+    pub fn on_destroy<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, KeyboardHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.device.as_ptr()).events.destroy,
+                        move |compositor, keyboard, _| callback(compositor, keyboard))
+        }
+    }
+
+    /// Registers `callback` on one of the signals of this keyboard.
+    unsafe fn listen<F>(&self, signal: *mut wl_signal, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, KeyboardHandle, *mut libc::c_void) + 'static
+    {
+        let handle = self.weak_reference();
+        ListenerToken::new(signal, &mut (*self.device.as_ptr()).events.destroy, move |data| {
+            if let Some(compositor) = compositor_handle() {
+                callback(compositor, handle.clone(), data)
+            }
+        })
+    }
+
     /// Creates a weak reference to a `Keyboard`.
     ///
     /// # Panics
@@ -385,10 +458,4 @@ impl fmt::Display for KeyboardModifier {
     }
 }
 
-impl PartialEq for KeyboardHandle {
-    fn eq(&self, other: &KeyboardHandle) -> bool {
-        self.keyboard == other.keyboard
-    }
-}
-
-impl Eq for KeyboardHandle {}
+impl_handle!(KeyboardHandle, Keyboard, keyboard);
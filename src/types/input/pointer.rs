@@ -2,8 +2,12 @@
 
 use std::{panic, ptr, cell::Cell, rc::{Rc, Weak}};
 
+use libc;
+use compositor::{compositor_handle, CompositorHandle};
 use errors::{HandleErr, HandleResult};
-use wlroots_sys::{wlr_input_device, wlr_pointer};
+use events::pointer_events::{AbsoluteMotionEvent, AxisEvent, ButtonEvent, MotionEvent};
+use listener::ListenerToken;
+use wlroots_sys::{wl_signal, wlr_input_device, wlr_pointer};
 
 use super::input_device::{InputDevice, InputState};
 
@@ -85,6 +89,80 @@ impl Pointer {
         self.pointer
     }
 
+    /// Calls `callback` every time the pointer moves.
+    pub fn on_motion<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, PointerHandle, &MotionEvent) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.pointer).events.motion, move |compositor, pointer, data| {
+                let event = MotionEvent::from_ptr(data as *mut _);
+                callback(compositor, pointer, &event)
+            })
+        }
+    }
+
+    /// Calls `callback` every time the pointer moves to an absolute position.
+    pub fn on_motion_absolute<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, PointerHandle, &AbsoluteMotionEvent) + 'static
+    {
+        unsafe {
+            let signal = &mut (*self.pointer).events.motion_absolute;
+            self.listen(signal, move |compositor, pointer, data| {
+                let event = AbsoluteMotionEvent::from_ptr(data as *mut _);
+                callback(compositor, pointer, &event)
+            })
+        }
+    }
+
+    /// Calls `callback` every time a button on the pointer is pressed or released.
+    pub fn on_button<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, PointerHandle, &ButtonEvent) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.pointer).events.button, move |compositor, pointer, data| {
+                let event = ButtonEvent::from_ptr(data as *mut _);
+                callback(compositor, pointer, &event)
+            })
+        }
+    }
+
+    /// Calls `callback` every time the pointer is scrolled.
+    pub fn on_axis<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, PointerHandle, &AxisEvent) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.pointer).events.axis, move |compositor, pointer, data| {
+                let event = AxisEvent::from_ptr(data as *mut _);
+                callback(compositor, pointer, &event)
+            })
+        }
+    }
+
+    /// Calls `callback` when the pointer is destroyed.
+    ///
+    /// The handle passed to `callback` might not be usable anymore, but it can
+    /// still be compared with other handles to the pointer.
+    pub fn on_destroy<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, PointerHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.device.as_ptr()).events.destroy,
+                        move |compositor, pointer, _| callback(compositor, pointer))
+        }
+    }
+
+    /// Registers `callback` on one of the signals of this pointer.
+    unsafe fn listen<F>(&self, signal: *mut wl_signal, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, PointerHandle, *mut libc::c_void) + 'static
+    {
+        let handle = self.weak_reference();
+        ListenerToken::new(signal, &mut (*self.device.as_ptr()).events.destroy, move |data| {
+            if let Some(compositor) = compositor_handle() {
+                callback(compositor, handle.clone(), data)
+            }
+        })
+    }
+
     /// Creates a weak reference to a `Pointer`.
     ///
     /// # Panics
@@ -193,7 +271,7 @@ impl PointerHandle {
     ///
     /// So don't nest `run` calls and everything will be ok :).
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
-        where F: FnOnce(&Pointer) -> R
+        where F: FnOnce(&mut Pointer) -> R
     {
         let mut pointer = unsafe { self.upgrade()? };
         let res = panic::catch_unwind(panic::AssertUnwindSafe(|| runner(&mut pointer)));
@@ -246,10 +324,4 @@ impl Clone for PointerHandle {
     }
 }
 
-impl PartialEq for PointerHandle {
-    fn eq(&self, other: &PointerHandle) -> bool {
-        self.pointer == other.pointer
-    }
-}
-
-impl Eq for PointerHandle {}
+impl_handle!(PointerHandle, Pointer, pointer);
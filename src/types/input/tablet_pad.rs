@@ -239,10 +239,4 @@ impl Clone for TabletPadHandle {
     }
 }
 
-impl PartialEq for TabletPadHandle {
-    fn eq(&self, other: &TabletPadHandle) -> bool {
-        self.pad == other.pad
-    }
-}
-
-impl Eq for TabletPadHandle {}
+impl_handle!(TabletPadHandle, TabletPad, pad);
@@ -239,10 +239,4 @@ impl Clone for TabletToolHandle {
     }
 }
 
-impl PartialEq for TabletToolHandle {
-    fn eq(&self, other: &TabletToolHandle) -> bool {
-        self.tool == other.tool
-    }
-}
-
-impl Eq for TabletToolHandle {}
+impl_handle!(TabletToolHandle, TabletTool, tool);
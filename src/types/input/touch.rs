@@ -192,7 +192,7 @@ impl TouchHandle {
     ///
     /// So don't nest `run` calls and everything will be ok :).
     pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
-        where F: FnOnce(&Touch) -> R
+        where F: FnOnce(&mut Touch) -> R
     {
         let mut touch = unsafe { self.upgrade()? };
         let res = panic::catch_unwind(panic::AssertUnwindSafe(|| runner(&mut touch)));
@@ -245,10 +245,4 @@ impl Clone for TouchHandle {
     }
 }
 
-impl PartialEq for TouchHandle {
-    fn eq(&self, other: &TouchHandle) -> bool {
-        self.touch == other.touch
-    }
-}
-
-impl Eq for TouchHandle {}
+impl_handle!(TouchHandle, Touch, touch);
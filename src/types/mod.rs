@@ -2,6 +2,7 @@ pub mod input;
 pub mod cursor;
 pub mod output;
 pub mod area;
+pub mod region;
 pub mod seat;
 pub mod surface;
 pub mod shell;
@@ -12,6 +13,7 @@ pub use self::cursor::*;
 pub use self::data_device::*;
 pub use self::input::*;
 pub use self::output::*;
+pub use self::region::*;
 pub use self::seat::*;
 pub use self::shell::*;
 pub use self::surface::*;
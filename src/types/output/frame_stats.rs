@@ -0,0 +1,144 @@
+//! Timing statistics about the frames drawn on an output.
+//!
+//! These are measured by the compositor, from the output's `frame` and
+//! `swap_buffers` events. Clients get told when their own content was shown
+//! through the presentation-time protocol instead, see
+//! `extensions::presentation`.
+
+use std::time::Duration;
+
+/// How often an output is drawn, and how long drawing takes.
+///
+/// Get it with `Output::frame_stats`. The times are measured on the
+/// monotonic clock (see `utils::current_time`) when the output emits its
+/// `frame` event and when its buffers are swapped, so they include the time
+/// the compositor spent in its frame handler but not the time until the
+/// frame is actually shown. They are not presentation timestamps, and can't
+/// tell whether a frame was shown on time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    refresh: Option<Duration>,
+    frames: u64,
+    late_frame_events: u64,
+    last_frame: Option<Duration>,
+    last_interval: Option<Duration>,
+    last_render: Option<Duration>,
+    average_render: Option<Duration>,
+    /// Whether the buffers were swapped since the last frame event.
+    swapped: bool
+}
+
+impl FrameStats {
+    /// Gets the time between two refreshes of the output in its current
+    /// mode.
+    ///
+    /// Returns `None` if the refresh rate isn't known, e.g for nested
+    /// outputs.
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh
+    }
+
+    /// Gets the number of frame events the output emitted.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Gets how many refreshes late the frame events that followed a drawn
+    /// frame were, in total.
+    ///
+    /// A late frame event usually means a frame was drawn too slowly for the
+    /// refresh it was meant for, but this is only an estimate made from when
+    /// the compositor got the events, not from when frames were shown.
+    /// Refreshes that passed while nothing was drawn are not counted, as the
+    /// output doesn't emit frame events while it is idle.
+    pub fn late_frame_events(&self) -> u64 {
+        self.late_frame_events
+    }
+
+    /// Gets the time of the last frame event.
+    pub fn last_frame_time(&self) -> Option<Duration> {
+        self.last_frame
+    }
+
+    /// Gets the time between the last two frame events.
+    pub fn last_frame_interval(&self) -> Option<Duration> {
+        self.last_interval
+    }
+
+    /// Gets the time between the last frame event and the buffer swap that
+    /// followed it.
+    pub fn render_duration(&self) -> Option<Duration> {
+        self.last_render
+    }
+
+    /// Gets a moving average of the render duration, which changes more
+    /// slowly than `render_duration`.
+    pub fn average_render_duration(&self) -> Option<Duration> {
+        self.average_render
+    }
+
+    /// Gets how long rendering can be delayed after a frame event while
+    /// still finishing before the next refresh, keeping `margin` spare.
+    ///
+    /// Delaying rendering lets clients commit more recent content, lowering
+    /// latency. Returns `None` if the refresh rate or the render duration
+    /// isn't known yet.
+    pub fn render_delay(&self, margin: Duration) -> Option<Duration> {
+        let refresh = self.refresh?;
+        let render = self.average_render?;
+        Some(refresh.checked_sub(render)
+                    .and_then(|delay| delay.checked_sub(margin))
+                    .unwrap_or_default())
+    }
+
+    pub(crate) fn set_refresh(&mut self, refresh: Option<Duration>) {
+        self.refresh = refresh
+    }
+
+    /// Records a frame event at `now`.
+    pub(crate) fn frame(&mut self, now: Duration, refresh: Option<Duration>) {
+        self.refresh = refresh;
+        let interval = self.last_frame.and_then(|last| now.checked_sub(last));
+        if let (true, Some(interval), Some(refresh)) = (self.swapped, interval, refresh) {
+            let refresh = nanos(refresh);
+            if refresh > 0 {
+                let refreshes = (nanos(interval) + refresh / 2) / refresh;
+                if refreshes > 1 {
+                    self.late_frame_events += refreshes - 1;
+                }
+            }
+        }
+        self.frames += 1;
+        self.last_frame = Some(now);
+        self.last_interval = interval;
+        self.swapped = false;
+    }
+
+    /// Records a buffer swap at `now`.
+    pub(crate) fn swap(&mut self, now: Duration) {
+        self.swapped = true;
+        let render = match self.last_frame.and_then(|last| now.checked_sub(last)) {
+            Some(render) => render,
+            None => return
+        };
+        self.last_render = Some(render);
+        self.average_render = Some(match self.average_render {
+                                       Some(average) => (average * 7 + render) / 8,
+                                       None => render
+                                   });
+    }
+}
+
+/// Converts a refresh rate in mHz, as wlroots stores it, to the time between
+/// two refreshes.
+pub(crate) fn refresh_interval(refresh: i32) -> Option<Duration> {
+    if refresh <= 0 {
+        return None
+    }
+    let interval = 1_000_000_000_000 / refresh as u64;
+    Some(Duration::new(interval / 1_000_000_000, (interval % 1_000_000_000) as u32))
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
@@ -0,0 +1,356 @@
+//! Strategies that decide where the outputs go in an `OutputLayout`, and
+//! remembering arrangements across hotplugs.
+//!
+//! Use them with `OutputLayout::arrange`, or `OutputLayout::add_arranged`
+//! when an output is plugged in.
+
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use ini::Ini;
+use ini::ini::Error as IniError;
+
+use {Origin, Output, OutputHandle, SavedLayoutError, Size};
+
+/// An output being arranged, with what strategies need to know about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrangedOutput {
+    pub output: OutputHandle,
+    pub name: String,
+    pub identity: OutputIdentity,
+    /// The size of the output in the layout, i.e after its scale and
+    /// transform.
+    pub size: Size,
+    /// Where the output is now, `None` if it is being added.
+    pub position: Option<Origin>
+}
+
+/// Identifies a monitor, whichever connector it is plugged into.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutputIdentity {
+    pub make: String,
+    pub model: String,
+    pub serial: String
+}
+
+/// Decides where the outputs of a layout go.
+pub trait LayoutStrategy {
+    /// Gets the positions of the outputs, in the same order as `outputs`.
+    ///
+    /// If fewer positions than outputs are returned, the outputs at the end
+    /// are left where they are.
+    fn arrange(&mut self, outputs: &[ArrangedOutput]) -> Vec<Origin>;
+}
+
+/// Where outputs of different sizes line up with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    /// Line up the top or left edges.
+    Start,
+    /// Line up the centers.
+    Center,
+    /// Line up the bottom or right edges.
+    End
+}
+
+/// Puts the outputs next to each other from left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Horizontal {
+    /// How the outputs line up vertically.
+    pub alignment: Alignment
+}
+
+/// Puts the outputs on top of each other from top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Vertical {
+    /// How the outputs line up horizontally.
+    pub alignment: Alignment
+}
+
+/// Puts sets of monitors back where they were the last time they were all
+/// plugged in, e.g when docking or undocking a laptop.
+///
+/// Arrangements are saved with `remember`, and are matched by the make,
+/// model and serial of all the monitors in the layout. Sets of monitors
+/// that weren't seen before are arranged by the fallback strategy.
+pub struct SavedLayout {
+    arrangements: Vec<Vec<(OutputIdentity, Origin)>>,
+    fallback: Box<LayoutStrategy>,
+    /// The file the arrangements were loaded from, to save them back.
+    path: Option<PathBuf>
+}
+
+impl ArrangedOutput {
+    /// Describes the output, which is at `position` in the layout.
+    pub fn new<T: Into<Option<Origin>>>(output: &Output, position: T) -> Self {
+        let (width, height) = output.effective_resolution();
+        ArrangedOutput { output: output.weak_reference(),
+                         name: output.name(),
+                         identity: OutputIdentity::of(output),
+                         size: Size::new(width, height),
+                         position: position.into() }
+    }
+}
+
+impl OutputIdentity {
+    /// Gets the identity of the monitor connected to the output.
+    pub fn of(output: &Output) -> Self {
+        OutputIdentity { make: output.make(),
+                         model: output.model(),
+                         serial: output.serial() }
+    }
+}
+
+impl Alignment {
+    /// Gets the offset of an item of `size` in a row or column that is
+    /// `total` wide.
+    fn offset(self, size: i32, total: i32) -> i32 {
+        match self {
+            Alignment::Start => 0,
+            Alignment::Center => (total - size) / 2,
+            Alignment::End => total - size
+        }
+    }
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Alignment::Start
+    }
+}
+
+impl Horizontal {
+    pub fn new(alignment: Alignment) -> Self {
+        Horizontal { alignment }
+    }
+}
+
+impl LayoutStrategy for Horizontal {
+    fn arrange(&mut self, outputs: &[ArrangedOutput]) -> Vec<Origin> {
+        let height = outputs.iter().map(|output| output.size.height).max().unwrap_or(0);
+        let mut x = 0;
+        outputs.iter()
+               .map(|output| {
+                        let origin = Origin::new(x,
+                                                 self.alignment.offset(output.size.height,
+                                                                       height));
+                        x += output.size.width;
+                        origin
+                    })
+               .collect()
+    }
+}
+
+impl Vertical {
+    pub fn new(alignment: Alignment) -> Self {
+        Vertical { alignment }
+    }
+}
+
+impl LayoutStrategy for Vertical {
+    fn arrange(&mut self, outputs: &[ArrangedOutput]) -> Vec<Origin> {
+        let width = outputs.iter().map(|output| output.size.width).max().unwrap_or(0);
+        let mut y = 0;
+        outputs.iter()
+               .map(|output| {
+                        let origin = Origin::new(self.alignment.offset(output.size.width, width),
+                                                 y);
+                        y += output.size.height;
+                        origin
+                    })
+               .collect()
+    }
+}
+
+impl SavedLayout {
+    /// Makes a layout without any saved arrangements.
+    pub fn new(fallback: Box<LayoutStrategy>) -> Self {
+        SavedLayout { arrangements: vec![],
+                      fallback,
+                      path: None }
+    }
+
+    /// Loads the arrangements saved in the file.
+    ///
+    /// A file that doesn't exist yet has no arrangements, so the first
+    /// `save` creates it. Sections other than `layout` sections are ignored,
+    /// but anything wrong in a `layout` section fails the whole load.
+    pub fn load<P: AsRef<Path>>(path: P,
+                                fallback: Box<LayoutStrategy>)
+                                -> Result<Self, SavedLayoutError> {
+        let path = path.as_ref();
+        let mut layout = SavedLayout::new(fallback);
+        layout.path = Some(path.to_path_buf());
+        if !path.exists() {
+            return Ok(layout)
+        }
+        let ini = match Ini::load_from_file(path) {
+            Ok(ini) => ini,
+            Err(IniError::Io(err)) => return Err(SavedLayoutError::Io(err)),
+            Err(IniError::Parse(err)) => return Err(SavedLayoutError::Parse(err.to_string()))
+        };
+        let mut arrangements = vec![];
+        for (section, properties) in ini.iter() {
+            let index = match section.as_ref().map(|section| section.trim()) {
+                Some(section) if section.starts_with("layout:") => {
+                    match section["layout:".len()..].trim().parse::<usize>() {
+                        Ok(index) => index,
+                        Err(_) => return Err(SavedLayoutError::InvalidSection(section.into()))
+                    }
+                }
+                _ => continue
+            };
+            let section = format!("layout:{}", index);
+            let invalid = |key: &str, value: &str| {
+                SavedLayoutError::InvalidValue { section: section.clone(),
+                                                 key: key.into(),
+                                                 value: value.into() }
+            };
+            let mut outputs = HashMap::new();
+            for (key, value) in properties.iter() {
+                let mut parts = key.trim().splitn(2, '.');
+                let (output, field) = match (parts.next(), parts.next()) {
+                    (Some(output), Some(field)) => (output, field),
+                    _ => return Err(invalid(key, value))
+                };
+                let entry = outputs.entry(output.to_string())
+                                   .or_insert_with(|| (OutputIdentity::default(), None));
+                match field {
+                    "make" => entry.0.make = value.clone(),
+                    "model" => entry.0.model = value.clone(),
+                    "serial" => entry.0.serial = value.clone(),
+                    "position" => {
+                        let mut coords = value.split(',').map(|coord| coord.trim().parse());
+                        match (coords.next(), coords.next(), coords.next()) {
+                            (Some(Ok(x)), Some(Ok(y)), None) => entry.1 = Some(Origin::new(x, y)),
+                            _ => return Err(invalid(key, value))
+                        }
+                    }
+                    _ => return Err(invalid(key, value))
+                }
+            }
+            let mut outputs = outputs.into_iter().collect::<Vec<_>>();
+            outputs.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut arrangement = vec![];
+            for (output, (identity, position)) in outputs {
+                match position {
+                    Some(position) => arrangement.push((identity, position)),
+                    None => {
+                        return Err(SavedLayoutError::MissingPosition { section: section.clone(),
+                                                                       output })
+                    }
+                }
+            }
+            arrangements.push((index, arrangement));
+        }
+        // NOTE
+        // rust-ini doesn't keep the order of the sections.
+        arrangements.sort_by_key(|&(index, _)| index);
+        layout.arrangements = arrangements.into_iter()
+                                          .map(|(_, arrangement)| arrangement)
+                                          .collect();
+        Ok(layout)
+    }
+
+    /// Saves the arrangements to the file they were loaded from.
+    pub fn save(&self) -> Result<(), SavedLayoutError> {
+        match self.path {
+            Some(ref path) => self.save_to(path),
+            None => Err(SavedLayoutError::NoPath)
+        }
+    }
+
+    /// Saves the arrangements to the file.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SavedLayoutError> {
+        let mut ini = Ini::new();
+        for (index, arrangement) in self.arrangements.iter().enumerate() {
+            let mut section = ini.with_section(Some(format!("layout:{}", index)));
+            for (output, &(ref identity, position)) in arrangement.iter().enumerate() {
+                section.set(format!("output{}.make", output), identity.make.clone())
+                       .set(format!("output{}.model", output), identity.model.clone())
+                       .set(format!("output{}.serial", output), identity.serial.clone())
+                       .set(format!("output{}.position", output),
+                            format!("{},{}", position.x, position.y));
+            }
+        }
+        ini.write_to_file(path)?;
+        Ok(())
+    }
+
+    /// Remembers where the outputs are, replacing the saved arrangement of
+    /// the same monitors if there is one.
+    ///
+    /// Outputs without a position are not remembered.
+    pub fn remember(&mut self, outputs: &[ArrangedOutput]) {
+        let arrangement = outputs.iter()
+                                 .filter_map(|output| {
+                                                 output.position
+                                                       .map(|position| {
+                                                                (output.identity.clone(), position)
+                                                            })
+                                             })
+                                 .collect::<Vec<_>>();
+        if arrangement.is_empty() {
+            return
+        }
+        let identities = arrangement.iter()
+                                    .map(|&(ref identity, _)| identity.clone())
+                                    .collect::<Vec<_>>();
+        self.arrangements.retain(|saved| !same_monitors(saved, &identities));
+        self.arrangements.push(arrangement);
+    }
+
+    /// Forgets all the saved arrangements.
+    pub fn clear(&mut self) {
+        self.arrangements.clear()
+    }
+
+    /// Gets the number of saved arrangements.
+    pub fn len(&self) -> usize {
+        self.arrangements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arrangements.is_empty()
+    }
+}
+
+impl LayoutStrategy for SavedLayout {
+    fn arrange(&mut self, outputs: &[ArrangedOutput]) -> Vec<Origin> {
+        let identities = outputs.iter()
+                                .map(|output| output.identity.clone())
+                                .collect::<Vec<_>>();
+        let saved = self.arrangements
+                        .iter()
+                        .find(|saved| same_monitors(saved, &identities));
+        let saved = match saved {
+            Some(saved) => saved,
+            None => return self.fallback.arrange(outputs)
+        };
+        // NOTE
+        // Identical monitors without serial numbers can't be told apart, so
+        // they are matched in order.
+        let mut used = vec![false; saved.len()];
+        identities.iter()
+                  .map(|identity| {
+                           let index = (0..saved.len())
+                               .position(|index| !used[index] && saved[index].0 == *identity)
+                               .expect("Saved arrangement doesn't match");
+                           used[index] = true;
+                           saved[index].1
+                       })
+                  .collect()
+    }
+}
+
+/// Determines if the arrangement is of exactly these monitors.
+fn same_monitors(saved: &[(OutputIdentity, Origin)], identities: &[OutputIdentity]) -> bool {
+    if saved.len() != identities.len() {
+        return false
+    }
+    let mut saved = saved.iter().map(|&(ref identity, _)| identity).collect::<Vec<_>>();
+    let mut identities = identities.iter().collect::<Vec<_>>();
+    saved.sort();
+    identities.sort();
+    saved == identities
+}
+
@@ -3,9 +3,18 @@ mod output_layout;
 mod output_mode;
 mod output_cursor;
 mod output_damage;
+mod frame_stats;
+mod layout_strategy;
+mod output_config;
+mod output_mirror;
 
+pub use self::frame_stats::FrameStats;
+pub(crate) use self::frame_stats::refresh_interval;
+pub use self::layout_strategy::*;
 pub use self::output::*;
 pub use self::output_cursor::*;
+pub use self::output_config::*;
 pub use self::output_damage::*;
+pub use self::output_mirror::ScaleMode;
 pub use self::output_layout::*;
 pub use self::output_mode::*;
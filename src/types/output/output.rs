@@ -1,16 +1,16 @@
 //! TODO Documentation
 
-use std::{panic, ptr};
+use std::{cmp, panic, ptr};
 use std::cell::Cell;
 use std::ffi::CStr;
 use std::mem::ManuallyDrop;
 use std::rc::{Rc, Weak};
 use std::time::Duration;
 
-use libc::{c_float, c_int};
+use libc::{self, c_float, c_int};
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
-use wlroots_sys::{timespec, wl_list, wl_output_subpixel, wl_output_transform, wlr_output,
-                  wlr_output_damage, wlr_output_effective_resolution, wlr_output_enable,
+use wlroots_sys::{timespec, wl_list, wl_output_subpixel, wl_output_transform, wl_signal,
+                  wlr_output, wlr_output_damage, wlr_output_effective_resolution, wlr_output_enable,
                   wlr_output_get_gamma_size, wlr_output_make_current, wlr_output_mode,
                   wlr_output_schedule_frame, wlr_output_set_custom_mode,
                   wlr_output_set_fullscreen_surface, wlr_output_set_gamma, wlr_output_set_mode,
@@ -18,20 +18,35 @@ use wlroots_sys::{timespec, wl_list, wl_output_subpixel, wl_output_transform, wl
                   wlr_output_swap_buffers, wlr_output_transformed_resolution};
 
 use manager::UserOutput;
+use compositor::{compositor_handle, CompositorHandle};
 use errors::{HandleErr, HandleResult};
-use utils::c_to_rust_string;
-use {OutputLayoutHandle, OutputMode};
+use listener::ListenerToken;
+use utils::{c_to_rust_string, current_time};
+use {OutputLayoutHandle, OutputMode, UserData};
 
 pub type Subpixel = wl_output_subpixel;
 pub type Transform = wl_output_transform;
 
-use {Origin, OutputDamage, PixmanRegion, Size, Surface, SurfaceHandle};
+use {FrameStats, Origin, OutputDamage, PixmanRegion, Size, Surface, SurfaceHandle};
+use super::frame_stats::refresh_interval;
+use super::output_mirror::Mirror;
+use render::{GenericRenderer, Matrix3, Screenshot};
 
 pub(crate) struct OutputState {
     pub(crate) output: *mut UserOutput,
     handle: Weak<Cell<bool>>,
     damage: *mut wlr_output_damage,
-    layout_handle: Option<OutputLayoutHandle>
+    layout_handle: Option<OutputLayoutHandle>,
+    /// Data attached by the user.
+    user_data: UserData,
+    frame_stats: FrameStats,
+    /// What this output mirrors, if it mirrors another output.
+    pub(crate) mirror: Option<Mirror>,
+    /// The outputs that mirror this output.
+    pub(crate) mirrored_by: Vec<OutputHandle>,
+    /// The gamma ramps last set with `Output::set_gamma_lut`, restored when
+    /// a client that changed them goes away. `None` for linear ramps.
+    gamma: Option<[Vec<u16>; 3]>
 }
 
 #[derive(Debug)]
@@ -94,7 +109,12 @@ impl Output {
         let state = Box::new(OutputState { output: ptr::null_mut(),
                                            handle,
                                            damage: damage.as_ptr(),
-                                           layout_handle: None });
+                                           layout_handle: None,
+                                           user_data: UserData::default(),
+                                           frame_stats: FrameStats::default(),
+                                           mirror: None,
+                                           mirrored_by: Vec::new(),
+                                           gamma: None });
         (*output).data = Box::into_raw(state) as *mut _;
         Output { liveliness,
                  damage,
@@ -105,7 +125,7 @@ impl Output {
         where T: Into<Option<OutputLayoutHandle>>
     {
         self.remove_from_output_layout();
-        let user_data = self.user_data();
+        let user_data = self.output_state();
         if user_data.is_null() {
             return
         }
@@ -114,14 +134,14 @@ impl Output {
         (*self.output).data = Box::into_raw(data) as *mut _;
     }
 
-    unsafe fn user_data(&mut self) -> *mut OutputState {
+    pub(crate) unsafe fn output_state(&mut self) -> *mut OutputState {
         (*self.output).data as *mut _
     }
 
     /// Used to clear the pointer to an OutputLayout when the OutputLayout
     /// removes this Output from its listing.
     pub(crate) unsafe fn clear_output_layout_data(&mut self) {
-        let user_data = self.user_data();
+        let user_data = self.output_state();
         if user_data.is_null() {
             return
         }
@@ -133,7 +153,7 @@ impl Output {
     /// Remove this Output from an OutputLayout, if it is part of an
     /// OutputLayout.
     pub(crate) unsafe fn remove_from_output_layout(&mut self) {
-        let output_data = self.user_data();
+        let output_data = self.output_state();
         if output_data.is_null() {
             return
         }
@@ -156,7 +176,7 @@ impl Output {
     /// create two mutable pointers to the same structure. We keep it internally
     /// though because we use it during the cleanup process.
     pub(crate) unsafe fn layout(&mut self) -> Option<OutputLayoutHandle> {
-        let data = self.user_data();
+        let data = self.output_state();
         if data.is_null() {
             None
         } else {
@@ -248,6 +268,38 @@ impl Output {
         unsafe { (*self.output).refresh }
     }
 
+    /// Gets timing statistics about the frames drawn on this output, e.g to
+    /// find out how long rendering takes.
+    pub fn frame_stats(&self) -> FrameStats {
+        unsafe {
+            let state = (*self.output).data as *mut OutputState;
+            let mut stats = if state.is_null() {
+                FrameStats::default()
+            } else {
+                (*state).frame_stats
+            };
+            stats.set_refresh(refresh_interval(self.refresh_rate()));
+            stats
+        }
+    }
+
+    /// Records that the output emitted a frame event.
+    pub(crate) unsafe fn record_frame(&self) {
+        let state = (*self.output).data as *mut OutputState;
+        if !state.is_null() {
+            let refresh = refresh_interval(self.refresh_rate());
+            (*state).frame_stats.frame(current_time(), refresh);
+        }
+    }
+
+    /// Records that the buffers of the output were swapped.
+    pub(crate) unsafe fn record_swap(&self) {
+        let state = (*self.output).data as *mut OutputState;
+        if !state.is_null() {
+            (*state).frame_stats.swap(current_time());
+        }
+    }
+
     pub fn current_mode<'output>(&'output self) -> Option<OutputMode<'output>> {
         unsafe {
             if (*self.output).current_mode.is_null() {
@@ -354,6 +406,23 @@ impl Output {
         unsafe { ((*self.output).width, (*self.output).height) }
     }
 
+    /// Reads back what was last rendered to the output.
+    ///
+    /// `renderer` must be the renderer the output is rendered with.
+    ///
+    /// For the gles2 renderer this reads the output's current buffer, so on
+    /// double buffered backends it should be called while rendering (see
+    /// `Renderer::read_pixels`). The buffers of headless outputs are kept
+    /// after they are swapped.
+    ///
+    /// For the software renderer this reads the last frame it rendered,
+    /// which should be of this output.
+    ///
+    /// Returns `None` if the pixels could not be read.
+    pub fn capture(&mut self, renderer: &mut GenericRenderer) -> Option<Screenshot> {
+        renderer.capture(self)
+    }
+
     /// Get the physical dimensions of the output as (width, height).
     pub fn physical_size(&self) -> (i32, i32) {
         unsafe { ((*self.output).phys_width, (*self.output).phys_height) }
@@ -377,8 +446,10 @@ impl Output {
         }
     }
 
-    pub fn transform_matrix(&self) -> [c_float; 9] {
-        unsafe { (*self.output).transform_matrix }
+    /// Gets the projection matrix of the output, which maps its pixels to
+    /// the coordinates the renderer draws in.
+    pub fn transform_matrix(&self) -> Matrix3 {
+        unsafe { Matrix3((*self.output).transform_matrix) }
     }
 
     pub fn transform(&mut self, transform: Transform) {
@@ -405,12 +476,73 @@ impl Output {
         unsafe { wlr_output_enable(self.output, enable) }
     }
 
-    /// Sets the gamma based on the size.
-    pub fn set_gamma(&mut self, size: u32, mut r: u16, mut g: u16, mut b: u16) {
-        unsafe { wlr_output_set_gamma(self.output, size, &mut r, &mut g, &mut b) }
+    /// Sets flat gamma ramps of `size` entries, each with the given value.
+    #[deprecated(note = "A single value can't describe a gamma ramp, use `set_gamma_lut`")]
+    pub fn set_gamma(&mut self, size: u32, r: u16, g: u16, b: u16) {
+        let (r, g, b) = (vec![r; size as usize], vec![g; size as usize], vec![b; size as usize]);
+        unsafe {
+            wlr_output_set_gamma(self.output,
+                                 size,
+                                 r.as_ptr() as *mut _,
+                                 g.as_ptr() as *mut _,
+                                 b.as_ptr() as *mut _);
+            let state = self.output_state();
+            if !state.is_null() {
+                (*state).gamma = Some([r, g, b]);
+            }
+        }
+    }
+
+    /// Sets the gamma lookup table of the output, one ramp per channel.
+    ///
+    /// Each ramp must have exactly `get_gamma_size` entries.
+    ///
+    /// Returns `false` if the ramps are the wrong size, or if the output
+    /// doesn't support gamma tables.
+    pub fn set_gamma_lut(&mut self, r: &[u16], g: &[u16], b: &[u16]) -> bool {
+        let size = self.get_gamma_size() as usize;
+        if size == 0 || r.len() != size || g.len() != size || b.len() != size {
+            wlr_log!(L_ERROR,
+                     "Gamma ramps of sizes {}, {}, {} don't match gamma size {}",
+                     r.len(),
+                     g.len(),
+                     b.len(),
+                     size);
+            return false
+        }
+        unsafe {
+            wlr_output_set_gamma(self.output,
+                                 size as u32,
+                                 r.as_ptr() as *mut _,
+                                 g.as_ptr() as *mut _,
+                                 b.as_ptr() as *mut _);
+            // NOTE
+            // wlroots can't tell what the gamma is, so it is kept to be put
+            // back once a gamma control client is done with the output.
+            let state = self.output_state();
+            if !state.is_null() {
+                (*state).gamma = Some([r.to_vec(), g.to_vec(), b.to_vec()]);
+            }
+        }
+        true
+    }
+
+    /// Resets the gamma lookup table to linear ramps, which is what outputs
+    /// start with.
+    pub fn reset_gamma(&mut self) {
+        unsafe {
+            let state = self.output_state();
+            if !state.is_null() {
+                (*state).gamma = None;
+            }
+            set_linear_gamma(self.output)
+        }
     }
 
     /// Get the gamma size.
+    ///
+    /// This is the number of entries in each ramp of the gamma lookup table,
+    /// or 0 if the output doesn't support gamma tables.
     pub fn get_gamma_size(&self) -> u32 {
         unsafe { wlr_output_get_gamma_size(self.output) }
     }
@@ -438,6 +570,111 @@ impl Output {
         self.output
     }
 
+    /// Gets the data attached by the user to this output.
+    pub fn user_data(&self) -> &UserData {
+        unsafe { &(*((*self.output).data as *mut OutputState)).user_data }
+    }
+
+    /// Mutably gets the data attached by the user to this output.
+    pub fn user_data_mut(&mut self) -> &mut UserData {
+        unsafe { &mut (*((*self.output).data as *mut OutputState)).user_data }
+    }
+
+    /// Calls `callback` every time the output frame is updated.
+    pub fn on_frame<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.frame,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` every time the output mode changes.
+    pub fn on_mode_change<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.mode,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` every time the output is enabled.
+    pub fn on_enable<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.enable,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` every time the output scale changes.
+    pub fn on_scale_change<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.scale,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` every time the output transforms.
+    pub fn on_transform<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.transform,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` every time the buffers are swapped on the output.
+    pub fn on_buffers_swapped<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.swap_buffers,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` every time the buffers need to be swapped on the output.
+    pub fn on_needs_swap<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.needs_swap,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Calls `callback` when the output is destroyed (e.g. unplugged).
+    ///
+    /// The handle passed to `callback` might not be usable anymore, but it can
+    /// still be compared with other handles to the output.
+    pub fn on_destroy<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.output).events.destroy,
+                        move |compositor, output, _| callback(compositor, output))
+        }
+    }
+
+    /// Registers `callback` on one of the signals of this output.
+    unsafe fn listen<F>(&self, signal: *mut wl_signal, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, OutputHandle, *mut libc::c_void) + 'static
+    {
+        let handle = self.weak_reference();
+        ListenerToken::new(signal, &mut (*self.output).events.destroy, move |data| {
+            if let Some(compositor) = compositor_handle() {
+                callback(compositor, handle.clone(), data)
+            }
+        })
+    }
+
     /// Creates a weak reference to an `Output`.
     ///
     /// # Panics
@@ -459,6 +696,41 @@ impl Output {
     }
 }
 
+/// Puts back the gamma ramps the compositor last set on the output, e.g
+/// after a gamma control client changed them.
+///
+/// Outputs whose gamma the compositor never set, or whose gamma size has
+/// changed since, get linear ramps.
+pub(crate) unsafe fn restore_gamma(output: *mut wlr_output) {
+    let state = (*output).data as *mut OutputState;
+    let size = wlr_output_get_gamma_size(output);
+    if !state.is_null() {
+        if let Some(ref ramps) = (*state).gamma {
+            if ramps[0].len() == size as usize {
+                wlr_output_set_gamma(output,
+                                     size,
+                                     ramps[0].as_ptr() as *mut _,
+                                     ramps[1].as_ptr() as *mut _,
+                                     ramps[2].as_ptr() as *mut _);
+                return
+            }
+        }
+    }
+    set_linear_gamma(output)
+}
+
+/// Sets linear gamma ramps on the output.
+unsafe fn set_linear_gamma(output: *mut wlr_output) {
+    let size = wlr_output_get_gamma_size(output);
+    if size == 0 {
+        return
+    }
+    let ramp = (0..size).map(|i| (i as u64 * 0xffff / cmp::max(size as u64 - 1, 1)) as u16)
+                        .collect::<Vec<_>>();
+    let ptr = ramp.as_ptr() as *mut _;
+    wlr_output_set_gamma(output, size, ptr, ptr, ptr);
+}
+
 impl Drop for Output {
     fn drop(&mut self) {
         // NOTE
@@ -484,6 +756,7 @@ impl Drop for Output {
         unsafe {
             self.remove_from_output_layout();
             let _ = Box::from_raw((*self.output).data as *mut OutputState);
+            (*self.output).data = ptr::null_mut();
         }
     }
 }
@@ -585,10 +858,4 @@ impl Default for OutputHandle {
     }
 }
 
-impl PartialEq for OutputHandle {
-    fn eq(&self, other: &OutputHandle) -> bool {
-        self.output == other.output
-    }
-}
-
-impl Eq for OutputHandle {}
+impl_handle!(OutputHandle, Output, output);
@@ -0,0 +1,588 @@
+//! Output settings loaded from an INI file, applied to outputs as they are
+//! plugged in.
+//!
+//! Each `output` section is a rule that matches some outputs and says how to
+//! set them up:
+//!
+//! ```ini
+//! [output:laptop]
+//! name = eDP-1
+//! mode = 1920x1080@60
+//! scale = 2
+//!
+//! [output:monitor]
+//! make = Dell Inc.
+//! model = DELL U2415
+//! position = 960,0
+//! transform = 90
+//!
+//! [output:HDMI-A-1]
+//! enabled = false
+//! ```
+//!
+//! A rule matches an output if all of its `name`, `make`, `model` and
+//! `serial` keys match it. A rule without any of them matches the output
+//! whose name is the rest of the section title.
+//!
+//! The settings are:
+//!
+//! * `enabled`: `true` or `false`.
+//! * `mode`: `WIDTHxHEIGHT`, optionally followed by `@REFRESH` in Hz. When the
+//!   output doesn't have such a mode, it is set as a custom mode.
+//! * `position`: `X,Y` in the output layout, or the `x` and `y` keys, which
+//!   take precedence over `position`.
+//! * `scale`: a number, e.g `1.5`.
+//! * `transform`: `normal`, `90`, `180`, `270`, `flipped`, `flipped-90`,
+//!   `flipped-180` or `flipped-270`.
+//!
+//! Any other key in an `output` section is an error, so that typos don't go
+//! unnoticed.
+
+use std::{str::FromStr, path::{Path, PathBuf}};
+
+use ini::Ini;
+use ini::ini::{Error as IniError, ParseError};
+use wlroots_sys::wl_output_transform;
+
+use {Origin, Output, OutputConfigError, OutputHandle, OutputLayout, OutputMode, Size, Transform};
+
+/// A set of rules saying how to set up outputs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputConfig {
+    rules: Vec<OutputRule>,
+    /// The file the rules were loaded from, to reload them.
+    path: Option<PathBuf>
+}
+
+/// Which outputs a rule applies to, and what to do with them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputRule {
+    /// The title of the section, without the `output:` prefix.
+    pub section: String,
+    pub name: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub settings: OutputSettings
+}
+
+/// The settings of an output. Settings that are `None` are left alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputSettings {
+    pub enabled: Option<bool>,
+    pub mode: Option<ModeSetting>,
+    pub position: Option<Origin>,
+    pub scale: Option<f32>,
+    pub transform: Option<Transform>
+}
+
+/// A mode to set on an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeSetting {
+    pub width: i32,
+    pub height: i32,
+    /// The refresh rate in mHz, if one was given.
+    pub refresh: Option<i32>
+}
+
+impl OutputConfig {
+    /// Makes a config without any rules.
+    pub fn new() -> Self {
+        OutputConfig::default()
+    }
+
+    /// Loads the rules from an INI file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, OutputConfigError> {
+        let path = path.as_ref();
+        let ini = match Ini::load_from_file(path) {
+            Ok(ini) => ini,
+            Err(IniError::Io(err)) => return Err(OutputConfigError::Io(err)),
+            Err(IniError::Parse(err)) => return Err(parse_error(err))
+        };
+        let mut config = OutputConfig::from_ini(&ini)?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Loads the rules again from the file they were loaded from.
+    ///
+    /// The rules are left as they were if the file can't be loaded. The new
+    /// rules are not applied, use `apply` for that.
+    pub fn reload(&mut self) -> Result<(), OutputConfigError> {
+        let path = self.path.clone().ok_or(OutputConfigError::NoPath)?;
+        *self = OutputConfig::load(path)?;
+        Ok(())
+    }
+
+    /// Makes a config out of the `output` sections of a parsed INI file.
+    ///
+    /// Other sections are ignored, so the output rules can share a file with
+    /// the rest of a compositor's configuration.
+    pub fn from_ini(ini: &Ini) -> Result<Self, OutputConfigError> {
+        let mut rules = vec![];
+        for (section, properties) in ini.iter() {
+            let section = match section.as_ref().map(|section| section.trim()) {
+                Some(section) if section.starts_with("output:") => &section["output:".len()..],
+                _ => continue
+            };
+            let mut rule = OutputRule { section: section.trim().into(),
+                                        ..OutputRule::default() };
+            // NOTE
+            // The keys aren't kept in order either, so `x` and `y` are set
+            // last to always override `position`.
+            let (coords, others): (Vec<_>, Vec<_>) =
+                properties.iter()
+                          .partition(|&(key, _)| key.trim() == "x" || key.trim() == "y");
+            for (key, value) in others.into_iter().chain(coords) {
+                rule.set(key.trim(), value.trim())?;
+            }
+            rules.push(rule);
+        }
+        // NOTE
+        // rust-ini doesn't keep the order of the sections, so they are sorted
+        // to make ties between rules predictable.
+        rules.sort_by(|a, b| a.section.cmp(&b.section));
+        Ok(OutputConfig { rules,
+                          path: None })
+    }
+
+    pub fn rules(&self) -> &[OutputRule] {
+        &self.rules
+    }
+
+    /// Adds a rule, which is used over the existing rules that are as
+    /// specific as it.
+    pub fn add_rule(&mut self, rule: OutputRule) {
+        self.rules.insert(0, rule)
+    }
+
+    /// Finds the rule for the output.
+    ///
+    /// When several rules match, the one that checks the most of `name`,
+    /// `make`, `model` and `serial` is used.
+    pub fn find(&self, output: &Output) -> Option<&OutputRule> {
+        self.find_by(|rule| rule.matches(output))
+    }
+
+    /// Finds the most specific rule among the ones that `matches` accepts.
+    ///
+    /// Ties go to the rule that comes first.
+    fn find_by<F>(&self, matches: F) -> Option<&OutputRule>
+        where F: Fn(&OutputRule) -> bool
+    {
+        let mut best: Option<&OutputRule> = None;
+        for rule in self.rules.iter().filter(|rule| matches(rule)) {
+            match best {
+                Some(best) if best.specificity() >= rule.specificity() => {}
+                _ => best = Some(rule)
+            }
+        }
+        best
+    }
+
+    /// Applies the settings of the rule for the output, if there is one.
+    ///
+    /// If the output is in an `OutputLayout` it is moved there when the rule
+    /// has a position.
+    ///
+    /// Returns `false` if no rule matched the output.
+    pub fn apply(&self, output: &mut Output) -> bool {
+        match self.find(output).cloned() {
+            Some(rule) => {
+                rule.settings.apply(output);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Applies the rules to all of the outputs, e.g after `reload`.
+    ///
+    /// Outputs that are already borrowed are skipped.
+    pub fn apply_all<'a, I>(&self, outputs: I)
+        where I: IntoIterator<Item = &'a OutputHandle>
+    {
+        for output in outputs {
+            output.run(|output| self.apply(output)).ok();
+        }
+    }
+
+    /// Adds the output to the layout, where its rule puts it.
+    ///
+    /// Outputs without a position are placed automatically.
+    pub fn add_to_layout(&self, layout: &mut OutputLayout, output: &mut Output) {
+        match self.find(output).and_then(|rule| rule.settings.position) {
+            Some(position) => layout.add(output, position),
+            None => layout.add_auto(output)
+        }
+    }
+}
+
+impl FromStr for OutputConfig {
+    type Err = OutputConfigError;
+
+    /// Parses the rules out of the contents of an INI file.
+    fn from_str(config: &str) -> Result<Self, OutputConfigError> {
+        let ini = Ini::load_from_str(config).map_err(parse_error)?;
+        OutputConfig::from_ini(&ini)
+    }
+}
+
+impl OutputRule {
+    /// Determines if the rule applies to the output.
+    pub fn matches(&self, output: &Output) -> bool {
+        self.matches_fields(&output.name(), &output.make(), &output.model(), &output.serial())
+    }
+
+    /// Determines if the rule applies to an output with these properties.
+    fn matches_fields(&self, name: &str, make: &str, model: &str, serial: &str) -> bool {
+        if self.specificity() == 0 {
+            return name == self.section
+        }
+        let check = |expected: &Option<String>, actual: &str| {
+            expected.as_ref().map(|expected| expected == actual).unwrap_or(true)
+        };
+        check(&self.name, name) && check(&self.make, make) && check(&self.model, model)
+        && check(&self.serial, serial)
+    }
+
+    /// Gets the number of properties of the output the rule checks.
+    fn specificity(&self) -> usize {
+        [&self.name, &self.make, &self.model, &self.serial].iter()
+                                                           .filter(|field| field.is_some())
+                                                           .count()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), OutputConfigError> {
+        let section = self.section.clone();
+        let invalid = || {
+            OutputConfigError::InvalidValue { section: section.clone(),
+                                              key: key.into(),
+                                              value: value.into() }
+        };
+        let settings = &mut self.settings;
+        match key {
+            "name" => self.name = Some(value.into()),
+            "make" => self.make = Some(value.into()),
+            "model" => self.model = Some(value.into()),
+            "serial" => self.serial = Some(value.into()),
+            "enabled" => settings.enabled = Some(parse_bool(value).ok_or_else(&invalid)?),
+            "mode" => settings.mode = Some(value.parse().map_err(|_| invalid())?),
+            "position" => {
+                let mut coords = value.split(',').map(|coord| coord.trim().parse());
+                match (coords.next(), coords.next(), coords.next()) {
+                    (Some(Ok(x)), Some(Ok(y)), None) => settings.position = Some(Origin::new(x, y)),
+                    _ => return Err(invalid())
+                }
+            }
+            "x" | "y" => {
+                let coord = value.parse().map_err(|_| invalid())?;
+                let position = settings.position.get_or_insert(Origin::default());
+                if key == "x" {
+                    position.x = coord
+                } else {
+                    position.y = coord
+                }
+            }
+            "scale" => {
+                let scale = value.parse::<f32>().map_err(|_| invalid())?;
+                if !(scale > 0.0) {
+                    return Err(invalid())
+                }
+                settings.scale = Some(scale)
+            }
+            "transform" | "rotate" => {
+                settings.transform = Some(parse_transform(value).ok_or_else(&invalid)?)
+            }
+            _ => {
+                return Err(OutputConfigError::UnknownKey { section: section.clone(),
+                                                           key: key.into() })
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputSettings {
+    /// Sets up the output with these settings.
+    ///
+    /// Returns `false` if the mode could not be set, the other settings are
+    /// applied anyway.
+    pub fn apply(&self, output: &mut Output) -> bool {
+        if let Some(enabled) = self.enabled {
+            output.enable(enabled);
+            if !enabled {
+                return true
+            }
+        }
+        let mode_set = self.mode.map(|mode| mode.apply(output)).unwrap_or(true);
+        if let Some(scale) = self.scale {
+            output.set_scale(scale);
+        }
+        if let Some(transform) = self.transform {
+            output.transform(transform);
+        }
+        if let Some(position) = self.position {
+            let moved = match unsafe { output.layout() } {
+                Some(layout) => layout.run(|layout| layout.move_output(output, position)).is_ok(),
+                None => false
+            };
+            if !moved {
+                output.set_position(position);
+            }
+        }
+        mode_set
+    }
+}
+
+impl ModeSetting {
+    /// Sets the mode on the output, using one of the output's modes if it
+    /// has a matching one.
+    ///
+    /// Returns `false` if the mode could not be set.
+    pub fn apply(&self, output: &mut Output) -> bool {
+        // NOTE
+        // The modes borrow the output, so the chosen one is turned back into
+        // a pointer to be able to set it.
+        let mode = self.find(output).map(|mode| unsafe { mode.as_ptr() });
+        match mode {
+            Some(mode) => unsafe { output.set_mode(OutputMode::new(mode)) },
+            None => {
+                output.set_custom_mode(Size::new(self.width, self.height),
+                                       self.refresh.unwrap_or(0))
+            }
+        }
+    }
+
+    /// Finds the mode of the output that matches this one, if it has one.
+    pub(crate) fn find<'output>(&self, output: &'output Output) -> Option<OutputMode<'output>> {
+        output.modes()
+              .into_iter()
+              .filter(|mode| mode.dimensions() == (self.width, self.height))
+              .min_by_key(|mode| match self.refresh {
+                              Some(refresh) => (mode.refresh() - refresh).abs(),
+                              None => -mode.refresh()
+                          })
+              .filter(|mode| match self.refresh {
+                          // Refresh rates are rounded differently by different
+                          // tools, so allow being 1 Hz off.
+                          Some(refresh) => (mode.refresh() - refresh).abs() <= 1000,
+                          None => true
+                      })
+    }
+}
+
+impl FromStr for ModeSetting {
+    type Err = ();
+
+    /// Parses `WIDTHxHEIGHT` or `WIDTHxHEIGHT@REFRESH`, with the refresh
+    /// rate in Hz.
+    fn from_str(mode: &str) -> Result<Self, ()> {
+        let mut parts = mode.splitn(2, '@');
+        let size = parts.next().ok_or(())?;
+        let refresh = match parts.next() {
+            Some(refresh) => {
+                let refresh = refresh.trim().trim_right_matches("Hz").parse::<f64>()
+                                     .map_err(|_| ())?;
+                if !(refresh > 0.0) {
+                    return Err(())
+                }
+                Some((refresh * 1000.0).round() as i32)
+            }
+            None => None
+        };
+        let mut size = size.splitn(2, 'x').map(|value| value.trim().parse::<i32>());
+        match (size.next(), size.next()) {
+            (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => {
+                Ok(ModeSetting { width,
+                                 height,
+                                 refresh })
+            }
+            _ => Err(())
+        }
+    }
+}
+
+fn parse_error(err: ParseError) -> OutputConfigError {
+    OutputConfigError::Parse(err.to_string())
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None
+    }
+}
+
+fn parse_transform(value: &str) -> Option<Transform> {
+    use self::wl_output_transform::*;
+    Some(match value.to_lowercase().as_str() {
+             "normal" | "0" => WL_OUTPUT_TRANSFORM_NORMAL,
+             "90" => WL_OUTPUT_TRANSFORM_90,
+             "180" => WL_OUTPUT_TRANSFORM_180,
+             "270" => WL_OUTPUT_TRANSFORM_270,
+             "flipped" => WL_OUTPUT_TRANSFORM_FLIPPED,
+             "flipped-90" => WL_OUTPUT_TRANSFORM_FLIPPED_90,
+             "flipped-180" => WL_OUTPUT_TRANSFORM_FLIPPED_180,
+             "flipped-270" => WL_OUTPUT_TRANSFORM_FLIPPED_270,
+             _ => return None
+         })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::wl_output_transform::*;
+
+    fn find<'config>(config: &'config OutputConfig,
+                     name: &str,
+                     make: &str,
+                     model: &str)
+                     -> Option<&'config str> {
+        config.find_by(|rule| rule.matches_fields(name, make, model, ""))
+              .map(|rule| rule.section.as_str())
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!("1920x1080".parse::<ModeSetting>(),
+                   Ok(ModeSetting { width: 1920,
+                                    height: 1080,
+                                    refresh: None }));
+        assert_eq!("2560x1440@59.951".parse::<ModeSetting>(),
+                   Ok(ModeSetting { width: 2560,
+                                    height: 1440,
+                                    refresh: Some(59951) }));
+        assert_eq!(" 1280 x 720 @ 60Hz".parse::<ModeSetting>().map(|mode| mode.refresh),
+                   Ok(Some(60000)));
+        for mode in &["1920", "0x1080", "1920x-1", "axb", "1920x1080@", "1920x1080@0"] {
+            assert_eq!(mode.parse::<ModeSetting>(), Err(()), "{} should be invalid", mode);
+        }
+    }
+
+    #[test]
+    fn parse_transforms() {
+        let names = [("normal", WL_OUTPUT_TRANSFORM_NORMAL),
+                     ("0", WL_OUTPUT_TRANSFORM_NORMAL),
+                     ("90", WL_OUTPUT_TRANSFORM_90),
+                     ("180", WL_OUTPUT_TRANSFORM_180),
+                     ("270", WL_OUTPUT_TRANSFORM_270),
+                     ("flipped", WL_OUTPUT_TRANSFORM_FLIPPED),
+                     ("Flipped-90", WL_OUTPUT_TRANSFORM_FLIPPED_90),
+                     ("flipped-180", WL_OUTPUT_TRANSFORM_FLIPPED_180),
+                     ("FLIPPED-270", WL_OUTPUT_TRANSFORM_FLIPPED_270)];
+        for &(name, transform) in &names {
+            assert_eq!(parse_transform(name), Some(transform));
+        }
+        assert_eq!(parse_transform("45"), None);
+        assert_eq!(parse_transform("flipped90"), None);
+    }
+
+    #[test]
+    fn parse_bools() {
+        for value in &["true", "Yes", "on", "1"] {
+            assert_eq!(parse_bool(value), Some(true));
+        }
+        for value in &["false", "NO", "off", "0"] {
+            assert_eq!(parse_bool(value), Some(false));
+        }
+        assert_eq!(parse_bool("maybe"), None);
+        assert_eq!(parse_bool(""), None);
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = "[general]\n\
+                      theme = dark\n\
+                      [output:monitor]\n\
+                      make = Dell Inc.\n\
+                      position = 960,0\n\
+                      transform = 90\n\
+                      [output:laptop]\n\
+                      name = eDP-1\n\
+                      mode = 1920x1080@60\n\
+                      scale = 2\n\
+                      enabled = yes\n"
+            .parse::<OutputConfig>()
+            .unwrap();
+        let rules = config.rules();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].section, "laptop");
+        assert_eq!(rules[0].name, Some("eDP-1".into()));
+        assert_eq!(rules[0].settings,
+                   OutputSettings { enabled: Some(true),
+                                    mode: Some(ModeSetting { width: 1920,
+                                                             height: 1080,
+                                                             refresh: Some(60000) }),
+                                    position: None,
+                                    scale: Some(2.0),
+                                    transform: None });
+        assert_eq!(rules[1].section, "monitor");
+        assert_eq!(rules[1].make, Some("Dell Inc.".into()));
+        assert_eq!(rules[1].settings.position, Some(Origin::new(960, 0)));
+        assert_eq!(rules[1].settings.transform, Some(WL_OUTPUT_TRANSFORM_90));
+    }
+
+    #[test]
+    fn reject_bad_config() {
+        match "[output:a]\nscale = 0\n".parse::<OutputConfig>() {
+            Err(OutputConfigError::InvalidValue { section, key, value }) => {
+                assert_eq!((section.as_str(), key.as_str(), value.as_str()), ("a", "scale", "0"))
+            }
+            other => panic!("Unexpected result {:?}", other)
+        }
+        match "[output:a]\nscael = 2\n".parse::<OutputConfig>() {
+            Err(OutputConfigError::UnknownKey { section, key }) => {
+                assert_eq!((section.as_str(), key.as_str()), ("a", "scael"))
+            }
+            other => panic!("Unexpected result {:?}", other)
+        }
+        assert!("[output:a]\nposition = 1,2,3\n".parse::<OutputConfig>().is_err());
+        assert!("[output:a]\nenabled = maybe\n".parse::<OutputConfig>().is_err());
+        assert!("[output:a\nscale = 2\n".parse::<OutputConfig>().is_err());
+    }
+
+    #[test]
+    fn coordinates_override_position() {
+        let position = |config: &str| {
+            config.parse::<OutputConfig>().unwrap().rules()[0].settings.position
+        };
+        assert_eq!(position("[output:a]\nposition = 1,2\nx = 5\n"), Some(Origin::new(5, 2)));
+        assert_eq!(position("[output:a]\ny = 5\nposition = 1,2\n"), Some(Origin::new(1, 5)));
+        assert_eq!(position("[output:a]\ny = 3\n"), Some(Origin::new(0, 3)));
+        assert_eq!(position("[output:a]\nx = 4\ny = 3\n"), Some(Origin::new(4, 3)));
+    }
+
+    #[test]
+    fn find_most_specific_rule() {
+        let config = "[output:eDP-1]\n\
+                      scale = 2\n\
+                      [output:dell]\n\
+                      make = Dell\n\
+                      model = U2415\n\
+                      [output:any-dell]\n\
+                      make = Dell\n"
+            .parse::<OutputConfig>()
+            .unwrap();
+        assert_eq!(find(&config, "DP-1", "Dell", "U2415"), Some("dell"));
+        assert_eq!(find(&config, "DP-2", "Dell", "P2419"), Some("any-dell"));
+        assert_eq!(find(&config, "eDP-1", "BOE", "0x0747"), Some("eDP-1"));
+        assert_eq!(find(&config, "HDMI-A-1", "Acer", "X"), None);
+    }
+
+    #[test]
+    fn find_breaks_ties_in_order() {
+        let mut config = "[output:b]\nmake = Dell\n[output:a]\nmake = Dell\n"
+            .parse::<OutputConfig>()
+            .unwrap();
+        assert_eq!(find(&config, "DP-1", "Dell", ""), Some("a"));
+        config.add_rule(OutputRule { section: "c".into(),
+                                     make: Some("Dell".into()),
+                                     ..OutputRule::default() });
+        assert_eq!(find(&config, "DP-1", "Dell", ""), Some("c"));
+        config.add_rule(OutputRule { section: "d".into(),
+                                     ..OutputRule::default() });
+        assert_eq!(find(&config, "DP-1", "Dell", ""), Some("c"));
+    }
+}
@@ -40,14 +40,18 @@ impl OutputCursor {
     pub fn set_image(&mut self, image: &Image) -> bool {
         unsafe {
             let cursor = self.cursor;
-            let res = self.output_handle.run(|_| {
-                                                 wlr_output_cursor_set_image(cursor,
-                                                                             image.pixels.as_ptr(),
-                                                                             image.stride,
-                                                                             image.width,
-                                                                             image.height,
-                                                                             image.hotspot_x,
-                                                                             image.hotspot_y)
+            let pixels = image.pixels.as_ptr();
+            let res = self.output_handle.run(|output| {
+                                                 let res =
+                                                     wlr_output_cursor_set_image(cursor,
+                                                                                 pixels,
+                                                                                 image.stride,
+                                                                                 image.width,
+                                                                                 image.height,
+                                                                                 image.hotspot_x,
+                                                                                 image.hotspot_y);
+                                                 output.cursor_changed();
+                                                 res
                                              });
             match res {
                 Ok(res) => res,
@@ -66,11 +70,12 @@ impl OutputCursor {
                                      .map(|surface| surface.as_ptr())
                                      .unwrap_or_else(|| ptr::null_mut());
             let cursor = self.cursor;
-            let res = self.output_handle.run(|_| {
+            let res = self.output_handle.run(|output| {
                                                  wlr_output_cursor_set_surface(cursor,
                                                                                surface_ptr,
                                                                                hotspot_x,
-                                                                               hotspot_y)
+                                                                               hotspot_y);
+                                                 output.cursor_changed()
                                              });
             match res {
                 Ok(_) | Err(HandleErr::AlreadyDropped) => {}
@@ -83,7 +88,11 @@ impl OutputCursor {
     pub fn move_to(&mut self, x: f64, y: f64) -> bool {
         unsafe {
             let cursor = self.cursor;
-            let res = self.output_handle.run(|_| wlr_output_cursor_move(cursor, x, y));
+            let res = self.output_handle.run(|output| {
+                                                 let res = wlr_output_cursor_move(cursor, x, y);
+                                                 output.cursor_changed();
+                                                 res
+                                             });
             match res {
                 Ok(res) => res,
                 Err(HandleErr::AlreadyDropped) => false,
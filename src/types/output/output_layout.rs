@@ -14,7 +14,7 @@ use wlroots_sys::{wlr_output_effective_resolution, wlr_output_layout, wlr_output
 
 use errors::{HandleErr, HandleResult};
 
-use {Area, Origin, Output, OutputHandle};
+use {Area, ArrangedOutput, LayoutStrategy, Origin, Output, OutputHandle};
 use compositor::{compositor_handle, CompositorHandle};
 
 struct OutputLayoutState {
@@ -199,14 +199,26 @@ impl OutputLayout {
     }
 
     /// Adds an output to the layout at the given coordinates.
+    ///
+    /// Outputs that mirror another output are not added.
     pub fn add(&mut self, output: &mut Output, origin: Origin) {
+        if output.mirror_source().is_some() {
+            wlr_log!(L_ERROR, "Not adding mirroring {:?} to {:?}", output, self);
+            return
+        }
         let (x, y) = (origin.x, origin.y);
         unsafe { wlr_output_layout_add(self.data.0, output.as_ptr(), x, y) }
     }
 
     /// Adds an output to the layout, automatically positioning it with
     /// the others that are already there.
+    ///
+    /// Outputs that mirror another output are not added.
     pub fn add_auto(&mut self, output: &mut Output) {
+        if output.mirror_source().is_some() {
+            wlr_log!(L_ERROR, "Not adding mirroring {:?} to {:?}", output, self);
+            return
+        }
         unsafe {
             let layout_handle = self.weak_reference();
             output.set_output_layout(Some(layout_handle));
@@ -215,6 +227,64 @@ impl OutputLayout {
         }
     }
 
+    /// Adds an output to the layout, then moves all the outputs to where
+    /// the strategy puts them.
+    ///
+    /// Outputs that mirror another output are not added.
+    pub fn add_arranged(&mut self, output: &mut Output, strategy: &mut LayoutStrategy) {
+        self.add_auto(output);
+        if output.mirror_source().is_none() {
+            self.arrange_with(strategy, Some(output))
+        }
+    }
+
+    /// Moves the outputs in the layout to where the strategy puts them,
+    /// e.g after one was removed.
+    ///
+    /// Outputs that are already borrowed are left where they are.
+    pub fn arrange(&mut self, strategy: &mut LayoutStrategy) {
+        self.arrange_with(strategy, None)
+    }
+
+    /// Gets descriptions of the outputs in the layout, e.g to remember
+    /// where they are with `SavedLayout::remember`.
+    ///
+    /// Outputs that are already borrowed are left out.
+    pub fn arranged_outputs(&mut self) -> Vec<ArrangedOutput> {
+        self.outputs()
+            .into_iter()
+            .filter_map(|(handle, origin)| {
+                            handle.run(|output| ArrangedOutput::new(output, origin)).ok()
+                        })
+            .collect()
+    }
+
+    /// Arranges the outputs, one of which may be borrowed by the caller
+    /// because it is being added.
+    fn arrange_with(&mut self, strategy: &mut LayoutStrategy, mut added: Option<&mut Output>) {
+        let added_handle = added.as_ref().map(|output| output.weak_reference());
+        let mut outputs = vec![];
+        for (handle, origin) in self.outputs() {
+            if Some(&handle) == added_handle.as_ref() {
+                if let Some(ref output) = added {
+                    outputs.push(ArrangedOutput::new(output, None));
+                }
+            } else if let Ok(output) = handle.run(|output| ArrangedOutput::new(output, origin)) {
+                outputs.push(output);
+            }
+        }
+        let positions = strategy.arrange(&outputs);
+        for (output, position) in outputs.into_iter().zip(positions) {
+            if Some(&output.output) == added_handle.as_ref() {
+                if let Some(ref mut added) = added {
+                    self.move_output(added, position);
+                }
+            } else {
+                output.output.run(|output| self.move_output(output, position)).ok();
+            }
+        }
+    }
+
     /// Moves the output to the given coordinates.
     ///
     /// If the output is not part of this layout this does nothing.
@@ -445,6 +515,10 @@ impl OutputLayoutHandle {
             self.upgrade().ok();
         }
     }
+
+    pub(crate) unsafe fn as_ptr(&self) -> *mut wlr_output_layout {
+        self.layout
+    }
 }
 
 impl<'output> OutputLayoutOutput<'output> {
@@ -500,10 +574,4 @@ impl Default for OutputLayoutHandle {
     }
 }
 
-impl PartialEq for OutputLayoutHandle {
-    fn eq(&self, other: &OutputLayoutHandle) -> bool {
-        self.layout == other.layout
-    }
-}
-
-impl Eq for OutputLayoutHandle {}
+impl_handle!(OutputLayoutHandle, OutputLayout, layout);
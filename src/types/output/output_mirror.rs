@@ -0,0 +1,337 @@
+//! Showing what one output shows on another, e.g on a projector with a
+//! different resolution.
+//!
+//! Every time the source output swaps its buffers, its frame is read back
+//! and the outputs mirroring it are scheduled to draw it. Reading back is a
+//! full copy of the frame, so it is skipped while none of the mirrors are
+//! enabled. The mirrors are drawn by the library, their handlers' `on_frame`
+//! is not called while they mirror something.
+//!
+//! Hardware cursors aren't part of the source's buffers, so the source's
+//! cursor is drawn on top of the frame by the mirrors. wlroots doesn't say
+//! when it moves, so the mirrors are redrawn when it is moved or given a new
+//! image through `Cursor` or `OutputCursor`. A cursor surface that changes
+//! its image by itself shows up on the mirrors with the next frame.
+//!
+//! Frames are read back and drawn with the compositor's renderer, so
+//! mirroring works with the software renderer too. Without a renderer the
+//! mirrors aren't drawn at all.
+
+use std::{ptr, rc::Rc};
+
+use wlroots_sys::{wl_output_transform, wl_shm_format, wlr_output_cursor, wlr_renderer,
+                  wlr_texture, wlr_texture_destroy, wlr_texture_from_pixels};
+
+use {Area, Handle, Origin, Output, OutputHandle, OutputState, Size};
+use compositor::running_renderer;
+use render::{Matrix3, Screenshot, Texture, WallpaperMode};
+
+/// How the source's frame is fitted to a mirror with another size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScaleMode {
+    /// Scale the frame to fit the mirror, keeping its aspect ratio. The
+    /// rest of the mirror is black.
+    Letterbox,
+    /// Scale the frame to cover the whole mirror, distorting it if the
+    /// aspect ratios differ.
+    Stretch
+}
+
+/// What an output mirrors, stored in its `OutputState`.
+#[derive(Debug)]
+pub(crate) struct Mirror {
+    source: OutputHandle,
+    mode: ScaleMode,
+    /// The last frame read back from the source, not uploaded yet.
+    frame: Option<Rc<Screenshot>>,
+    /// The last frame of the source, owned by this mirror.
+    texture: *mut wlr_texture,
+    /// Where the source's hardware cursor was and what it looked like when
+    /// the mirror was last drawn.
+    cursor: Option<(Area, *mut wlr_texture)>
+}
+
+impl ScaleMode {
+    /// Gets where a frame of the given size goes on a mirror of the given
+    /// size.
+    pub fn area(self, frame: Size, mirror: Size) -> Area {
+        let whole = Area::new(Origin::new(0, 0), mirror);
+        match self {
+            ScaleMode::Stretch => whole,
+            ScaleMode::Letterbox => {
+                WallpaperMode::Fit.areas(frame, mirror).pop().unwrap_or(whole)
+            }
+        }
+    }
+}
+
+impl Output {
+    /// Makes this output show what `source` shows, scaled to its own
+    /// resolution.
+    ///
+    /// The output is removed from its `OutputLayout`, as it doesn't have
+    /// a place of its own anymore, and it can't be added back to one until
+    /// `stop_mirroring` is called.
+    ///
+    /// The frame is shown as it is in the source's buffers, so the mirror
+    /// should use the same transform as the source.
+    ///
+    /// Returns `false` if the source is this output or is gone.
+    pub fn mirror_from(&mut self, source: &OutputHandle, mode: ScaleMode) -> bool {
+        let this = self.weak_reference();
+        if *source == this {
+            return false
+        }
+        self.stop_mirroring();
+        let registered = source.run(|source| unsafe {
+                                    let state = source.output_state();
+                                    if !state.is_null() {
+                                        (*state).mirrored_by.push(this);
+                                    }
+                                });
+        if registered.is_err() {
+            return false
+        }
+        unsafe {
+            self.remove_from_output_layout();
+            let state = self.output_state();
+            if state.is_null() {
+                return false
+            }
+            (*state).mirror = Some(Mirror { source: source.clone(),
+                                            mode,
+                                            frame: None,
+                                            texture: ptr::null_mut(),
+                                            cursor: None });
+        }
+        self.schedule_frame();
+        true
+    }
+
+    /// Stops mirroring, the output's handler draws it again from the next
+    /// frame on.
+    pub fn stop_mirroring(&mut self) {
+        let this = self.weak_reference();
+        let mirror = unsafe {
+            let state = self.output_state();
+            if state.is_null() {
+                return
+            }
+            match (*state).mirror.take() {
+                Some(mirror) => mirror,
+                None => return
+            }
+        };
+        mirror.source
+              .run(|source| unsafe {
+                       let state = source.output_state();
+                       if !state.is_null() {
+                           (*state).mirrored_by.retain(|mirror| *mirror != this);
+                       }
+                   })
+              .ok();
+        self.schedule_frame();
+    }
+
+    /// Gets the output this output mirrors, if it mirrors one.
+    pub fn mirror_source(&self) -> Option<OutputHandle> {
+        unsafe {
+            let state = (*self.as_ptr()).data as *mut OutputState;
+            if state.is_null() {
+                return None
+            }
+            (*state).mirror.as_ref().map(|mirror| mirror.source.clone())
+        }
+    }
+
+    /// Reads back the frame that is being swapped in and hands it to the
+    /// outputs mirroring this one.
+    ///
+    /// Must be called before the buffers are swapped, while the frame is
+    /// still in the back buffer.
+    pub(crate) unsafe fn capture_for_mirrors(&mut self) {
+        let state = self.output_state();
+        if state.is_null() {
+            return
+        }
+        (*state).mirrored_by.retain(|mirror| mirror.is_alive());
+        let drawing = (*state).mirrored_by
+                              .iter()
+                              .any(|mirror| mirror.run(|mirror| mirror.enabled()).unwrap_or(false));
+        if !drawing {
+            return
+        }
+        let mut renderer = match running_renderer() {
+            Some(renderer) => renderer,
+            None => return
+        };
+        let frame = match renderer.capture(self) {
+            Some(frame) => Rc::new(frame),
+            None => return
+        };
+        for mirror in &(*state).mirrored_by {
+            mirror.run(|mirror| {
+                           let state = mirror.output_state();
+                           if state.is_null() || !mirror.enabled() {
+                               return
+                           }
+                           if let Some(ref mut mirror) = (*state).mirror {
+                               mirror.frame = Some(frame.clone());
+                           }
+                           mirror.schedule_frame();
+                       })
+                  .ok();
+        }
+    }
+
+    /// Schedules a frame on the outputs mirroring this one that show its
+    /// hardware cursor somewhere else or with another image.
+    ///
+    /// Must be called after the cursor is moved or changes its image.
+    pub(crate) unsafe fn cursor_changed(&mut self) {
+        let state = self.output_state();
+        if state.is_null() || (*state).mirrored_by.is_empty() {
+            return
+        }
+        let cursor = hardware_cursor(self);
+        for mirror in &(*state).mirrored_by {
+            mirror.run(|mirror| {
+                           let state = mirror.output_state();
+                           if state.is_null() || !mirror.enabled() {
+                               return
+                           }
+                           let stale = match (*state).mirror {
+                               Some(ref mirror) => mirror.cursor != cursor,
+                               None => false
+                           };
+                           if stale {
+                               mirror.schedule_frame();
+                           }
+                       })
+                  .ok();
+        }
+    }
+
+    /// Draws the source's last frame, if this output is mirroring.
+    ///
+    /// Returns `false` if the output isn't mirroring, and should be drawn by
+    /// its handler.
+    pub(crate) unsafe fn render_mirror(&mut self) -> bool {
+        let state = self.output_state();
+        if state.is_null() {
+            return false
+        }
+        let source_alive = match (*state).mirror {
+            Some(ref mirror) => mirror.source.is_alive(),
+            None => return false
+        };
+        if !source_alive {
+            // NOTE
+            // The source was unplugged, so go back to drawing this output
+            // normally instead of showing a stale frame.
+            (*state).mirror = None;
+            return false
+        }
+        let mirror = match (*state).mirror {
+            Some(ref mut mirror) => mirror,
+            None => return false
+        };
+        let mut renderer = match running_renderer() {
+            Some(renderer) => renderer,
+            None => return true
+        };
+        if let Some(frame) = mirror.frame.take() {
+            mirror.upload(renderer.as_ptr(), &frame);
+        }
+        let source_cursor = mirror.source.run(|source| {
+                                                  let (width, height) = source.size();
+                                                  let size = Size::new(width, height);
+                                                  (hardware_cursor(source), size)
+                                              });
+        let (cursor, source_size) = source_cursor.unwrap_or((None, Size::default()));
+        {
+            let mut renderer = renderer.render(self, None);
+            renderer.clear([0.0, 0.0, 0.0, 1.0]);
+            if !mirror.texture.is_null() {
+                let texture = Texture::from_ptr(mirror.texture);
+                let (frame_width, frame_height) = texture.size();
+                let frame_size = Size::new(frame_width, frame_height);
+                let (width, height) = renderer.output.transformed_resolution();
+                let area = mirror.mode.area(frame_size, Size::new(width, height));
+                let projection = renderer.output.transform_matrix();
+                let transform = wl_output_transform::WL_OUTPUT_TRANSFORM_NORMAL;
+                let matrix = Matrix3::project_box(area, transform, 0.0, projection);
+                renderer.render_texture_with_matrix(&texture, matrix);
+                if let Some((cursor_area, cursor_texture)) = cursor {
+                    let cursor_area = scale_area(cursor_area, source_size, area);
+                    let matrix = Matrix3::project_box(cursor_area, transform, 0.0, projection);
+                    renderer.render_texture_with_matrix(&Texture::from_ptr(cursor_texture),
+                                                        matrix);
+                }
+            }
+        }
+        mirror.cursor = cursor;
+        true
+    }
+}
+
+impl Mirror {
+    /// Replaces the texture with the frame, made by the renderer that draws
+    /// it.
+    unsafe fn upload(&mut self, renderer: *mut wlr_renderer, frame: &Screenshot) {
+        if !self.texture.is_null() {
+            wlr_texture_destroy(self.texture);
+        }
+        self.texture = wlr_texture_from_pixels(renderer,
+                                               wl_shm_format::WL_SHM_FORMAT_ARGB8888,
+                                               frame.width() * 4,
+                                               frame.width(),
+                                               frame.height(),
+                                               frame.pixels().as_ptr() as *const _);
+    }
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        if !self.texture.is_null() {
+            unsafe { wlr_texture_destroy(self.texture) }
+        }
+    }
+}
+
+/// Gets where the output's hardware cursor is in the output's buffer and its
+/// texture, if it is shown.
+unsafe fn hardware_cursor(output: &mut Output) -> Option<(Area, *mut wlr_texture)> {
+    let cursor: *mut wlr_output_cursor = (*output.as_ptr()).hardware_cursor;
+    if cursor.is_null() || !(*cursor).enabled || !(*cursor).visible {
+        return None
+    }
+    let texture = if !(*cursor).texture.is_null() {
+        (*cursor).texture
+    } else if !(*cursor).surface.is_null() {
+        (*(*cursor).surface).texture
+    } else {
+        return None
+    };
+    if texture.is_null() {
+        return None
+    }
+    let origin = Origin::new((*cursor).x as i32 - (*cursor).hotspot_x,
+                             (*cursor).y as i32 - (*cursor).hotspot_y);
+    let size = Size::new((*cursor).width as i32, (*cursor).height as i32);
+    Some((Area::new(origin, size), texture))
+}
+
+/// Maps an area of the source's buffer to where it is drawn on the mirror.
+fn scale_area(area: Area, source: Size, target: Area) -> Area {
+    if source.width <= 0 || source.height <= 0 {
+        return area
+    }
+    let scale_x = target.size.width as f64 / source.width as f64;
+    let scale_y = target.size.height as f64 / source.height as f64;
+    let x = target.origin.x + (area.origin.x as f64 * scale_x).round() as i32;
+    let y = target.origin.y + (area.origin.y as f64 * scale_y).round() as i32;
+    Area::new(Origin::new(x, y),
+              Size::new((area.size.width as f64 * scale_x).round() as i32,
+                        (area.size.height as f64 * scale_y).round() as i32))
+}
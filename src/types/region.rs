@@ -0,0 +1,388 @@
+//! Wrapper for pixman's 32 bit regions, which are used for damage tracking.
+
+use libc::{c_int, c_uint};
+use std::{fmt, mem, ptr, slice, iter::FromIterator};
+
+use wlroots_sys::{pixman_box32_t, pixman_region32_clear, pixman_region32_contains_point,
+                  pixman_region32_copy, pixman_region32_equal, pixman_region32_extents,
+                  pixman_region32_fini, pixman_region32_init, pixman_region32_init_rect,
+                  pixman_region32_init_rects, pixman_region32_intersect,
+                  pixman_region32_intersect_rect, pixman_region32_not_empty,
+                  pixman_region32_rectangles, pixman_region32_subtract, pixman_region32_t,
+                  pixman_region32_translate, pixman_region32_union, pixman_region32_union_rect};
+
+use {Area, Origin, Size};
+
+/// A pixman region, used for damage tracking.
+///
+/// A region is a set of pixels, stored as a list of non overlapping
+/// rectangles.
+pub struct PixmanRegion {
+    pub region: pixman_region32_t
+}
+
+/// Iterator over the rectangles of a `PixmanRegion`, made by
+/// `PixmanRegion::rectangles`.
+#[derive(Debug, Clone)]
+pub struct Rectangles<'region> {
+    rects: slice::Iter<'region, pixman_box32_t>
+}
+
+impl PixmanRegion {
+    /// Make a new pixman region.
+    pub fn new() -> Self {
+        unsafe {
+            // NOTE Rational for uninitialized memory:
+            // We are automatically filling it in with pixman_region32_init.
+            let mut region = mem::uninitialized();
+            pixman_region32_init(&mut region);
+            PixmanRegion { region }
+        }
+    }
+
+    /// Make a region covering the area.
+    ///
+    /// An area with no width or height makes an empty region.
+    pub fn from_area(area: Area) -> Self {
+        if area.size.width <= 0 || area.size.height <= 0 {
+            return PixmanRegion::new()
+        }
+        unsafe {
+            let mut region = mem::uninitialized();
+            pixman_region32_init_rect(&mut region,
+                                      area.origin.x,
+                                      area.origin.y,
+                                      area.size.width as c_uint,
+                                      area.size.height as c_uint);
+            PixmanRegion { region }
+        }
+    }
+
+    /// Adds the rectangle to the region.
+    pub fn rectangle(&mut self, x: c_int, y: c_int, width: c_uint, height: c_uint) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_union_rect(region_ptr, region_ptr, x, y, width, height);
+        }
+    }
+
+    /// Adds the area to the region.
+    pub fn add_area(&mut self, area: Area) {
+        if area.size.width <= 0 || area.size.height <= 0 {
+            return
+        }
+        self.rectangle(area.origin.x,
+                       area.origin.y,
+                       area.size.width as c_uint,
+                       area.size.height as c_uint)
+    }
+
+    /// Adds the pixels in `other` to this region.
+    pub fn union(&mut self, other: &PixmanRegion) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_union(region_ptr, region_ptr, other.as_ptr());
+        }
+    }
+
+    /// Removes the pixels that are not in `other` from this region.
+    pub fn intersect(&mut self, other: &PixmanRegion) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_intersect(region_ptr, region_ptr, other.as_ptr());
+        }
+    }
+
+    /// Removes the pixels that are not in the area from this region.
+    pub fn intersect_area(&mut self, area: Area) {
+        if area.size.width <= 0 || area.size.height <= 0 {
+            return self.clear()
+        }
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_intersect_rect(region_ptr,
+                                           region_ptr,
+                                           area.origin.x,
+                                           area.origin.y,
+                                           area.size.width as c_uint,
+                                           area.size.height as c_uint);
+        }
+    }
+
+    /// Removes the pixels in `other` from this region.
+    pub fn subtract(&mut self, other: &PixmanRegion) {
+        unsafe {
+            let region_ptr = &mut self.region as *mut _;
+            pixman_region32_subtract(region_ptr, region_ptr, other.as_ptr());
+        }
+    }
+
+    /// Removes the pixels in the area from this region.
+    pub fn subtract_area(&mut self, area: Area) {
+        self.subtract(&PixmanRegion::from_area(area))
+    }
+
+    /// Moves the region by the given offset.
+    pub fn translate(&mut self, x: c_int, y: c_int) {
+        unsafe { pixman_region32_translate(&mut self.region, x, y) }
+    }
+
+    /// Scales the region, e.g from layout coordinates to the buffer of an
+    /// output with that scale.
+    ///
+    /// Rectangles are rounded outwards, so that every pixel that was partly
+    /// covered stays in the region.
+    pub fn scale(&mut self, scale: f32) {
+        if scale == 1.0 {
+            return
+        }
+        let scaled = self.rectangles()
+                         .map(|area| scale_area(area, scale))
+                         .collect::<Vec<_>>();
+        unsafe {
+            let mut region = mem::uninitialized();
+            pixman_region32_init_rects(&mut region, scaled.as_ptr(), scaled.len() as c_int);
+            pixman_region32_fini(&mut self.region);
+            self.region = region;
+        }
+    }
+
+    /// Empties the region.
+    pub fn clear(&mut self) {
+        unsafe { pixman_region32_clear(&mut self.region) }
+    }
+
+    /// Determines if the region has no pixels.
+    pub fn is_empty(&self) -> bool {
+        unsafe { pixman_region32_not_empty(self.as_ptr()) == 0 }
+    }
+
+    /// Determines if the pixel at the given point is in the region.
+    pub fn contains_point(&self, x: c_int, y: c_int) -> bool {
+        unsafe { pixman_region32_contains_point(self.as_ptr(), x, y, ptr::null_mut()) != 0 }
+    }
+
+    /// Gets the smallest area that contains the whole region.
+    ///
+    /// An empty region has empty extents.
+    pub fn extents(&self) -> Area {
+        unsafe { box_to_area(&*pixman_region32_extents(self.as_ptr())) }
+    }
+
+    /// Iterates over the rectangles that make up the region, from the top
+    /// left to the bottom right.
+    pub fn rectangles(&self) -> Rectangles {
+        unsafe {
+            let mut len = 0;
+            let rects = pixman_region32_rectangles(self.as_ptr(), &mut len);
+            let rects: &[pixman_box32_t] = if rects.is_null() || len <= 0 {
+                &[]
+            } else {
+                slice::from_raw_parts(rects, len as usize)
+            };
+            Rectangles { rects: rects.iter() }
+        }
+    }
+
+    /// Gets the rectangles that make up the region.
+    pub fn areas(&self) -> Vec<Area> {
+        self.rectangles().collect()
+    }
+
+    /// pixman takes mutable pointers even for regions it only reads.
+    fn as_ptr(&self) -> *mut pixman_region32_t {
+        &self.region as *const _ as *mut _
+    }
+}
+
+impl Default for PixmanRegion {
+    fn default() -> Self {
+        PixmanRegion::new()
+    }
+}
+
+impl Clone for PixmanRegion {
+    fn clone(&self) -> Self {
+        let mut clone = PixmanRegion::new();
+        unsafe {
+            pixman_region32_copy(&mut clone.region, self.as_ptr());
+        }
+        clone
+    }
+}
+
+impl PartialEq for PixmanRegion {
+    fn eq(&self, other: &PixmanRegion) -> bool {
+        unsafe { pixman_region32_equal(self.as_ptr(), other.as_ptr()) != 0 }
+    }
+}
+
+impl Eq for PixmanRegion {}
+
+impl fmt::Debug for PixmanRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PixmanRegion")
+         .field("rectangles", &self.areas())
+         .finish()
+    }
+}
+
+impl From<Area> for PixmanRegion {
+    fn from(area: Area) -> Self {
+        PixmanRegion::from_area(area)
+    }
+}
+
+impl FromIterator<Area> for PixmanRegion {
+    fn from_iter<I: IntoIterator<Item = Area>>(iter: I) -> Self {
+        let mut region = PixmanRegion::new();
+        for area in iter {
+            region.add_area(area);
+        }
+        region
+    }
+}
+
+impl<'region> IntoIterator for &'region PixmanRegion {
+    type Item = Area;
+    type IntoIter = Rectangles<'region>;
+
+    fn into_iter(self) -> Rectangles<'region> {
+        self.rectangles()
+    }
+}
+
+impl Drop for PixmanRegion {
+    fn drop(&mut self) {
+        unsafe { pixman_region32_fini(&mut self.region) }
+    }
+}
+
+impl<'region> Iterator for Rectangles<'region> {
+    type Item = Area;
+
+    fn next(&mut self) -> Option<Area> {
+        self.rects.next().map(box_to_area)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rects.size_hint()
+    }
+}
+
+impl<'region> ExactSizeIterator for Rectangles<'region> {}
+
+fn box_to_area(rect: &pixman_box32_t) -> Area {
+    Area::new(Origin::new(rect.x1, rect.y1),
+              Size::new(rect.x2 - rect.x1, rect.y2 - rect.y1))
+}
+
+fn scale_area(area: Area, scale: f32) -> pixman_box32_t {
+    let x2 = area.origin.x + area.size.width;
+    let y2 = area.origin.y + area.size.height;
+    pixman_box32_t { x1: (area.origin.x as f32 * scale).floor() as c_int,
+                     y1: (area.origin.y as f32 * scale).floor() as c_int,
+                     x2: (x2 as f32 * scale).ceil() as c_int,
+                     y2: (y2 as f32 * scale).ceil() as c_int }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: c_int, y: c_int, width: c_int, height: c_int) -> Area {
+        Area::new(Origin::new(x, y), Size::new(width, height))
+    }
+
+    #[test]
+    fn union_merges_overlapping_areas() {
+        let mut region = PixmanRegion::from_area(area(0, 0, 10, 10));
+        region.union(&PixmanRegion::from_area(area(5, 5, 10, 10)));
+        assert_eq!(region.extents(), area(0, 0, 15, 15));
+        assert!(region.contains_point(14, 14));
+        assert!(!region.contains_point(14, 0));
+        let total: c_int = region.rectangles()
+                                 .map(|rect| rect.size.width * rect.size.height)
+                                 .sum();
+        assert_eq!(total, 100 + 100 - 25);
+    }
+
+    #[test]
+    fn intersect_keeps_common_pixels() {
+        let mut region = PixmanRegion::from_area(area(0, 0, 10, 10));
+        region.intersect(&PixmanRegion::from_area(area(5, 5, 10, 10)));
+        assert_eq!(region.areas(), vec![area(5, 5, 5, 5)]);
+        region.intersect_area(area(20, 20, 5, 5));
+        assert!(region.is_empty());
+    }
+
+    #[test]
+    fn subtract_removes_pixels() {
+        let mut region = PixmanRegion::from_area(area(0, 0, 10, 10));
+        region.subtract_area(area(0, 0, 10, 5));
+        assert_eq!(region.areas(), vec![area(0, 5, 10, 5)]);
+        let copy = region.clone();
+        region.subtract(&copy);
+        assert!(region.is_empty());
+        assert_eq!(region.extents().size, Size::new(0, 0));
+    }
+
+    #[test]
+    fn translate_moves_region() {
+        let mut region = PixmanRegion::from_area(area(0, 0, 10, 10));
+        region.translate(5, -5);
+        assert_eq!(region, PixmanRegion::from_area(area(5, -5, 10, 10)));
+    }
+
+    #[test]
+    fn scale_rounds_outwards() {
+        let mut region = PixmanRegion::from_area(area(1, 1, 1, 1));
+        region.scale(1.5);
+        // 1.5 floors to 1, 3.0 stays 3.
+        assert_eq!(region.areas(), vec![area(1, 1, 2, 2)]);
+
+        let mut region = PixmanRegion::from_area(area(1, 1, 2, 2));
+        region.scale(0.5);
+        // 0.5 floors to 0, 1.5 ceils to 2.
+        assert_eq!(region.areas(), vec![area(0, 0, 2, 2)]);
+
+        let mut region = PixmanRegion::from_area(area(3, 4, 5, 6));
+        region.scale(2.0);
+        assert_eq!(region.areas(), vec![area(6, 8, 10, 12)]);
+    }
+
+    #[test]
+    fn scale_empty_region() {
+        let mut region = PixmanRegion::new();
+        region.scale(2.0);
+        assert!(region.is_empty());
+    }
+
+    #[test]
+    fn contains_point_excludes_far_edges() {
+        let region = PixmanRegion::from_area(area(0, 0, 10, 10));
+        assert!(region.contains_point(0, 0));
+        assert!(region.contains_point(9, 9));
+        assert!(!region.contains_point(10, 9));
+        assert!(!region.contains_point(9, 10));
+        assert!(!region.contains_point(-1, 0));
+    }
+
+    #[test]
+    fn empty_region_has_empty_extents() {
+        let region = PixmanRegion::new();
+        assert!(region.is_empty());
+        assert_eq!(region.extents(), Area::default());
+        assert_eq!(region.rectangles().len(), 0);
+        assert!(PixmanRegion::from_area(area(5, 5, 0, 10)).is_empty());
+    }
+
+    #[test]
+    fn collect_areas() {
+        let region = vec![area(0, 0, 10, 10), area(10, 0, 10, 10), area(0, 0, 0, 0)]
+            .into_iter()
+            .collect::<PixmanRegion>();
+        assert_eq!(region.areas(), vec![area(0, 0, 20, 10)]);
+        assert_eq!(PixmanRegion::new(), Vec::<Area>::new().into_iter().collect());
+    }
+}
@@ -7,7 +7,7 @@ use std::{fmt, panic, ptr, cell::Cell, rc::{Rc, Weak}, time::Duration};
 
 use libc;
 use wayland_sys::server::{signal::wl_signal_add, WAYLAND_SERVER_HANDLE};
-use wlroots_sys::{wlr_axis_orientation, wlr_seat, wlr_seat_create, wlr_seat_destroy,
+use wlroots_sys::{wl_signal, wlr_axis_orientation, wlr_seat, wlr_seat_create, wlr_seat_destroy,
                   wlr_seat_get_keyboard, wlr_seat_keyboard_clear_focus,
                   wlr_seat_keyboard_end_grab, wlr_seat_keyboard_enter, wlr_seat_keyboard_has_grab,
                   wlr_seat_keyboard_notify_enter, wlr_seat_keyboard_notify_key,
@@ -31,9 +31,10 @@ pub use wlroots_sys::wayland_server::protocol::wl_seat::Capability;
 use xkbcommon::xkb::Keycode;
 
 use {wlr_keyboard_modifiers, InputDevice, KeyboardGrab, KeyboardHandle, PointerGrab, Surface,
-     TouchGrab, TouchId, TouchPoint, events::seat_events::SetCursorEvent};
+     TouchGrab, TouchId, TouchPoint, UserData, events::seat_events::SetCursorEvent};
 use compositor::{compositor_handle, Compositor, CompositorHandle};
 use errors::{HandleErr, HandleResult};
+use listener::ListenerToken;
 use utils::{c_to_rust_string, safe_as_cstring};
 use utils::ToMS;
 
@@ -44,7 +45,9 @@ struct SeatState {
     /// they cannot be upgraded.
     counter: Rc<Cell<bool>>,
     /// A raw pointer to the Seat on the heap.
-    seat: *mut Seat
+    seat: *mut Seat,
+    /// Data attached by the user.
+    user_data: UserData
 }
 
 #[derive(Debug, Clone)]
@@ -290,7 +293,8 @@ impl Seat {
             let counter = Rc::new(Cell::new(false));
             let handle = Rc::downgrade(&counter);
             let state = Box::new(SeatState { counter,
-                                             seat: Box::into_raw(res) });
+                                             seat: Box::into_raw(res),
+                                             user_data: UserData::default() });
             (*seat).data = Box::into_raw(state) as *mut libc::c_void;
             SeatHandle { seat: seat, handle }
         }
@@ -305,6 +309,75 @@ impl Seat {
         Box::from_raw((*data).seat)
     }
 
+    /// Gets the data attached by the user to this seat.
+    pub fn user_data(&self) -> &UserData {
+        unsafe { &(*((*self.data.0).data as *mut SeatState)).user_data }
+    }
+
+    /// Mutably gets the data attached by the user to this seat.
+    pub fn user_data_mut(&mut self) -> &mut UserData {
+        unsafe { &mut (*((*self.data.0).data as *mut SeatState)).user_data }
+    }
+
+    /// Calls `callback` when a client sets the cursor for this seat.
+    pub fn on_cursor_set<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SeatHandle, &SetCursorEvent) + 'static
+    {
+        unsafe {
+            let signal = &mut (*self.data.0).events.request_set_cursor;
+            self.listen(signal, move |compositor, seat, data| {
+                let event = SetCursorEvent::from_ptr(data as *mut _);
+                callback(compositor, seat, &event)
+            })
+        }
+    }
+
+    /// Calls `callback` when the seat is provided with a selection by a client.
+    pub fn on_selection<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SeatHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.data.0).events.selection,
+                        move |compositor, seat, _| callback(compositor, seat))
+        }
+    }
+
+    /// Calls `callback` when the seat is provided with a selection from the
+    /// primary buffer by a client.
+    pub fn on_primary_selection<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SeatHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.data.0).events.primary_selection,
+                        move |compositor, seat, _| callback(compositor, seat))
+        }
+    }
+
+    /// Calls `callback` when the seat is destroyed.
+    ///
+    /// The handle passed to `callback` might not be usable anymore, but it can
+    /// still be compared with other handles to the seat.
+    pub fn on_destroy<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SeatHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.data.0).events.destroy,
+                        move |compositor, seat, _| callback(compositor, seat))
+        }
+    }
+
+    /// Registers `callback` on one of the signals of this seat.
+    unsafe fn listen<F>(&self, signal: *mut wl_signal, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SeatHandle, *mut libc::c_void) + 'static
+    {
+        let handle = self.weak_reference();
+        ListenerToken::new(signal, &mut (*self.data.0).events.destroy, move |data| {
+            if let Some(compositor) = compositor_handle() {
+                callback(compositor, handle.clone(), data)
+            }
+        })
+    }
+
     /// Get a weak reference to this seat.
     pub fn weak_reference(&self) -> SeatHandle {
         unsafe {
@@ -883,10 +956,4 @@ impl Default for SeatHandle {
     }
 }
 
-impl PartialEq for SeatHandle {
-    fn eq(&self, other: &SeatHandle) -> bool {
-        self.seat == other.seat
-    }
-}
-
-impl Eq for SeatHandle {}
+impl_handle!(SeatHandle, Seat, seat);
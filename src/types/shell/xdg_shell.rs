@@ -12,7 +12,7 @@ use wlroots_sys::{wlr_xdg_popup, wlr_xdg_surface, wlr_xdg_surface_ping,
                   wlr_xdg_toplevel_set_size, wlr_xdg_toplevel_state,
                   wlr_xdg_surface_for_each_surface, wlr_surface, wlr_xdg_surface_from_wlr_surface};
 
-use {Area, SeatHandle, SurfaceHandle, Surface};
+use {Area, SeatHandle, SurfaceHandle, Surface, UserData};
 use errors::{HandleErr, HandleResult};
 use utils::c_to_rust_string;
 use manager::XdgShell;
@@ -23,7 +23,9 @@ pub(crate) struct XdgShellSurfaceState {
     /// Pointer to the backing storage.
     pub(crate) shell: *mut XdgShell,
     handle: Weak<Cell<bool>>,
-    shell_state: Option<XdgShellState>
+    shell_state: Option<XdgShellState>,
+    /// Data attached by the user.
+    user_data: UserData
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -78,7 +80,6 @@ impl XdgShellSurface {
         where T: Into<Option<XdgShellState>>
     {
         let state = state.into();
-        // TODO FIXME Free state in drop impl when Rc == 1
         (*shell_surface).data = ptr::null_mut();
         let liveliness = Rc::new(Cell::new(false));
         let shell_state =
@@ -87,7 +88,8 @@ impl XdgShellSurface {
                                             shell_state: match state {
                                                 None => None,
                                                 Some(ref state) => Some(state.clone())
-                                            } });
+                                            },
+                                            user_data: UserData::default() });
         (*shell_surface).data = Box::into_raw(shell_state) as *mut _;
         XdgShellSurface { liveliness,
                           state: state,
@@ -195,6 +197,16 @@ impl XdgShellSurface {
         }
     }
 
+    /// Gets the data attached by the user to this shell surface.
+    pub fn user_data(&self) -> &UserData {
+        unsafe { &(*((*self.shell_surface).data as *mut XdgShellSurfaceState)).user_data }
+    }
+
+    /// Mutably gets the data attached by the user to this shell surface.
+    pub fn user_data_mut(&mut self) -> &mut UserData {
+        unsafe { &mut (*((*self.shell_surface).data as *mut XdgShellSurfaceState)).user_data }
+    }
+
     /// Creates a weak reference to an `XdgShellSurface`.
     ///
     /// # Panics
@@ -210,6 +222,19 @@ impl XdgShellSurface {
     }
 }
 
+impl Drop for XdgShellSurface {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.liveliness) > 1 {
+            return
+        }
+        unsafe {
+            let data = (*self.shell_surface).data as *mut XdgShellSurfaceState;
+            (*self.shell_surface).data = ptr::null_mut();
+            Box::from_raw(data);
+        }
+    }
+}
+
 impl XdgShellSurfaceHandle {
     /// Constructs a new XdgShellSurfaceHandle that is always invalid. Calling `run` on this
     /// will always fail.
@@ -291,7 +316,7 @@ impl XdgShellSurfaceHandle {
     /// or if you run this function within the another run to the same `Output`.
     ///
     /// So don't nest `run` calls and everything will be ok :).
-    pub fn run<F, R>(&mut self, runner: F) -> HandleResult<R>
+    pub fn run<F, R>(&self, runner: F) -> HandleResult<R>
         where F: FnOnce(&mut XdgShellSurface) -> R
     {
         let mut xdg_surface = unsafe { self.upgrade()? };
@@ -324,13 +349,7 @@ impl Default for XdgShellSurfaceHandle {
     }
 }
 
-impl PartialEq for XdgShellSurfaceHandle {
-    fn eq(&self, other: &XdgShellSurfaceHandle) -> bool {
-        self.shell_surface == other.shell_surface
-    }
-}
-
-impl Eq for XdgShellSurfaceHandle {}
+impl_handle!(XdgShellSurfaceHandle, XdgShellSurface, shell_surface);
 
 impl XdgTopLevel {
     pub(crate) unsafe fn from_shell(shell_surface: *mut wlr_xdg_surface,
@@ -12,7 +12,7 @@ use wlroots_sys::{wlr_xdg_popup_v6, wlr_xdg_surface_v6, wlr_xdg_surface_v6_ping,
                   wlr_xdg_toplevel_v6_set_size, wlr_xdg_toplevel_v6_state,
                   wlr_xdg_surface_v6_for_each_surface, wlr_surface};
 
-use {Area, SeatHandle, SurfaceHandle};
+use {Area, SeatHandle, SurfaceHandle, UserData};
 use errors::{HandleErr, HandleResult};
 use utils::c_to_rust_string;
 use manager::XdgV6Shell;
@@ -22,7 +22,9 @@ use libc::c_void;
 pub(crate) struct XdgV6ShellSurfaceState {
     pub(crate) shell: *mut XdgV6Shell,
     handle: Weak<Cell<bool>>,
-    shell_state: Option<XdgV6ShellState>
+    shell_state: Option<XdgV6ShellState>,
+    /// Data attached by the user.
+    user_data: UserData
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -77,7 +79,6 @@ impl XdgV6ShellSurface {
         where T: Into<Option<XdgV6ShellState>>
     {
         let state = state.into();
-        // TODO FIXME Free state in drop impl when Rc == 1
         (*shell_surface).data = ptr::null_mut();
         let liveliness = Rc::new(Cell::new(false));
         let shell_state =
@@ -86,7 +87,8 @@ impl XdgV6ShellSurface {
                                               shell_state: match state {
                                                   None => None,
                                                   Some(ref state) => Some(state.clone())
-                                              } });
+                                              },
+                                              user_data: UserData::default() });
         (*shell_surface).data = Box::into_raw(shell_state) as *mut _;
         XdgV6ShellSurface { liveliness,
                             state: state,
@@ -194,6 +196,16 @@ impl XdgV6ShellSurface {
         }
     }
 
+    /// Gets the data attached by the user to this shell surface.
+    pub fn user_data(&self) -> &UserData {
+        unsafe { &(*((*self.shell_surface).data as *mut XdgV6ShellSurfaceState)).user_data }
+    }
+
+    /// Mutably gets the data attached by the user to this shell surface.
+    pub fn user_data_mut(&mut self) -> &mut UserData {
+        unsafe { &mut (*((*self.shell_surface).data as *mut XdgV6ShellSurfaceState)).user_data }
+    }
+
     /// Creates a weak reference to an `XdgV6ShellSurface`.
     ///
     /// # Panics
@@ -209,6 +221,19 @@ impl XdgV6ShellSurface {
     }
 }
 
+impl Drop for XdgV6ShellSurface {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.liveliness) > 1 {
+            return
+        }
+        unsafe {
+            let data = (*self.shell_surface).data as *mut XdgV6ShellSurfaceState;
+            (*self.shell_surface).data = ptr::null_mut();
+            Box::from_raw(data);
+        }
+    }
+}
+
 impl XdgV6ShellSurfaceHandle {
     /// Constructs a new XdgV6ShellSurfaceHandle that is always invalid. Calling `run` on this
     /// will always fail.
@@ -314,13 +339,7 @@ impl Default for XdgV6ShellSurfaceHandle {
     }
 }
 
-impl PartialEq for XdgV6ShellSurfaceHandle {
-    fn eq(&self, other: &XdgV6ShellSurfaceHandle) -> bool {
-        self.shell_surface == other.shell_surface
-    }
-}
-
-impl Eq for XdgV6ShellSurfaceHandle {}
+impl_handle!(XdgV6ShellSurfaceHandle, XdgV6ShellSurface, shell_surface);
 
 impl XdgV6TopLevel {
     pub(crate) unsafe fn from_shell(shell_surface: *mut wlr_xdg_surface_v6,
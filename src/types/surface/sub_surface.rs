@@ -203,6 +203,8 @@ impl Default for SubsurfaceHandle {
     }
 }
 
+impl_handle!(SubsurfaceHandle, Subsurface, subsurface);
+
 impl Drop for InternalSubsurface {
     fn drop(&mut self) {
         unsafe {
@@ -5,7 +5,7 @@ use std::{panic, ptr, cell::Cell, rc::{Rc, Weak}, time::Duration};
 
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 use wayland_sys::server::signal::wl_signal_add;
-use wlroots_sys::{timespec, wlr_subsurface, wlr_surface, wlr_surface_get_root_surface,
+use wlroots_sys::{timespec, wl_signal, wlr_subsurface, wlr_surface, wlr_surface_get_root_surface,
                   wlr_surface_has_buffer, wlr_surface_point_accepts_input, wlr_surface_send_enter,
                   wlr_surface_send_frame_done, wlr_surface_send_leave, wlr_surface_surface_at,
                   wlr_surface_is_xdg_surface};
@@ -13,9 +13,10 @@ use wlroots_sys::{timespec, wlr_subsurface, wlr_surface, wlr_surface_get_root_su
 use super::{Subsurface, SubsurfaceHandle, SubsurfaceHandler, SubsurfaceManager, SurfaceState,
             InternalSubsurface};
 use compositor::{compositor_handle, CompositorHandle};
-use Output;
+use {Output, UserData};
 use errors::{HandleErr, HandleResult};
-use render::Texture;
+use listener::ListenerToken;
+use render::{Matrix3, Texture};
 use utils::c_to_rust_string;
 
 pub trait SurfaceHandler {
@@ -89,7 +90,9 @@ pub(crate) struct InternalSurfaceState {
     handle: Weak<Cell<bool>>,
     /// Weak reference to the manager for the list of subsurfaces.
     /// This is here so that we can reconstruct the Surface from a SurfaceHandle.
-    subsurfaces_manager: Weak<Box<SubsurfaceManager>>
+    subsurfaces_manager: Weak<Box<SubsurfaceManager>>,
+    /// Data attached by the user.
+    user_data: UserData
 }
 
 /// A Wayland object that represents the data that we display on the screen.
@@ -146,11 +149,11 @@ impl Surface {
         let handle = Rc::downgrade(&liveliness);
         let subsurfaces_manager = Rc::new(Surface::create_manager(surface));
         let weak_manager = Rc::downgrade(&subsurfaces_manager);
-        (*surface).data = Box::into_raw(Box::new(InternalSurfaceState { surface: ptr::null_mut(),
-                                                                        handle,
-                                                                        subsurfaces_manager:
-                                                                        weak_manager }))
-            as _;
+        let state = Box::new(InternalSurfaceState { surface: ptr::null_mut(),
+                                                    handle,
+                                                    subsurfaces_manager: weak_manager,
+                                                    user_data: UserData::default() });
+        (*surface).data = Box::into_raw(state) as _;
         Surface { liveliness,
                   subsurfaces_manager,
                   surface }
@@ -268,6 +271,35 @@ impl Surface {
         }
     }
 
+    /// Calls `iterator` on this surface and on all of its subsurfaces,
+    /// recursively, from the bottom up.
+    ///
+    /// The position passed along with each surface is relative to this one.
+    ///
+    /// Subsurfaces that are already borrowed are skipped.
+    pub fn for_each_surface<F>(&mut self, mut iterator: F)
+        where F: FnMut(&mut Surface, i32, i32)
+    {
+        fn walk(surface: &mut Surface,
+                x: i32,
+                y: i32,
+                iterator: &mut FnMut(&mut Surface, i32, i32)) {
+            iterator(surface, x, y);
+            for subsurface in surface.subsurfaces() {
+                let child = match subsurface.run(|subsurface| subsurface.surface()) {
+                    Ok(child) => child,
+                    Err(_) => continue
+                };
+                child.run(|child| {
+                                let (sx, sy) = child.current_state().subsurface_position();
+                                walk(child, x + sx, y + sy, iterator)
+                            })
+                     .ok();
+            }
+        }
+        walk(self, 0, 0, &mut iterator)
+    }
+
     pub fn send_enter(&mut self, output: &mut Output) {
         unsafe { wlr_surface_send_enter(self.surface, output.as_ptr()) }
     }
@@ -290,14 +322,59 @@ impl Surface {
 
     /// Get the matrix used to convert the internal byte buffer to use in the
     /// surface.
-    pub fn buffer_to_surface_matrix(&self) -> [f32; 9] {
-        unsafe { (*self.surface).buffer_to_surface_matrix }
+    pub fn buffer_to_surface_matrix(&self) -> Matrix3 {
+        unsafe { Matrix3((*self.surface).buffer_to_surface_matrix) }
     }
 
     /// Get the matrix used to convert the surface back to the internal byte
     /// buffer.
-    pub fn surface_to_buffer_matrix(&self) -> [f32; 9] {
-        unsafe { (*self.surface).surface_to_buffer_matrix }
+    pub fn surface_to_buffer_matrix(&self) -> Matrix3 {
+        unsafe { Matrix3((*self.surface).surface_to_buffer_matrix) }
+    }
+
+    /// Gets the data attached by the user to this surface.
+    pub fn user_data(&self) -> &UserData {
+        unsafe { &(*((*self.surface).data as *mut InternalSurfaceState)).user_data }
+    }
+
+    /// Mutably gets the data attached by the user to this surface.
+    pub fn user_data_mut(&mut self) -> &mut UserData {
+        unsafe { &mut (*((*self.surface).data as *mut InternalSurfaceState)).user_data }
+    }
+
+    /// Calls `callback` every time the client commits the surface.
+    pub fn on_commit<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SurfaceHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.surface).events.commit,
+                        move |compositor, surface, _| callback(compositor, surface))
+        }
+    }
+
+    /// Calls `callback` when the surface is destroyed.
+    ///
+    /// The handle passed to `callback` might not be usable anymore, but it can
+    /// still be compared with other handles to the surface.
+    pub fn on_destroy<F>(&mut self, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SurfaceHandle) + 'static
+    {
+        unsafe {
+            self.listen(&mut (*self.surface).events.destroy,
+                        move |compositor, surface, _| callback(compositor, surface))
+        }
+    }
+
+    /// Registers `callback` on one of the signals of this surface.
+    unsafe fn listen<F>(&self, signal: *mut wl_signal, mut callback: F) -> ListenerToken
+        where F: FnMut(CompositorHandle, SurfaceHandle, *mut libc::c_void) + 'static
+    {
+        let handle = self.weak_reference();
+        ListenerToken::new(signal, &mut (*self.surface).events.destroy, move |data| {
+            if let Some(compositor) = compositor_handle() {
+                callback(compositor, handle.clone(), data)
+            }
+        })
     }
 
     /// Creates a weak reference to a `Surface`.
@@ -419,6 +496,8 @@ impl Default for SurfaceHandle {
     }
 }
 
+impl_handle!(SurfaceHandle, Surface, surface);
+
 impl Drop for Surface {
     fn drop(&mut self) {
         if Rc::strong_count(&self.liveliness) != 1 {
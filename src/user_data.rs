@@ -0,0 +1,54 @@
+//! Data attached by the user to the structures wrapped by this library.
+
+use std::any::Any;
+
+/// A slot holding data the user attached to a structure, e.g to an `Output`
+/// or a `Surface`.
+///
+/// The data can be of any type, and is dropped when the structure is
+/// destroyed.
+#[derive(Debug, Default)]
+pub struct UserData {
+    data: Option<Box<Any>>
+}
+
+impl UserData {
+    /// Attaches `data`, replacing any data attached before.
+    pub fn set<T: Any>(&mut self, data: T) {
+        self.data = Some(Box::new(data));
+    }
+
+    /// Gets the attached data.
+    ///
+    /// Returns `None` if no data is attached or if it is not a `T`.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.data.as_ref().and_then(|data| data.downcast_ref())
+    }
+
+    /// Mutably gets the attached data.
+    ///
+    /// Returns `None` if no data is attached or if it is not a `T`.
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.data.as_mut().and_then(|data| data.downcast_mut())
+    }
+
+    /// Removes the attached data, returning it.
+    ///
+    /// Returns `None` if no data is attached or if it is not a `T`, in which
+    /// case the data stays attached.
+    pub fn take<T: Any>(&mut self) -> Option<T> {
+        match self.data.take().map(|data| data.downcast::<T>()) {
+            Some(Ok(data)) => Some(*data),
+            Some(Err(data)) => {
+                self.data = Some(data);
+                None
+            }
+            None => None
+        }
+    }
+
+    /// Determines if no data is attached.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_none()
+    }
+}
@@ -102,10 +102,11 @@ pub(crate) unsafe fn handle_unwind<T>(res: ::std::thread::Result<T>) {
     match res {
         Ok(_) => {}
         Err(err) => {
-            if ::compositor::COMPOSITOR_PTR == 0 as *mut _ {
+            let compositor = ::compositor::running_compositor();
+            if compositor.is_null() {
                 ::std::process::abort();
             }
-            (&mut *::compositor::COMPOSITOR_PTR).save_panic_error(err);
+            (&mut *compositor).save_panic_error(err);
             ::compositor::terminate()
         }
     }
@@ -5,7 +5,7 @@ use libc::{self, size_t, int16_t, uint16_t};
 use wayland_sys::server::WAYLAND_SERVER_HANDLE;
 use wlroots_sys::{pid_t, wl_event_source, wlr_xwayland_surface, xcb_atom_t, xcb_window_t};
 
-use {SurfaceHandle, XWaylandSurfaceHints, XWaylandSurfaceSizeHints};
+use {SurfaceHandle, UserData, XWaylandSurfaceHints, XWaylandSurfaceSizeHints};
 use compositor::{compositor_handle, CompositorHandle};
 use errors::{HandleErr, HandleResult};
 use events::xwayland_events::{ConfigureEvent, MoveEvent, ResizeEvent};
@@ -247,7 +247,9 @@ wayland_listener!(XWaylandShell, (XWaylandSurface, Box<XWaylandSurfaceHandler>),
 
 pub(crate) struct XWaylandSurfaceState {
     pub(crate) shell: *mut XWaylandShell,
-    handle: Weak<Cell<bool>>
+    handle: Weak<Cell<bool>>,
+    /// Data attached by the user.
+    user_data: UserData
 }
 
 /// An Xwayland user interface component. It has an absolute position in
@@ -274,7 +276,9 @@ impl XWaylandSurface {
     pub(crate) unsafe fn new(shell_surface: *mut wlr_xwayland_surface) -> Self {
         (*shell_surface).data = ptr::null_mut();
         let liveliness = Rc::new(Cell::new(false));
-        let state = Box::new(XWaylandSurfaceState { shell: ptr::null_mut(), handle: Rc::downgrade(&liveliness) });
+        let state = Box::new(XWaylandSurfaceState { shell: ptr::null_mut(),
+                                                    handle: Rc::downgrade(&liveliness),
+                                                    user_data: UserData::default() });
         (*shell_surface).data = Box::into_raw(state) as *mut _;
         XWaylandSurface { liveliness,
                           shell_surface }
@@ -288,6 +292,16 @@ impl XWaylandSurface {
                              shell_surface: handle.as_ptr() })
     }
 
+    /// Gets the data attached by the user to this surface.
+    pub fn user_data(&self) -> &UserData {
+        unsafe { &(*((*self.shell_surface).data as *mut XWaylandSurfaceState)).user_data }
+    }
+
+    /// Mutably gets the data attached by the user to this surface.
+    pub fn user_data_mut(&mut self) -> &mut UserData {
+        unsafe { &mut (*((*self.shell_surface).data as *mut XWaylandSurfaceState)).user_data }
+    }
+
     /// Creates a weak reference to an `XWaylandSurface`.
     pub fn weak_reference(&self) -> XWaylandSurfaceHandle {
         XWaylandSurfaceHandle { handle: Rc::downgrade(&self.liveliness),
@@ -554,7 +568,9 @@ impl Drop for XWaylandSurface {
             return
         }
         unsafe {
-            Box::from_raw((*self.shell_surface).data as *mut XWaylandSurfaceState);
+            let data = (*self.shell_surface).data as *mut XWaylandSurfaceState;
+            (*self.shell_surface).data = ptr::null_mut();
+            Box::from_raw(data);
         }
     }
 }
@@ -565,13 +581,7 @@ impl Default for XWaylandSurfaceHandle {
     }
 }
 
-impl PartialEq for XWaylandSurfaceHandle {
-    fn eq(&self, other: &XWaylandSurfaceHandle) -> bool {
-        self.shell_surface == other.shell_surface
-    }
-}
-
-impl Eq for XWaylandSurfaceHandle {}
+impl_handle!(XWaylandSurfaceHandle, XWaylandSurface, shell_surface);
 
 impl Drop for XWaylandShell {
     fn drop(&mut self) {
@@ -28,6 +28,9 @@ fn main() {
         .whitelisted_function(r"^_?pixman_.*$")
         .whitelisted_function(r"^_?wlr_.*$")
         .whitelisted_function(r"^xkb_.*$")
+        // Not wrapped by wayland-sys.
+        .whitelisted_function(r"^wl_display_add_protocol_logger$")
+        .whitelisted_function(r"^wl_protocol_logger_destroy$")
         .ctypes_prefix("libc")
         .clang_arg("-Iwlroots/include")
         .clang_arg("-Iwlroots/include/wlr")
@@ -165,7 +168,14 @@ fn generate_protocols() {
 
     let output_dir = Path::new(&output_dir_str);
 
-    let protocols = &[("./wlroots/protocol/server-decoration.xml", "server_decoration")];
+    let protocols = &[("./wlroots/protocol/server-decoration.xml", "server_decoration"),
+                      ("./wlroots/protocol/screenshooter.xml", "screenshooter"),
+                      ("/usr/share/wayland-protocols/stable/presentation-time/\
+                        presentation-time.xml",
+                       "presentation_time"),
+                      // Not shipped by wlroots or wayland-protocols yet.
+                      ("./protocols/wlr-output-management-unstable-v1.xml",
+                       "wlr_output_management")];
 
     for protocol in protocols {
         wayland_scanner::generate_code(protocol.0,
@@ -32,6 +32,46 @@ mod generated {
                 include!(concat!(env!("OUT_DIR"), "/server_decoration_server_api.rs"));
             }
         }
+
+        pub mod screenshooter {
+            #![allow(unused_imports)]
+            pub mod server {
+                // NOTE
+                // Only the interfaces are needed, the protocol is implemented
+                // by hand on top of libwayland.
+                pub mod interfaces {
+                    pub(crate) use wayland_server::protocol_interfaces::{wl_buffer_interface,
+                                                                         wl_output_interface};
+                    include!(concat!(env!("OUT_DIR"), "/screenshooter_interfaces.rs"));
+                }
+            }
+        }
+
+        pub mod presentation_time {
+            #![allow(unused_imports)]
+            pub mod server {
+                // NOTE
+                // Only the interfaces are needed, the protocol is implemented
+                // by hand on top of libwayland.
+                pub mod interfaces {
+                    pub(crate) use wayland_server::protocol_interfaces::{wl_output_interface,
+                                                                         wl_surface_interface};
+                    include!(concat!(env!("OUT_DIR"), "/presentation_time_interfaces.rs"));
+                }
+            }
+        }
+
+        pub mod wlr_output_management {
+            #![allow(unused_imports)]
+            pub mod server {
+                // NOTE
+                // Only the interfaces are needed, the protocol is implemented
+                // by hand on top of libwayland.
+                pub mod interfaces {
+                    include!(concat!(env!("OUT_DIR"), "/wlr_output_management_interfaces.rs"));
+                }
+            }
+        }
     }
 }
 pub use self::generated::*;
@@ -17,6 +17,9 @@
 #include <wlr/render/interface.h>
 #include <wlr/render/wlr_texture.h>
 
+/// Interface includes
+#include <wlr/interfaces/wlr_keyboard.h>
+
 /// Type includes
 #include <wlr/types/wlr_box.h>
 #include <wlr/types/wlr_matrix.h>
@@ -42,6 +45,7 @@
 #include <wlr/types/wlr_xdg_shell_v6.h>
 #include <wlr/types/wlr_xdg_shell.h>
 #include <wlr/types/wlr_xcursor_manager.h>
+#include <wlr/types/wlr_xdg_output.h>
 
 /// Util includes
 #include <wlr/util/log.h>